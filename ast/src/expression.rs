@@ -1,6 +1,9 @@
 use std::fmt::{self, Display};
 
-use crate::{intern::SymbolId, prelude::Spanned};
+use crate::{
+    intern::{LiteralId, SymbolId},
+    prelude::{FunctionParam, Spanned, Statement},
+};
 #[derive(Debug)]
 pub enum Expression {
     Literal(Literal),
@@ -15,6 +18,12 @@ pub enum Expression {
         lhs: Box<Spanned<Expression>>,
         rhs: Box<Spanned<Expression>>,
     },
+    /// `and`/`or`, which only evaluate the rhs when the lhs doesn't decide the result
+    Logical {
+        op: Spanned<LogicalOp>,
+        lhs: Box<Spanned<Expression>>,
+        rhs: Box<Spanned<Expression>>,
+    },
     Grouping(Box<Spanned<Expression>>),
     Call {
         callee: Box<Spanned<Expression>>,
@@ -24,17 +33,24 @@ pub enum Expression {
         op: Spanned<UnaryOp>,
         rhs: Box<Spanned<Expression>>,
     },
+    /// |x: number| x + 1
+    /// An expression body is stored as the statement `return expr;`
+    Closure {
+        params: Vec<Spanned<FunctionParam>>,
+        body: Box<Spanned<Statement>>,
+    },
     Error,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub enum Literal {
-    String,
-    Number,
+    /// The text between the quotes, interned in the program's literals
+    String(LiteralId),
+    Number(f64),
     Bool(bool),
     Nil,
 }
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub enum BinaryOp {
     Plus,
     Minus,
@@ -48,7 +64,12 @@ pub enum BinaryOp {
     LessEqual,
     Assignment,
 }
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
+pub enum LogicalOp {
+    And,
+    Or,
+}
+#[derive(Debug, Clone, Copy)]
 pub enum UnaryOp {
     Bang,
     Plus,
@@ -71,6 +92,19 @@ impl Display for Spanned<BinaryOp> {
         write!(f, "{}", self.value())
     }
 }
+impl Display for Spanned<LogicalOp> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.value())
+    }
+}
+impl Display for LogicalOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LogicalOp::And => write!(f, "and"),
+            LogicalOp::Or => write!(f, "or"),
+        }
+    }
+}
 impl Display for UnaryOp {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -102,8 +136,8 @@ impl Display for Expression {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Expression::Literal(lit) => match lit {
-                Literal::String => write!(f, "string"),
-                Literal::Number => write!(f, "number"),
+                Literal::String(_) => write!(f, "string"),
+                Literal::Number(n) => write!(f, "{}", n),
                 Literal::Bool(b) => write!(f, "{}", b),
                 Literal::Nil => {
                     write!(f, "nil")
@@ -114,9 +148,38 @@ impl Display for Expression {
             }
             Expression::Identifier(ident) => write!(f, "{}", ident.value()),
             Expression::Binary { op, lhs, rhs } => write!(f, "{} {} {}", lhs, op, rhs),
+            Expression::Logical { op, lhs, rhs } => write!(f, "{} {} {}", lhs, op, rhs),
             Expression::Grouping(expr) => write!(f, "({})", expr),
-            Expression::Call { callee, args } => todo!(),
+            Expression::Call { callee, args } => {
+                write!(f, "{}(", callee)?;
+
+                for (i, arg) in args.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+
+                    write!(f, "{}", arg)?;
+                }
+
+                write!(f, ")")
+            }
             Expression::Unary { op, rhs } => write!(f, "{}{}", op, rhs),
+            Expression::Closure { params, body } => {
+                write!(f, "|")?;
+
+                for (i, param) in params.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+
+                    write!(f, "{}", param.name.value())?;
+                }
+
+                match body.value() {
+                    Statement::Return(Some(expr)) => write!(f, "| {}", expr),
+                    _ => write!(f, "| {}", body),
+                }
+            }
             Expression::Error => write!(f, "error"),
         }
     }
@@ -8,13 +8,34 @@ use std::{
     ptr::NonNull,
 };
 
+#[cfg(debug_assertions)]
+use std::sync::atomic::{AtomicU32, Ordering};
+
 pub type SymbolDB = Interner<SymbolId>;
+pub type LiteralDB = Interner<LiteralId>;
+
+/// Tells apart the interners an id could have come from. Only debug builds keep track of it so an
+/// id looked up in the wrong interner is caught, in release builds an id is just its index.
+#[cfg(debug_assertions)]
+pub type InternerTag = u32;
+#[cfg(not(debug_assertions))]
+pub type InternerTag = ();
+
+#[cfg(debug_assertions)]
+fn next_tag() -> InternerTag {
+    static NEXT_TAG: AtomicU32 = AtomicU32::new(0);
+
+    NEXT_TAG.fetch_add(1, Ordering::Relaxed)
+}
+
+#[cfg(not(debug_assertions))]
+fn next_tag() -> InternerTag {}
 
 #[derive(Debug, Clone, Copy, PartialEq, Hash, Eq)]
-pub struct SymbolId(u32);
+pub struct SymbolId(u32, InternerTag);
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub struct LiteralId(u32);
+pub struct LiteralId(u32, InternerTag);
 
 impl Display for SymbolId {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -23,28 +44,38 @@ impl Display for SymbolId {
 }
 
 pub trait InternId: Copy + Clone + Debug {
-    fn id(index: u32) -> Self;
+    fn id(index: u32, tag: InternerTag) -> Self;
     fn index(&self) -> u32;
+    /// The interner that handed out the id
+    fn tag(&self) -> InternerTag;
 }
 
 impl InternId for SymbolId {
-    fn id(index: u32) -> Self {
-        SymbolId(index)
+    fn id(index: u32, tag: InternerTag) -> Self {
+        SymbolId(index, tag)
     }
 
     fn index(&self) -> u32 {
         self.0
     }
+
+    fn tag(&self) -> InternerTag {
+        self.1
+    }
 }
 
 impl InternId for LiteralId {
-    fn id(index: u32) -> Self {
-        LiteralId(index)
+    fn id(index: u32, tag: InternerTag) -> Self {
+        LiteralId(index, tag)
     }
 
     fn index(&self) -> u32 {
         self.0
     }
+
+    fn tag(&self) -> InternerTag {
+        self.1
+    }
 }
 
 pub struct OwnedPtr<T: ?Sized> {
@@ -104,6 +135,7 @@ pub struct Interner<T: InternId> {
     strings: Vec<&'static str>,
     buf: String,
     full: Vec<String>,
+    tag: InternerTag,
 }
 impl<T: InternId> Debug for Interner<T> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -112,6 +144,7 @@ impl<T: InternId> Debug for Interner<T> {
             .field("strings", &self.strings)
             .field("buf", &self.buf)
             .field("full", &self.full)
+            .field("tag", &self.tag)
             .finish()
     }
 }
@@ -123,6 +156,7 @@ impl Default for SymbolDB {
             strings: Vec::with_capacity(8),
             buf: String::with_capacity(8),
             full: Vec::with_capacity(8),
+            tag: next_tag(),
         };
 
         db.intern("number");
@@ -141,6 +175,7 @@ impl<T: InternId> Interner<T> {
             strings: Vec::with_capacity(8),
             buf: String::with_capacity(8),
             full: Vec::with_capacity(8),
+            tag: next_tag(),
         }
     }
 
@@ -150,6 +185,7 @@ impl<T: InternId> Interner<T> {
             strings: Vec::with_capacity(N),
             buf: String::with_capacity(N),
             full: Vec::with_capacity(N),
+            tag: next_tag(),
         };
 
         for i in symbols {
@@ -168,7 +204,7 @@ impl<T: InternId> Interner<T> {
 
         let string: &'static str = unsafe { self.alloc(item) };
 
-        let id = T::id(self.map.borrow().len() as u32);
+        let id = T::id(self.map.borrow().len() as u32, self.tag);
 
         self.map.insert(string, id);
         self.strings.push(string);
@@ -192,8 +228,23 @@ impl<T: InternId> Interner<T> {
         &*(interned as *const str)
     }
 
+    /// The string behind `key`, or `None` if it wasn't handed out by this interner
+    pub fn try_lookup(&self, key: &T) -> Option<&'static str> {
+        if key.tag() != self.tag {
+            return None;
+        }
+
+        self.strings.get(key.index() as usize).copied()
+    }
+
     pub fn lookup(&self, key: &T) -> &'static str {
-        self.strings[key.index() as usize]
+        self.try_lookup(key).unwrap_or_else(|| {
+            panic!(
+                "Id {} was not interned by this interner, it only has {} strings",
+                key.index(),
+                self.strings.len()
+            )
+        })
     }
 }
 
@@ -201,15 +252,46 @@ pub const DEFAULT_TYPES: [&'static str; 4] = ["number", "string", "boolean", "fl
 
 #[cfg(test)]
 mod tests {
-    use super::{InternId, Interner, SymbolId};
+    use super::{InternId, Interner, SymbolDB, SymbolId};
 
     #[test]
     fn it_works() {
         let mut interner = Interner::new();
 
-        assert_eq!(interner.intern("hello"), SymbolId::id(0));
-        assert_eq!(interner.intern("world"), SymbolId::id(1));
-        assert_eq!(interner.intern("hello"), SymbolId::id(0));
-        assert_eq!(interner.lookup(&SymbolId::id(0)), "hello");
+        assert_eq!(interner.intern("hello"), SymbolId::id(0, interner.tag));
+        assert_eq!(interner.intern("world"), SymbolId::id(1, interner.tag));
+        assert_eq!(interner.intern("hello"), SymbolId::id(0, interner.tag));
+        assert_eq!(interner.lookup(&SymbolId::id(0, interner.tag)), "hello");
+    }
+
+    #[test]
+    fn it_returns_none_for_an_id_it_did_not_hand_out() {
+        let mut interner = Interner::new();
+
+        interner.intern("hello");
+
+        assert_eq!(
+            interner.try_lookup(&SymbolId::id(0, interner.tag)),
+            Some("hello")
+        );
+        assert_eq!(interner.try_lookup(&SymbolId::id(1, interner.tag)), None);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    fn it_catches_an_id_from_another_interner() {
+        let mut first = SymbolDB::new();
+        let mut second = SymbolDB::new();
+
+        let hello = first.intern("hello");
+        let world = second.intern("world");
+
+        assert_eq!(hello.index(), world.index());
+        assert_ne!(hello, world);
+        assert_eq!(second.try_lookup(&hello), None);
+
+        let lookup = std::panic::catch_unwind(|| second.lookup(&hello));
+
+        assert!(lookup.is_err());
     }
 }
@@ -1,7 +1,11 @@
 use std::fmt::Display;
 
 use crate::{
-    expression::Expression, intern::SymbolId, span::Spanned, statements::Statement, types::Type,
+    expression::Expression,
+    intern::{LiteralDB, SymbolId},
+    span::Spanned,
+    statements::Statement,
+    types::Type,
 };
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
@@ -15,6 +19,9 @@ pub struct Program {
     pub functions: Vec<Spanned<Function>>,
     pub consts: Vec<Spanned<Const>>,
     pub type_alias: Vec<Spanned<TypeAlias>>,
+    pub traits: Vec<Spanned<Trait>>,
+    /// The text of the string literals in the program
+    pub literals: LiteralDB,
 }
 #[derive(Debug)]
 pub struct Function {
@@ -35,8 +42,17 @@ pub struct FunctionParam {
 }
 
 #[derive(Debug)]
-
-pub struct Trait {}
+pub struct Trait {
+    pub name: Spanned<SymbolId>,
+    pub methods: Vec<Spanned<TraitMethod>>,
+}
+/// fn show() -> string;
+#[derive(Debug)]
+pub struct TraitMethod {
+    pub name: Spanned<SymbolId>,
+    pub params: Vec<Spanned<FunctionParam>>,
+    pub returns: Option<Spanned<Type>>,
+}
 #[derive(Debug)]
 pub struct Const {
     pub name: Spanned<SymbolId>,
@@ -57,6 +73,8 @@ impl Program {
             functions: Vec::new(),
             consts: Vec::new(),
             type_alias: Vec::new(),
+            traits: Vec::new(),
+            literals: LiteralDB::new(),
         }
     }
 
@@ -71,6 +89,10 @@ impl Program {
     pub fn add_type_alias(&mut self, alias: Spanned<TypeAlias>) {
         self.type_alias.push(alias)
     }
+
+    pub fn add_trait(&mut self, trait_: Spanned<Trait>) {
+        self.traits.push(trait_)
+    }
 }
 
 impl Display for ParamKind {
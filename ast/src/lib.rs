@@ -1,15 +1,18 @@
 mod expression;
 mod intern;
 mod items;
+mod printer;
 mod span;
 mod statements;
 mod token;
 mod types;
+pub mod visitor;
 
 pub mod prelude {
     pub use crate::expression::*;
     pub use crate::intern::*;
     pub use crate::items::*;
+    pub use crate::printer::*;
     pub use crate::span::*;
     pub use crate::statements::*;
     pub use crate::token::*;
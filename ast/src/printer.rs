@@ -0,0 +1,368 @@
+use std::fmt::Write;
+
+use crate::{
+    prelude::{
+        Const, Expression, Function, FunctionParam, ItemKind, Literal, LiteralDB, Program, Spanned,
+        Statement, SymbolDB, SymbolId, Trait, Type, TypeAlias,
+    },
+    visitor::Visitor,
+};
+
+/// Renders a program back into source code.
+/// Numbers are read back out of the source so they print as they were written
+pub struct PrettyPrinter<'a> {
+    src: &'a str,
+    symbols: &'a SymbolDB,
+    /// The strings of the program being printed, set by `print`
+    literals: Option<&'a LiteralDB>,
+    out: String,
+    indent: usize,
+}
+
+impl<'a> PrettyPrinter<'a> {
+    pub fn new(src: &'a str, symbols: &'a SymbolDB) -> Self {
+        Self {
+            src,
+            symbols,
+            literals: None,
+            out: String::new(),
+            indent: 0,
+        }
+    }
+
+    pub fn print(mut self, program: &'a Program) -> String {
+        self.literals = Some(&program.literals);
+
+        for type_alias in &program.type_alias {
+            self.visit_type_alias(type_alias);
+            self.out.push('\n');
+        }
+
+        for const_ in &program.consts {
+            self.visit_const(const_);
+            self.out.push('\n');
+        }
+
+        for trait_ in &program.traits {
+            self.visit_trait(trait_);
+            self.out.push('\n');
+        }
+
+        for (i, function) in program.functions.iter().enumerate() {
+            if i > 0 {
+                self.out.push('\n');
+            }
+
+            self.visit_function(function);
+        }
+
+        self.out
+    }
+
+    fn write_indent(&mut self) {
+        for _ in 0..self.indent {
+            self.out.push_str("    ");
+        }
+    }
+
+    /// Writes a body, starting on the current line, with its statements indented
+    fn write_body(&mut self, body: &Spanned<Statement>) {
+        self.out.push_str("{\n");
+        self.indent += 1;
+
+        match body.value() {
+            Statement::Block(stmts) => {
+                for stmt in stmts {
+                    self.visit_stmt(stmt);
+                }
+            }
+            _ => self.visit_stmt(body),
+        }
+
+        self.indent -= 1;
+        self.write_indent();
+        self.out.push('}');
+    }
+
+    fn write_if(&mut self, stmt: &Spanned<Statement>) {
+        if let Statement::If { cond, then, else_ } = stmt.value() {
+            self.out.push_str("if ");
+            self.visit_expr(cond);
+            self.out.push(' ');
+            self.write_body(then);
+
+            if let Some(else_) = else_ {
+                self.out.push_str(" else ");
+
+                match else_.value() {
+                    Statement::If { .. } => self.write_if(else_),
+                    _ => self.write_body(else_),
+                }
+            }
+        }
+    }
+}
+
+impl<'a, 'ast> Visitor<'ast> for PrettyPrinter<'a> {
+    type Output = ();
+
+    fn visit_stmt(&mut self, stmt: &'ast Spanned<Statement>) {
+        self.write_indent();
+
+        match stmt.value() {
+            Statement::Expression(expr) => {
+                self.visit_expr(expr);
+                self.out.push(';');
+            }
+            Statement::While { cond, body } => {
+                self.out.push_str("while ");
+                self.visit_expr(cond);
+                self.out.push(' ');
+                self.write_body(body);
+            }
+            Statement::If { .. } => self.write_if(stmt),
+            Statement::Block(_) => self.write_body(stmt),
+            Statement::Return(expr) => {
+                self.out.push_str("return");
+
+                if let Some(expr) = expr {
+                    self.out.push(' ');
+                    self.visit_expr(expr);
+                }
+
+                self.out.push(';');
+            }
+            Statement::Break => self.out.push_str("break;"),
+            Statement::Continue => self.out.push_str("continue;"),
+            Statement::Let {
+                identifier,
+                ty,
+                init,
+            } => {
+                self.out.push_str("let ");
+                self.visit_name(identifier, ItemKind::Value);
+
+                if let Some(ty) = ty {
+                    self.out.push_str(": ");
+                    self.visit_type(ty);
+                }
+
+                if let Some(init) = init {
+                    self.out.push_str(" := ");
+                    self.visit_expr(init);
+                }
+
+                self.out.push(';');
+            }
+        }
+
+        self.out.push('\n');
+    }
+
+    fn visit_expr(&mut self, expression: &'ast Spanned<Expression>) {
+        match expression.value() {
+            Expression::Literal(literal) => match literal {
+                Literal::String(id) => match self.literals {
+                    Some(literals) => {
+                        let _ = write!(self.out, "\"{}\"", literals.lookup(id));
+                    }
+                    None => self
+                        .out
+                        .push_str(expression.view(self.src).unwrap_or_default()),
+                },
+                Literal::Number(_) => {
+                    let text = expression.view(self.src).unwrap_or_default();
+                    self.out.push_str(text)
+                }
+                Literal::Bool(b) => {
+                    let _ = write!(self.out, "{}", b);
+                }
+                Literal::Nil => self.out.push_str("nil"),
+            },
+            Expression::Ternary { cond, lhs, rhs } => {
+                self.visit_expr(cond);
+                self.out.push_str(" ? ");
+                self.visit_expr(lhs);
+                self.out.push_str(" : ");
+                self.visit_expr(rhs);
+            }
+            Expression::Identifier(name) => self.visit_name(name, ItemKind::Value),
+            Expression::Binary { op, lhs, rhs } => {
+                self.visit_expr(lhs);
+                let _ = write!(self.out, " {} ", op);
+                self.visit_expr(rhs);
+            }
+            Expression::Logical { op, lhs, rhs } => {
+                self.visit_expr(lhs);
+                let _ = write!(self.out, " {} ", op);
+                self.visit_expr(rhs);
+            }
+            Expression::Grouping(expr) => {
+                self.out.push('(');
+                self.visit_expr(expr);
+                self.out.push(')');
+            }
+            Expression::Call { callee, args } => {
+                self.visit_expr(callee);
+                self.out.push('(');
+
+                for (i, arg) in args.iter().enumerate() {
+                    if i > 0 {
+                        self.out.push_str(", ");
+                    }
+
+                    self.visit_expr(arg);
+                }
+
+                self.out.push(')');
+            }
+            Expression::Unary { op, rhs } => {
+                let _ = write!(self.out, "{}", op);
+                self.visit_expr(rhs);
+            }
+            Expression::Closure { params, body } => {
+                self.out.push('|');
+
+                for (i, param) in params.iter().enumerate() {
+                    if i > 0 {
+                        self.out.push_str(", ");
+                    }
+
+                    self.visit_function_param(param);
+                }
+
+                self.out.push_str("| ");
+
+                match body.value() {
+                    Statement::Return(Some(expr)) => self.visit_expr(expr),
+                    _ => self.write_body(body),
+                }
+            }
+            Expression::Error => self.out.push_str("<error>"),
+        }
+    }
+
+    fn visit_function(&mut self, function: &'ast Spanned<Function>) {
+        self.out.push_str("fn ");
+        self.visit_name(&function.name, ItemKind::Value);
+        self.out.push('(');
+
+        for (i, param) in function.params.iter().enumerate() {
+            if i > 0 {
+                self.out.push_str(", ");
+            }
+
+            self.visit_function_param(param);
+        }
+
+        self.out.push(')');
+
+        if let Some(returns) = &function.returns {
+            self.out.push_str(" -> ");
+            self.visit_type(returns);
+        }
+
+        self.out.push(' ');
+        self.write_body(&function.body);
+        self.out.push('\n');
+    }
+
+    fn visit_const(&mut self, const_: &'ast Spanned<Const>) {
+        self.out.push_str("const ");
+        self.visit_name(&const_.name, ItemKind::Value);
+
+        if let Some(ty) = &const_.ty {
+            self.out.push_str(": ");
+            self.visit_type(ty);
+        }
+
+        self.out.push_str(" := ");
+        self.visit_expr(&const_.initializer);
+        self.out.push_str(";\n");
+    }
+
+    fn visit_trait(&mut self, trait_: &'ast Spanned<Trait>) {
+        self.out.push_str("trait ");
+        self.visit_name(&trait_.name, ItemKind::Type);
+        self.out.push_str(" {\n");
+
+        for method in &trait_.methods {
+            self.out.push_str("    fn ");
+            self.visit_name(&method.name, ItemKind::Value);
+            self.out.push('(');
+
+            for (i, param) in method.params.iter().enumerate() {
+                if i > 0 {
+                    self.out.push_str(", ");
+                }
+
+                self.visit_function_param(param);
+            }
+
+            self.out.push(')');
+
+            if let Some(returns) = &method.returns {
+                self.out.push_str(" -> ");
+                self.visit_type(returns);
+            }
+
+            self.out.push_str(";\n");
+        }
+
+        self.out.push_str("}\n");
+    }
+
+    fn visit_type(&mut self, type_: &'ast Spanned<Type>) {
+        match type_.value() {
+            Type::Identifier(name) => self.visit_name(name, ItemKind::Type),
+            Type::Array { ty, length } => {
+                self.out.push('[');
+                self.visit_type(ty);
+
+                if let Some(length) = length {
+                    let _ = write!(self.out, "; {}", length);
+                }
+
+                self.out.push(']');
+            }
+            Type::Function { params, returns } => {
+                self.out.push_str("fn(");
+
+                for (i, param) in params.iter().enumerate() {
+                    if i > 0 {
+                        self.out.push_str(", ");
+                    }
+
+                    self.visit_type(param);
+                }
+
+                self.out.push(')');
+
+                if let Some(returns) = returns {
+                    self.out.push_str(" -> ");
+                    self.visit_type(returns);
+                }
+            }
+            Type::Void => self.out.push_str("()"),
+            Type::Error => self.out.push_str("<error>"),
+        }
+    }
+
+    fn visit_type_alias(&mut self, type_alias: &'ast Spanned<TypeAlias>) {
+        self.out.push_str("type ");
+        self.visit_name(&type_alias.name, ItemKind::Type);
+        self.out.push_str(" = ");
+        self.visit_type(&type_alias.ty);
+        self.out.push_str(";\n");
+    }
+
+    fn visit_name(&mut self, name: &'ast Spanned<SymbolId>, _: ItemKind) {
+        self.out.push_str(self.symbols.lookup(name.value()));
+    }
+
+    fn visit_function_param(&mut self, param: &'ast Spanned<FunctionParam>) {
+        self.visit_name(&param.name, ItemKind::Value);
+        self.out.push_str(": ");
+        self.visit_type(&param.ty);
+    }
+}
@@ -38,8 +38,11 @@ impl Display for Statement {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Statement::Expression(expr) => write!(f, "{};", expr),
-            Statement::While { cond, body } => todo!(),
-            Statement::Return(_) => todo!(),
+            Statement::While { cond, body } => write!(f, "while {} {}", cond, body),
+            Statement::Return(expr) => match expr {
+                Some(expr) => write!(f, "return {};", expr),
+                None => write!(f, "return;"),
+            },
             Statement::Block(block) => {
                 writeln!(f, "{{")?;
 
@@ -49,7 +52,14 @@ impl Display for Statement {
 
                 writeln!(f, "}}")
             }
-            Statement::If { cond, then, else_ } => todo!(),
+            Statement::If { cond, then, else_ } => {
+                write!(f, "if {} {}", cond, then)?;
+
+                match else_ {
+                    Some(else_) => write!(f, " else {}", else_),
+                    None => Ok(()),
+                }
+            }
             Statement::Break => write!(f, "break"),
             Statement::Continue => write!(f, "continue"),
             Statement::Let {
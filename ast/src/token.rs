@@ -1,3 +1,5 @@
+use crate::prelude::Spanned;
+
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Hash)]
 pub enum Token {
     LeftBracket,
@@ -26,7 +28,9 @@ pub enum Token {
     String,
     Number,
     And,
+    Break,
     Class,
+    Continue,
     Else,
     False,
     For,
@@ -53,3 +57,77 @@ pub enum Token {
     FunctionReturn,
     Bar,
 }
+
+impl Token {
+    /// The text every token of this kind is written as, or `None` when it depends on the source
+    pub fn canonical_str(&self) -> Option<&'static str> {
+        let text = match self {
+            Token::LeftBracket => "[",
+            Token::RightBracket => "]",
+            Token::LeftParen => "(",
+            Token::RightParen => ")",
+            Token::Assignment => ":=",
+            Token::LeftBrace => "{",
+            Token::RightBrace => "}",
+            Token::Comma => ",",
+            Token::Dot => ".",
+            Token::Minus => "-",
+            Token::Plus => "+",
+            Token::SemiColon => ";",
+            Token::Slash => "/",
+            Token::Star => "*",
+            Token::Bang => "!",
+            Token::BangEqual => "!=",
+            Token::Equal => "=",
+            Token::EqualEqual => "==",
+            Token::Greater => ">",
+            Token::GreaterEqual => ">=",
+            Token::Less => "<",
+            Token::LessEqual => "<=",
+            Token::And => "and",
+            Token::Break => "break",
+            Token::Class => "class",
+            Token::Continue => "continue",
+            Token::Else => "else",
+            Token::False => "false",
+            Token::For => "for",
+            Token::Fun => "fn",
+            Token::If => "if",
+            Token::Nil => "nil",
+            Token::Or => "or",
+            Token::Print => "print",
+            Token::Return => "return",
+            Token::Super => "super",
+            Token::This => "this",
+            Token::True => "true",
+            Token::Var => "let",
+            Token::While => "while",
+            Token::QuestionMark => "?",
+            Token::Colon => ":",
+            Token::Const => "const",
+            Token::Trait => "trait",
+            Token::Type => "type",
+            Token::Dollar => "$",
+            Token::FunctionReturn => "->",
+            Token::Bar => "|",
+            Token::Eof => "",
+            Token::Identifier
+            | Token::String
+            | Token::Number
+            | Token::Error
+            | Token::Interpolation => return None,
+        };
+
+        Some(text)
+    }
+}
+
+impl Spanned<Token> {
+    /// The source text of the token, `src` is only read for tokens without a canonical spelling
+    pub fn text<'a>(&self, src: &'a str) -> &'a str {
+        match self.value().canonical_str() {
+            Some(text) => text,
+            None => self.view(src).unwrap_or_default(),
+        }
+    }
+}
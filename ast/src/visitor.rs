@@ -1,4 +1,4 @@
-use ast::prelude::{
+use crate::prelude::{
     Const, Expression, Function, FunctionParam, ItemKind, Spanned, Statement, SymbolId, Trait,
     Type, TypeAlias,
 };
@@ -1,4 +1,5 @@
 use crate::token::{Token, TokenType};
+use std::collections::HashMap;
 use std::fmt::Debug;
 use vm::{FunctionObject, ObjectPtr};
 #[derive(Debug, Clone, Copy)]
@@ -10,6 +11,8 @@ pub struct Local<'a> {
 #[derive(Debug, PartialEq, Eq)]
 pub enum FunctionType {
     Function,
+    Initializer,
+    Method,
     Script,
 }
 
@@ -27,6 +30,7 @@ impl<'a> Default for Local<'a> {
                 lexme: "\0",
                 length: 0,
                 line: 0,
+                start: 0,
             },
             depth: Default::default(),
             is_captured: false,
@@ -36,24 +40,39 @@ impl<'a> Default for Local<'a> {
 
 pub struct Compiler<'a> {
     pub function: ObjectPtr<FunctionObject<'a>>,
+    /// The name the function was declared with, empty for the top level script
+    pub name: &'a str,
     pub compiler_type: FunctionType,
     pub locals: [Local<'a>; 257],
     pub upvalues: [Option<UpValue>; 257],
     pub local_count: usize,
     pub scope_depth: isize,
     pub enclosing: Option<usize>,
+    /// The constant slot of each string already in the function's chunk, repeated literals and names
+    /// share it
+    pub strings: HashMap<&'a str, u8>,
 }
 
 impl<'a> Compiler<'a> {
     pub fn new(compiler_type: FunctionType, function: ObjectPtr<FunctionObject<'a>>) -> Self {
+        let mut locals = [Local::default(); 257];
+
+        // Methods keep their receiver in slot 0
+        if compiler_type != FunctionType::Function && compiler_type != FunctionType::Script {
+            locals[0].name.lexme = "this";
+            locals[0].name.length = 4;
+        }
+
         Self {
-            locals: [Local::default(); 257],
+            locals,
             enclosing: None,
             local_count: 1,
             scope_depth: 0,
             function,
+            name: "",
             compiler_type,
             upvalues: [None; 257],
+            strings: HashMap::new(),
         }
     }
 }
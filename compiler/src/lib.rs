@@ -3,12 +3,25 @@ mod parser;
 mod scanner;
 mod token;
 
-use scanner::Scanner;
-use vm::{Allocator, FunctionObject, ObjectPtr, Table};
+use errors::Reporter;
+pub use scanner::Scanner;
+pub use token::{Token, TokenType};
+use vm::{chunk::SharedConstants, Allocator, FunctionObject, ObjectPtr, ObjectType, Table, Value};
 
-use crate::token::TokenType;
+/// Compiles `input`, writing any syntax errors to stderr
+pub fn compile(input: &str) -> Option<ParseResult<'_>> {
+    match try_compile(input) {
+        Ok(result) => Some(result),
+        Err(errors) => {
+            let _ = errors.emit_to(input, &mut std::io::stderr());
+            None
+        }
+    }
+}
 
-pub fn compile(input: &str) -> Option<ParseResult> {
+/// Compiles `input` or returns every syntax error in it. After an error the parser skips to the
+/// next statement and carries on, so one run reports all of them
+pub fn try_compile(input: &str) -> Result<ParseResult<'_>, Reporter> {
     let scanner = Scanner::new(input);
     let mut parser = parser::Parser::new(scanner);
 
@@ -19,9 +32,9 @@ pub fn compile(input: &str) -> Option<ParseResult> {
     }
 
     if parser.had_error() {
-        None
+        Err(parser.reporter())
     } else {
-        Some(parser.end())
+        Ok(parser.end())
     }
 }
 
@@ -30,3 +43,190 @@ pub struct ParseResult<'a> {
     pub allocator: Allocator,
     pub function: ObjectPtr<FunctionObject<'a>>,
 }
+
+impl<'a> ParseResult<'a> {
+    /// Copies the compiled program into a heap of its own, so it can be run by another vm while this
+    /// one is run or dropped by the first. The copy gets its own shared constants too
+    pub fn duplicate(&self) -> ParseResult<'a> {
+        let mut allocator = Allocator::new();
+        let mut table = Table::new();
+
+        let shared = self
+            .function
+            .chunk
+            .shared
+            .as_deref()
+            .map(SharedConstants::from);
+
+        let function = copy_function(self.function.clone(), &shared, &mut allocator, &mut table);
+
+        ParseResult {
+            table,
+            allocator,
+            function,
+        }
+    }
+}
+
+/// Copies `function` and every string and function in its constants, pointing each copied chunk at
+/// `shared`. Compiled code holds no other objects
+fn copy_function<'a>(
+    function: ObjectPtr<FunctionObject<'a>>,
+    shared: &Option<SharedConstants>,
+    allocator: &mut Allocator,
+    table: &mut Table,
+) -> ObjectPtr<FunctionObject<'a>> {
+    let name = function
+        .name
+        .map(|name| allocator.alloc_string(name.value().trim_end_matches('\0'), table));
+
+    let mut copy = FunctionObject::new(name);
+
+    copy.arity = function.arity;
+    copy.upvalue_count = function.upvalue_count;
+    copy.chunk = function.chunk.copy_with(shared.clone(), |constant| {
+        if !constant.is_obj() {
+            return constant;
+        }
+
+        match constant.obj_type() {
+            ObjectType::String => {
+                let string = constant.as_string();
+                let string = allocator.alloc_string(string.value().trim_end_matches('\0'), table);
+
+                Value::object(string.into())
+            }
+            ObjectType::Function => Value::object(
+                copy_function(constant.as_function(), shared, allocator, table).into(),
+            ),
+            ty => unreachable!("compiled code doesn't hold {:?} constants", ty),
+        }
+    });
+
+    allocator.alloc(copy)
+}
+
+#[cfg(test)]
+mod test {
+    use std::rc::Rc;
+
+    use vm::{ObjectType, Value};
+
+    use crate::try_compile;
+
+    #[test]
+    fn it_reports_every_syntax_error_in_one_run() {
+        let errors = match try_compile("var = 1;\nprint ;\nvar a = 1;\nvar b = (2;\n") {
+            Ok(_) => panic!("expected syntax errors"),
+            Err(errors) => errors,
+        };
+
+        let diagnostics = errors.diagnostics();
+
+        let reported = diagnostics
+            .iter()
+            .map(|diagnostic| (diagnostic.span().start.line, diagnostic.msg.as_str()))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            reported,
+            [
+                (1, "Expect variable name."),
+                (2, "Expect expression."),
+                (4, "Expect ')' after expression."),
+            ]
+        );
+    }
+
+    #[test]
+    fn it_rejects_assigning_to_an_expression_that_is_not_a_target() {
+        let errors = match try_compile("var m = {};\nm[\"a\"] + 1 = 2;\n") {
+            Ok(_) => panic!("expected an invalid assignment"),
+            Err(errors) => errors,
+        };
+
+        let diagnostics = errors.diagnostics();
+
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].msg, "Invalid assignment target.");
+        assert_eq!(diagnostics[0].span().start.line, 2);
+    }
+
+    #[test]
+    fn it_stores_a_number_used_by_two_functions_once() {
+        let result = try_compile("fun a() { return 42; } fun b() { return 42 + 1.5; } print a();")
+            .unwrap_or_else(|_| panic!("program should compile"));
+
+        let script = &result.function.chunk;
+        let shared = script.shared.as_ref().unwrap();
+
+        assert_eq!(**shared, [Value::int(42), Value::number(1.5)]);
+
+        let functions = script
+            .constants
+            .iter()
+            .filter(|constant| constant.is_obj() && constant.obj_type() == ObjectType::Function)
+            .map(|constant| constant.as_function())
+            .collect::<Vec<_>>();
+
+        assert_eq!(functions.len(), 2);
+
+        for function in functions {
+            assert!(Rc::ptr_eq(function.chunk.shared.as_ref().unwrap(), shared));
+            assert!(function.chunk.constants.is_empty());
+        }
+    }
+
+    #[test]
+    fn a_duplicate_gets_a_shared_pool_of_its_own() {
+        let result = try_compile("fun f() { return 42; } print f() + 1.5;")
+            .unwrap_or_else(|_| panic!("program should compile"));
+
+        let copy = result.duplicate();
+
+        let shared = result.function.chunk.shared.as_ref().unwrap();
+        let copied = copy.function.chunk.shared.as_ref().unwrap();
+
+        assert_eq!(shared, copied);
+        assert!(!Rc::ptr_eq(shared, copied));
+
+        let function = copy
+            .function
+            .chunk
+            .constants
+            .iter()
+            .find(|constant| constant.is_obj() && constant.obj_type() == ObjectType::Function)
+            .unwrap()
+            .as_function();
+
+        assert!(Rc::ptr_eq(function.chunk.shared.as_ref().unwrap(), copied));
+
+        drop(vm::VM::new(result.table, result.allocator));
+        drop(vm::VM::new(copy.table, copy.allocator));
+    }
+
+    #[test]
+    fn it_adds_a_repeated_string_literal_to_a_chunk_once() {
+        let result = try_compile("fun f() { print \"x\"; print \"x\" + \"x\"; }")
+            .unwrap_or_else(|_| panic!("program should compile"));
+
+        let function = result
+            .function
+            .chunk
+            .constants
+            .iter()
+            .find(|constant| constant.is_obj() && constant.obj_type() == ObjectType::Function)
+            .unwrap()
+            .as_function();
+
+        let strings = function
+            .chunk
+            .constants
+            .iter()
+            .filter(|constant| constant.is_string())
+            .collect::<Vec<_>>();
+
+        assert_eq!(strings.len(), 1);
+        assert_eq!(strings[0].as_string().value(), "x\0");
+    }
+}
@@ -1,16 +1,29 @@
 use std::collections::HashMap;
 
+use ast::prelude::{Position, Span};
+use errors::Reporter;
+
 use crate::{
     compiler::{Compiler, FunctionType, UpValue},
     scanner::Scanner,
     token::{Token, TokenType},
     ParseResult,
 };
-use vm::{chunk::Chunk, op::Op, FunctionObject, ObjectPtr, RawObject, Table, Value};
-use vm::{Allocator, StringObject};
+use vm::Allocator;
+use vm::{
+    chunk::{Chunk, ConstantPool, SharedConstants},
+    op::Op,
+    FunctionObject, ObjectPtr, Table, Value,
+};
+
+/// How deeply expressions may nest before the parser gives up instead of overflowing the stack
+const MAX_DEPTH: usize = 256;
 
 pub struct Parser<'a> {
     scanner: Scanner<'a>,
+    src: &'a str,
+    /// Collects every syntax error, parsing carries on after each one
+    reporter: Reporter,
     previous: Token<'a>,
     current: Token<'a>,
     had_error: bool,
@@ -19,7 +32,14 @@ pub struct Parser<'a> {
     table: Table,
     compilers: Vec<Compiler<'a>>,
     current_compiler: usize,
+    /// How many class bodies enclose the code being compiled
+    class_depth: usize,
+    /// How many expressions enclose the one being compiled
+    depth: usize,
     allocator: Allocator,
+    /// The numeric constants of every function in the program, frozen and handed to every chunk
+    /// when compilation ends
+    constants: ConstantPool,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
@@ -74,21 +94,25 @@ macro_rules! hashmap {
 impl<'a> Parser<'a> {
     pub fn new(scanner: Scanner<'a>) -> Parser<'a> {
         let mut allocator = Allocator::new();
-        let fn_object = allocator.alloc(|next| FunctionObject::new(None, next));
+        let fn_object = allocator.alloc(FunctionObject::new(None));
 
         Parser {
+            src: scanner.src(),
+            reporter: Reporter::new(),
             scanner,
             previous: Token {
                 ty: TokenType::Eof,
                 lexme: "\0",
                 length: 0,
                 line: 0,
+                start: 0,
             },
             current: Token {
                 ty: TokenType::Eof,
                 lexme: "\0",
                 length: 0,
                 line: 0,
+                start: 0,
             },
             had_error: false,
             panic_mode: false,
@@ -99,10 +123,24 @@ impl<'a> Parser<'a> {
                         precedence: Precedence::Call,
                     },
                     TokenType::RightParen => ParseRule::default(),
-                    TokenType::LeftBrace => ParseRule::default(),
+                    TokenType::LeftBrace => ParseRule {
+                        prefix: Some(Parser::map),
+                        infix: None,
+                        precedence: Precedence::None,
+                    },
                     TokenType::RightBrace => ParseRule::default(),
+                    TokenType::LeftBracket => ParseRule {
+                        prefix: Some(Parser::array),
+                        infix: Some(Parser::index),
+                        precedence: Precedence::Call,
+                    },
+                    TokenType::RightBracket => ParseRule::default(),
                     TokenType::Comma => ParseRule::default(),
-                    TokenType::Dot => ParseRule::default(),
+                    TokenType::Dot => ParseRule {
+                        prefix: None,
+                        infix: Some(Parser::dot),
+                        precedence: Precedence::Call,
+                    },
                     TokenType::Minus=> ParseRule {
                         prefix: Some(Parser::unary),
                         infix: Some(Parser::binary),
@@ -180,6 +218,11 @@ impl<'a> Parser<'a> {
                         infix: None,
                         precedence: Precedence::None,
                     },
+                    TokenType::Float => ParseRule {
+                        prefix: Some(Parser::float),
+                        infix: None,
+                        precedence: Precedence::None,
+                    },
                     TokenType::And => ParseRule {
                         prefix: None,
                         infix: Some(Parser::and),
@@ -208,7 +251,11 @@ impl<'a> Parser<'a> {
                     TokenType::Print => ParseRule::default(),
                     TokenType::Return => ParseRule::default(),
                     TokenType::Super => ParseRule::default(),
-                    TokenType::This => ParseRule::default(),
+                    TokenType::This => ParseRule {
+                        prefix: Some(Parser::this),
+                        infix: None,
+                        precedence: Precedence::None,
+                    },
                     TokenType::True => ParseRule {
                         prefix: Some(Parser::literal),
                         infix: None,
@@ -224,18 +271,29 @@ impl<'a> Parser<'a> {
                         precedence: Precedence::Assignment
                     },
                     TokenType::Colon => ParseRule::default(),
+                    TokenType::Switch => ParseRule::default(),
+                    TokenType::Case => ParseRule::default(),
+                    TokenType::Default => ParseRule::default(),
 
             },
             table: Table::new(),
             allocator,
             compilers: vec![Compiler::new(FunctionType::Script, fn_object)],
             current_compiler: 0,
+            class_depth: 0,
+            depth: 0,
+            constants: ConstantPool::new(),
         }
     }
 
     pub fn had_error(&self) -> bool {
         self.had_error
     }
+
+    /// The errors reported so far
+    pub fn reporter(&self) -> Reporter {
+        self.reporter.clone()
+    }
     pub fn advance(&mut self) {
         std::mem::swap(&mut self.previous, &mut self.current);
 
@@ -264,21 +322,26 @@ impl<'a> Parser<'a> {
         }
         self.panic_mode = true;
 
-        eprint!("[line {}] Error", token.line);
-
-        if token.ty == TokenType::Eof {
-            eprint!(" at end");
-        } else if token.ty == TokenType::Error {
-            // Nothing
-        } else {
-            eprint!(" at '{}.{}'", token.length, token.lexme);
-        }
+        let span = Span::new(
+            self.position(token.start),
+            self.position(token.start + token.length),
+        );
 
-        eprintln!(": {}", msg);
+        self.reporter.error(msg, span);
 
         self.had_error = true;
     }
 
+    /// The line and column of a byte offset in the source, as the reporter expects them
+    fn position(&self, offset: usize) -> Position {
+        let before = &self.src[..offset.min(self.src.len())];
+
+        let line = before.matches('\n').count() + 1;
+        let column = before.len() - before.rfind('\n').map_or(0, |newline| newline + 1) + 1;
+
+        Position::new(line as u32, column as u32, offset)
+    }
+
     pub fn emit_byte(&mut self, byte: u8) {
         let line = self.previous.line;
         self.current_chunk_mut().write(byte, line);
@@ -290,7 +353,13 @@ impl<'a> Parser<'a> {
     }
 
     pub fn emit_return(&mut self) {
-        self.emit_bytes(Op::NIL as u8, Op::RETURN as u8);
+        if self.current_compiler().compiler_type == FunctionType::Initializer {
+            self.emit_bytes(Op::GET_LOCAL as u8, 0);
+        } else {
+            self.emit_byte(Op::NIL as u8);
+        }
+
+        self.emit_byte(Op::RETURN as u8);
     }
 
     pub(crate) fn expression(&mut self) {
@@ -298,26 +367,56 @@ impl<'a> Parser<'a> {
     }
 
     pub(crate) fn number(&mut self, _can_assign: bool) {
-        let value = self.previous.lexme.parse::<f64>().unwrap();
+        let lexme = self.previous.lexme.replace('_', "");
+
+        let value = if let Some(digits) = lexme.strip_prefix("0x") {
+            i64::from_str_radix(digits, 16)
+        } else if let Some(digits) = lexme.strip_prefix("0b") {
+            i64::from_str_radix(digits, 2)
+        } else {
+            lexme.parse::<i64>()
+        };
+
+        match value {
+            Ok(value) => self.emit_constant(Value::int(value)),
+            Err(_) => self.error("Integer literal is too large."),
+        }
+    }
+
+    pub(crate) fn float(&mut self, _can_assign: bool) {
+        let value = self.previous.lexme.replace('_', "").parse::<f64>().unwrap();
         self.emit_constant(Value::number(value));
     }
 
+    /// Objects are kept in the chunk's own constants, everything else is stored once in the pool
+    /// shared by the whole program
     pub fn emit_constant(&mut self, value: Value) {
-        let constant = self.make_constant(value);
-        self.emit_bytes(Op::CONSTANT as u8, constant);
+        if value.is_obj() {
+            let constant = self.make_constant(value);
+            self.emit_bytes(Op::CONSTANT as u8, constant);
+            return;
+        }
+
+        let index = self.constants.add(value);
+
+        if index > u16::MAX as usize {
+            self.error("Too many constants in one program.");
+            return;
+        }
+
+        self.emit_byte(Op::SHARED_CONSTANT as u8);
+        self.emit_bytes((index >> 8) as u8, index as u8);
     }
 
     pub fn start_compiler(&mut self, function: FunctionType) -> usize {
-        let mut compiler = Compiler::new(
-            function,
-            self.allocator.alloc(|next| FunctionObject::new(None, next)),
-        );
+        let mut compiler = Compiler::new(function, self.allocator.alloc(FunctionObject::new(None)));
 
         compiler.function.name = Some(
             self.allocator
-                .alloc(|next| StringObject::new(self.previous.lexme, &mut self.table, next)),
+                .alloc_string(self.previous.lexme, &mut self.table),
         );
 
+        compiler.name = self.previous.lexme;
         compiler.enclosing = Some(self.current_compiler);
         self.compilers.push(compiler);
         self.current_compiler = self.compilers.len() - 1;
@@ -329,14 +428,6 @@ impl<'a> Parser<'a> {
 
         let function = self.current_compiler().function.clone();
 
-        #[cfg(feature = "debug")]
-        {
-            function.chunk.disassemble(match function.name {
-                Some(name) => name.chars,
-                None => "<script>",
-            });
-        }
-
         self.current_compiler = self.current_compiler().enclosing.unwrap();
 
         function
@@ -363,19 +454,13 @@ impl<'a> Parser<'a> {
     pub fn end(mut self) -> ParseResult<'a> {
         self.emit_return();
 
-        #[cfg(feature = "debug")]
-        {
-            if !self.had_error {
-                self.current_chunk()
-                    .disassemble(match self.current_compiler().function.name {
-                        Some(name) => name.chars,
-                        None => "<script>",
-                    });
-            }
-        }
-
         let function = self.current_compiler().function.clone();
 
+        share_constants(
+            function.clone(),
+            &std::mem::take(&mut self.constants).freeze(),
+        );
+
         ParseResult {
             function,
             table: self.table,
@@ -410,6 +495,73 @@ impl<'a> Parser<'a> {
         self.consume(TokenType::RightParen, "Expect ')' after expression.");
     }
 
+    /// `{ "a": 1, "b": 2 }`, the keys and values are pushed in order and `MAP_NEW` pairs them up
+    pub(crate) fn map(&mut self, _can_assign: bool) {
+        let mut count = 0;
+
+        if !self.check(TokenType::RightBrace) {
+            loop {
+                self.expression();
+                self.consume(TokenType::Colon, "Expect ':' after map key.");
+                self.expression();
+
+                if count == 255 {
+                    self.error("Can't have more than 255 entries in a map literal.");
+                }
+
+                count += 1;
+
+                if !self.match_token(TokenType::Comma) {
+                    break;
+                }
+            }
+        }
+
+        self.consume(TokenType::RightBrace, "Expect '}' after map entries.");
+
+        self.emit_bytes(Op::MAP_NEW as u8, count as u8);
+    }
+
+    /// `[1, 2, 3]`, the items are pushed in order and `ARRAY_NEW` collects them
+    pub(crate) fn array(&mut self, _can_assign: bool) {
+        let mut count = 0;
+
+        if !self.check(TokenType::RightBracket) {
+            loop {
+                self.expression();
+
+                if count == 255 {
+                    self.error("Can't have more than 255 items in an array literal.");
+                }
+
+                count += 1;
+
+                if !self.match_token(TokenType::Comma) {
+                    break;
+                }
+            }
+        }
+
+        self.consume(TokenType::RightBracket, "Expect ']' after array items.");
+
+        self.emit_bytes(Op::ARRAY_NEW as u8, count as u8);
+    }
+
+    /// `array[index]` or `map[key]`, or `receiver[index] = value` when it's the target of an
+    /// assignment. The vm picks between an array and a map when it runs
+    pub(crate) fn index(&mut self, can_assign: bool) {
+        self.expression();
+
+        self.consume(TokenType::RightBracket, "Expect ']' after index.");
+
+        if can_assign && self.match_token(TokenType::Equal) {
+            self.expression();
+            self.emit_byte(Op::INDEX_SET as u8);
+        } else {
+            self.emit_byte(Op::INDEX_GET as u8);
+        }
+    }
+
     pub(crate) fn unary(&mut self, _can_assign: bool) {
         let ty = self.previous.ty;
 
@@ -422,7 +574,7 @@ impl<'a> Parser<'a> {
         }
     }
 
-    pub fn binary(&mut self) {
+    pub fn binary(&mut self, _can_assign: bool) {
         let ty = self.previous.ty;
 
         let rule = self.get_rule(ty);
@@ -456,20 +608,29 @@ impl<'a> Parser<'a> {
     }
 
     pub fn string(&mut self, _can_assign: bool) {
-        let string_object = self.allocator.alloc(|next| {
-            StringObject::new(
-                &self.previous.lexme[1..self.previous.lexme.len() - 1],
-                &mut self.table,
-                next,
-            )
-        });
+        let constant = self.string_constant(&self.previous.lexme[1..self.previous.lexme.len() - 1]);
 
-        let obj = Value::object(string_object.into());
-
-        self.emit_constant(obj);
+        self.emit_bytes(Op::CONSTANT as u8, constant);
     }
 
     pub(crate) fn parse_with_precedence(&mut self, precedence: Precedence) {
+        if self.depth >= MAX_DEPTH {
+            self.error_at_current("Expression too deeply nested.");
+
+            // Nothing after this point can be trusted so stop compiling altogether
+            while self.current.ty != TokenType::Eof {
+                self.advance();
+            }
+
+            return;
+        }
+
+        self.depth += 1;
+        self.parse_operators(precedence);
+        self.depth -= 1;
+    }
+
+    fn parse_operators(&mut self, precedence: Precedence) {
         self.advance();
 
         let prefix_rule = self.get_rule(self.previous.ty).prefix;
@@ -483,13 +644,25 @@ impl<'a> Parser<'a> {
             }
         }
 
+        self.infix(precedence);
+
+        // Anything that could be assigned to has already taken the `=`
+        if can_assign && self.match_token(TokenType::Equal) {
+            self.error("Invalid assignment target.");
+        }
+    }
+
+    /// Parses the infix operators that follow an already compiled operand
+    fn infix(&mut self, precedence: Precedence) {
+        let can_assign = precedence <= Precedence::Assignment;
+
         while precedence <= self.get_rule(self.current.ty).precedence {
             self.advance();
 
             let infix_rule = self.get_rule(self.previous.ty).infix;
 
             match infix_rule {
-                Some(infix_rule) => infix_rule(self),
+                Some(infix_rule) => infix_rule(self, can_assign),
                 None => {
                     self.error("Expect expression.");
                 }
@@ -511,7 +684,9 @@ impl<'a> Parser<'a> {
     }
 
     pub(crate) fn declaration(&mut self) {
-        if self.match_token(TokenType::Var) {
+        if self.match_token(TokenType::Class) {
+            self.class_declaration();
+        } else if self.match_token(TokenType::Var) {
             self.var_declaration();
         } else if self.match_token(TokenType::Fun) {
             self.fun_declaration();
@@ -533,6 +708,8 @@ impl<'a> Parser<'a> {
             self.end_scope();
         } else if self.match_token(TokenType::If) {
             self.if_statement()
+        } else if self.match_token(TokenType::Switch) {
+            self.switch_statement();
         } else if self.match_token(TokenType::While) {
             self.while_statement();
         } else if self.match_token(TokenType::For) {
@@ -574,6 +751,7 @@ impl<'a> Parser<'a> {
                 | TokenType::Var
                 | TokenType::For
                 | TokenType::If
+                | TokenType::Switch
                 | TokenType::While
                 | TokenType::Print
                 | TokenType::Return => return,
@@ -631,20 +809,30 @@ impl<'a> Parser<'a> {
         self.emit_bytes(Op::DEFINE_GLOBAL as u8, global)
     }
 
-    fn identifier_constant(&mut self, lexme: &str) -> u8 {
-        let string_object = self
-            .allocator
-            .alloc(|next| StringObject::new(lexme, &mut self.table, next));
+    fn identifier_constant(&mut self, lexme: &'a str) -> u8 {
+        self.string_constant(lexme)
+    }
+
+    /// The constant slot holding `text` as a string, each distinct string is only added to a chunk
+    /// once
+    fn string_constant(&mut self, text: &'a str) -> u8 {
+        if let Some(&constant) = self.current_compiler().strings.get(text) {
+            return constant;
+        }
 
-        let val = Value::object(string_object.into());
-        self.make_constant(val)
+        let string_object = self.allocator.alloc_string(text, &mut self.table);
+        let constant = self.make_constant(Value::object(string_object.into()));
+
+        self.current_compiler_mut().strings.insert(text, constant);
+
+        constant
     }
 
     fn variable(&mut self, can_assign: bool) {
         self.named_variable(self.previous.lexme, can_assign);
     }
 
-    fn named_variable(&mut self, name: &str, can_assign: bool) {
+    fn named_variable(&mut self, name: &'a str, can_assign: bool) {
         let get_op;
 
         let set_op;
@@ -818,6 +1006,9 @@ impl<'a> Parser<'a> {
 
         let then_jump = self.emit_jump(Op::JUMP_IF_FALSE as u8);
 
+        // JUMP_IF_FALSE leaves the condition on the stack, each branch pops it before running
+        self.emit_byte(Op::POP as u8);
+
         self.statement();
 
         let else_jump = self.emit_jump(Op::JUMP as u8);
@@ -833,6 +1024,73 @@ impl<'a> Parser<'a> {
         self.patch_jump(else_jump)
     }
 
+    /// `switch (subject) { case a: ... default: ... }`, the first case equal to the subject runs
+    /// and then jumps past the rest, there is no fallthrough
+    fn switch_statement(&mut self) {
+        let keyword = self.previous;
+
+        self.consume(TokenType::LeftParen, "Expect '(' after 'switch'.");
+        self.expression();
+        self.consume(TokenType::RightParen, "Expect ')' after switch subject.");
+        self.consume(TokenType::LeftBrace, "Expect '{' before switch cases.");
+
+        // The subject is kept in a local named `switch`, which no identifier can refer to
+        self.begin_scope();
+        self.add_local(keyword);
+        self.mark_initialized();
+
+        let subject = (self.current_compiler().local_count - 1) as u8;
+
+        let mut end_jumps = Vec::new();
+
+        while self.match_token(TokenType::Case) {
+            self.emit_bytes(Op::GET_LOCAL as u8, subject);
+            self.expression();
+            self.consume(TokenType::Colon, "Expect ':' after case value.");
+            self.emit_byte(Op::EQUAL as u8);
+
+            let next_case = self.emit_jump(Op::JUMP_IF_FALSE as u8);
+
+            self.emit_byte(Op::POP as u8);
+
+            self.case_body();
+
+            end_jumps.push(self.emit_jump(Op::JUMP as u8));
+
+            self.patch_jump(next_case);
+
+            self.emit_byte(Op::POP as u8);
+        }
+
+        if self.match_token(TokenType::Default) {
+            self.consume(TokenType::Colon, "Expect ':' after 'default'.");
+            self.case_body();
+        }
+
+        self.consume(TokenType::RightBrace, "Expect '}' after switch cases.");
+
+        for jump in end_jumps {
+            self.patch_jump(jump);
+        }
+
+        self.end_scope();
+    }
+
+    /// The statements of a case run up to the next case, the default or the end of the switch
+    fn case_body(&mut self) {
+        self.begin_scope();
+
+        while !self.check(TokenType::Case)
+            && !self.check(TokenType::Default)
+            && !self.check(TokenType::RightBrace)
+            && !self.check(TokenType::Eof)
+        {
+            self.declaration();
+        }
+
+        self.end_scope();
+    }
+
     fn emit_jump(&mut self, jump_if_false: u8) -> usize {
         self.emit_byte(jump_if_false);
         self.emit_bytes(0xff, 0xff);
@@ -850,7 +1108,7 @@ impl<'a> Parser<'a> {
         self.current_chunk_mut().code[offset + 1] = (jump & 0xff) as u8;
     }
 
-    fn and(&mut self) {
+    fn and(&mut self, _can_assign: bool) {
         let end_jump = self.emit_jump(Op::JUMP_IF_FALSE as u8);
 
         self.emit_byte(Op::POP as u8);
@@ -860,14 +1118,18 @@ impl<'a> Parser<'a> {
         self.patch_jump(end_jump)
     }
 
-    pub fn ternary(&mut self) {
+    /// `cond ? a : b`, `JUMP_IF_FALSE` leaves the condition on the stack so each branch pops it
+    /// before pushing its own value
+    pub fn ternary(&mut self, _can_assign: bool) {
         let then_jump = self.emit_jump(Op::JUMP_IF_FALSE as u8);
 
+        self.emit_byte(Op::POP as u8);
         self.expression();
 
         let else_jump = self.emit_jump(Op::JUMP as u8);
 
         self.patch_jump(then_jump);
+        self.emit_byte(Op::POP as u8);
 
         self.consume(TokenType::Colon, "Expect ':' after ternary expression ");
 
@@ -876,7 +1138,7 @@ impl<'a> Parser<'a> {
         self.patch_jump(else_jump);
     }
 
-    fn or(&mut self) {
+    fn or(&mut self, _can_assign: bool) {
         let else_jump = self.emit_jump(Op::JUMP_IF_FALSE as u8);
         let end_jump = self.emit_jump(Op::JUMP as u8);
 
@@ -979,6 +1241,73 @@ impl<'a> Parser<'a> {
         self.end_scope();
     }
 
+    fn class_declaration(&mut self) {
+        self.consume(TokenType::Identifier, "Expect class name.");
+
+        let class_name = self.previous.lexme;
+        let name_constant = self.identifier_constant(class_name);
+
+        self.declare_variable();
+
+        self.emit_bytes(Op::CLASS as u8, name_constant);
+        self.define_variable(name_constant);
+
+        self.class_depth += 1;
+
+        // Load the class back so the methods can be attached to it
+        self.named_variable(class_name, false);
+
+        self.consume(TokenType::LeftBrace, "Expect '{' before class body.");
+
+        while !self.check(TokenType::RightBrace) && !self.check(TokenType::Eof) {
+            self.method();
+        }
+
+        self.consume(TokenType::RightBrace, "Expect '}' after class body.");
+
+        self.emit_byte(Op::POP as u8);
+
+        self.class_depth -= 1;
+    }
+
+    fn method(&mut self) {
+        self.consume(TokenType::Identifier, "Expect method name.");
+
+        let constant = self.identifier_constant(self.previous.lexme);
+
+        let ty = if self.previous.lexme == "init" {
+            FunctionType::Initializer
+        } else {
+            FunctionType::Method
+        };
+
+        self.function(ty);
+
+        self.emit_bytes(Op::METHOD as u8, constant);
+    }
+
+    fn dot(&mut self, can_assign: bool) {
+        self.consume(TokenType::Identifier, "Expect property name after '.'.");
+
+        let name = self.identifier_constant(self.previous.lexme);
+
+        if can_assign && self.match_token(TokenType::Equal) {
+            self.expression();
+            self.emit_bytes(Op::SET_PROPERTY as u8, name);
+        } else {
+            self.emit_bytes(Op::GET_PROPERTY as u8, name);
+        }
+    }
+
+    fn this(&mut self, _can_assign: bool) {
+        if self.class_depth == 0 {
+            self.error("Can't use 'this' outside of a class.");
+            return;
+        }
+
+        self.variable(false);
+    }
+
     fn fun_declaration(&mut self) {
         let global = self.parse_variable("Expect function name.");
 
@@ -1041,7 +1370,7 @@ impl<'a> Parser<'a> {
         }
     }
 
-    fn call(&mut self) {
+    fn call(&mut self, _can_assign: bool) {
         let arg_count = self.arg_list();
         self.emit_bytes(Op::CALL as u8, arg_count)
     }
@@ -1076,18 +1405,115 @@ impl<'a> Parser<'a> {
         }
         if self.match_token(TokenType::SemiColon) {
             self.emit_return();
-        } else {
-            self.expression();
+        } else if self.current_compiler().compiler_type == FunctionType::Initializer {
+            self.error("Can't return a value from an initializer.");
+        } else if !self.self_tail_call() {
             self.consume(TokenType::SemiColon, "Expect ';' after return value.");
             self.emit_byte(Op::RETURN as u8)
         }
     }
+
+    /// Compiles the value of a return statement. When it is a call to the function being compiled,
+    /// with every parameter passed, the arguments are stored into the parameters and the function
+    /// jumps back to its start instead of making a new call. The name can be rebound to another
+    /// function by the time the call runs, so the jump is only taken when the callee is still the
+    /// running function and otherwise the call is made as usual.
+    ///
+    /// Returns true if a tail call was compiled, otherwise the returned value has been left on the stack
+    fn self_tail_call(&mut self) -> bool {
+        let name = self.current_compiler().name;
+
+        if self.current_compiler().compiler_type != FunctionType::Function
+            || !self.check(TokenType::Identifier)
+            || self.current.lexme != name
+            || self.resolve_local(self.current_compiler, name).is_some()
+        {
+            self.expression();
+            return false;
+        }
+
+        self.advance();
+        self.named_variable(name, true);
+
+        if !self.match_token(TokenType::LeftParen) {
+            self.infix(Precedence::Assignment);
+            return false;
+        }
+
+        let arg_count = self.arg_list();
+
+        // Nothing but locals sits below a return's value, so the callee is in the slot after them
+        let callee_slot = self.current_compiler().local_count;
+
+        if !self.check(TokenType::SemiColon)
+            || arg_count as usize != self.current_compiler().function.arity
+            || callee_slot > u8::MAX as usize
+        {
+            self.emit_bytes(Op::CALL as u8, arg_count);
+            self.infix(Precedence::Assignment);
+            return false;
+        }
+
+        self.advance();
+
+        // Slot 0 holds the function that is running
+        self.emit_bytes(Op::GET_LOCAL as u8, callee_slot as u8);
+        self.emit_bytes(Op::GET_LOCAL as u8, 0);
+        self.emit_byte(Op::EQUAL as u8);
+
+        let call_jump = self.emit_jump(Op::JUMP_IF_FALSE as u8);
+        self.emit_byte(Op::POP as u8);
+
+        // The parameters start at 1
+        for slot in (1..=arg_count).rev() {
+            self.emit_bytes(Op::SET_LOCAL as u8, slot);
+            self.emit_byte(Op::POP as u8);
+        }
+
+        // The callee
+        self.emit_byte(Op::POP as u8);
+
+        // Discard the locals declared in the body, keeping the parameters
+        for local in (arg_count as usize + 1..self.current_compiler().local_count).rev() {
+            if self.current_compiler().locals[local].is_captured {
+                self.emit_byte(Op::CLOSE_UPVALUE as u8);
+            } else {
+                self.emit_byte(Op::POP as u8);
+            }
+        }
+
+        self.emit_loop(0);
+
+        self.patch_jump(call_jump);
+        self.emit_byte(Op::POP as u8);
+        self.emit_bytes(Op::CALL as u8, arg_count);
+        self.emit_byte(Op::RETURN as u8);
+
+        true
+    }
+}
+
+/// Points `function` and every function in its constants at the frozen pool
+fn share_constants(mut function: ObjectPtr<FunctionObject>, shared: &SharedConstants) {
+    function.chunk.shared = Some(shared.clone());
+
+    #[cfg(feature = "debug")]
+    function.chunk.disassemble(match function.name {
+        Some(name) => name.chars,
+        None => "<script>",
+    });
+
+    for constant in &function.chunk.constants {
+        if constant.is_obj() && constant.obj_type() == vm::ObjectType::Function {
+            share_constants(constant.as_function(), shared);
+        }
+    }
 }
 
 #[derive(Clone, Copy)]
 struct ParseRule<'a> {
     prefix: Option<fn(&mut Parser<'a>, bool)>,
-    infix: Option<fn(&mut Parser<'a>)>,
+    infix: Option<fn(&mut Parser<'a>, bool)>,
     precedence: Precedence,
 }
 
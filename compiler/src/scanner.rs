@@ -8,6 +8,8 @@ pub struct Scanner<'a> {
     current: usize,
     /// The current line of the source code
     line: usize,
+    /// The type of the last token returned, used to tell `.5` apart from a property access
+    previous: Option<TokenType>,
 }
 
 macro_rules! matches {
@@ -27,10 +29,22 @@ impl<'a> Scanner<'a> {
             start: 0,
             current: 0,
             line: 1,
+            previous: None,
         }
     }
 
+    /// The source being scanned
+    pub fn src(&self) -> &'a str {
+        self.src
+    }
+
     pub fn scan_token(&mut self) -> Token<'a> {
+        let token = self.lex();
+        self.previous = Some(token.ty);
+        token
+    }
+
+    fn lex(&mut self) -> Token<'a> {
         self.skip_whitespace();
         self.start = self.current;
 
@@ -38,6 +52,10 @@ impl<'a> Scanner<'a> {
             return self.make_token(TokenType::Eof);
         }
 
+        // `.5` is a number unless the `.` could be accessing a field of the previous operand
+        let fraction =
+            self.peek() == Some(".") && self.is_digit(self.peek_next()) && !self.follows_operand();
+
         let c = self.advance();
 
         match c {
@@ -46,8 +64,11 @@ impl<'a> Scanner<'a> {
                 ")" => self.make_token(TokenType::RightParen),
                 "{" => self.make_token(TokenType::LeftBrace),
                 "}" => self.make_token(TokenType::RightBrace),
+                "[" => self.make_token(TokenType::LeftBracket),
+                "]" => self.make_token(TokenType::RightBracket),
                 ";" => self.make_token(TokenType::SemiColon),
                 "," => self.make_token(TokenType::Comma),
+                "." if fraction => self.fraction(),
                 "." => self.make_token(TokenType::Dot),
                 "-" => self.make_token(TokenType::Minus),
                 "+" => self.make_token(TokenType::Plus),
@@ -87,6 +108,7 @@ impl<'a> Scanner<'a> {
             lexme: arg,
             length: self.current - self.start,
             line: self.line,
+            start: self.start,
         }
     }
 
@@ -111,6 +133,7 @@ impl<'a> Scanner<'a> {
             lexme: &self.src[self.start..self.current],
             length,
             line: self.line,
+            start: self.start,
         }
     }
 
@@ -188,16 +211,125 @@ impl<'a> Scanner<'a> {
     }
 
     fn number(&mut self) -> Token<'a> {
-        while self.is_digit(self.peek()) {
-            self.advance();
+        if self.current - self.start == 1 && &self.src[self.start..self.current] == "0" {
+            match self.peek() {
+                Some("x") => return self.radix_number(16, "Invalid hexadecimal literal."),
+                Some("b") => return self.radix_number(2, "Invalid binary literal."),
+                _ => {}
+            }
         }
 
-        if self.peek() == Some(".") && self.is_digit(self.peek_next()) {
+        if !self.digits() {
+            return self.error_token("Invalid digit separator.");
+        }
+
+        if self.peek() == Some(".") {
             self.advance();
 
-            while self.is_digit(self.peek()) {
-                self.advance();
+            // `1.` would be read as a float by some and as a property access by others
+            if !self.is_digit(self.peek()) {
+                return self.error_token("Expect a digit after '.'.");
             }
+
+            return self.fraction();
+        }
+
+        match self.exponent() {
+            Ok(true) => self.make_token(TokenType::Float),
+            Ok(false) => self.make_token(TokenType::Number),
+            Err(message) => self.error_token(message),
+        }
+    }
+
+    /// Scans the digits after a decimal point, which has already been consumed
+    fn fraction(&mut self) -> Token<'a> {
+        if !self.digits() {
+            return self.error_token("Invalid digit separator.");
+        }
+
+        if let Err(message) = self.exponent() {
+            return self.error_token(message);
+        }
+
+        self.make_token(TokenType::Float)
+    }
+
+    /// Consumes an exponent such as `e10` or `E-3` if one follows, returns the error to report when
+    /// it has no digits
+    fn exponent(&mut self) -> Result<bool, &'static str> {
+        if !std::matches!(self.peek(), Some("e") | Some("E")) {
+            return Ok(false);
+        }
+
+        self.advance();
+
+        if std::matches!(self.peek(), Some("+") | Some("-")) {
+            self.advance();
+        }
+
+        if !self.is_digit(self.peek()) {
+            return Err("Expect digits in exponent.");
+        }
+
+        if !self.digits() {
+            return Err("Invalid digit separator.");
+        }
+
+        Ok(true)
+    }
+
+    /// Whether the last token can end an operand, in which case a `.` starts a property access
+    fn follows_operand(&self) -> bool {
+        std::matches!(
+            self.previous,
+            Some(
+                TokenType::Identifier
+                    | TokenType::Number
+                    | TokenType::Float
+                    | TokenType::String
+                    | TokenType::True
+                    | TokenType::False
+                    | TokenType::Nil
+                    | TokenType::This
+                    | TokenType::RightParen
+                    | TokenType::RightBracket
+            )
+        )
+    }
+
+    /// Consumes a run of digits that may be separated by single `_`s, returns false if a `_` is
+    /// doubled or ends the run
+    fn digits(&mut self) -> bool {
+        let mut separated = false;
+        let mut valid = true;
+
+        while self.is_digit(self.peek()) || self.peek() == Some("_") {
+            let separator = self.peek() == Some("_");
+
+            valid &= !(separator && separated);
+            separated = separator;
+
+            self.advance();
+        }
+
+        valid && !separated
+    }
+
+    /// Scans the digits of a `0x` or `0b` literal, the whole literal is an error if any of them aren't valid
+    fn radix_number(&mut self, radix: u32, error: &'a str) -> Token<'a> {
+        // The `x` or `b`
+        self.advance();
+
+        let digits_start = self.current;
+
+        while self.is_alpha(self.peek()) || self.is_digit(self.peek()) {
+            self.advance();
+        }
+
+        let digits = &self.src[digits_start..self.current];
+
+        if digits.is_empty() || !digits.chars().all(|c| c.is_digit(radix)) {
+            return self.error_token(error);
         }
 
         self.make_token(TokenType::Number)
@@ -214,7 +346,18 @@ impl<'a> Scanner<'a> {
     fn identifier_type(&self) -> TokenType {
         match self.src.get(self.start..self.start + 1) {
             Some("a") => self.check_keyword(1, 2, "nd", TokenType::And),
-            Some("c") => self.check_keyword(1, 4, "lass", TokenType::Class),
+            Some("c") => {
+                if self.current - self.start > 1 {
+                    match self.src.get(self.start + 1..self.start + 2) {
+                        Some("a") => self.check_keyword(2, 2, "se", TokenType::Case),
+                        Some("l") => self.check_keyword(2, 3, "ass", TokenType::Class),
+                        _ => TokenType::Identifier,
+                    }
+                } else {
+                    TokenType::Identifier
+                }
+            }
+            Some("d") => self.check_keyword(1, 6, "efault", TokenType::Default),
             Some("e") => self.check_keyword(1, 3, "lse", TokenType::Else),
             Some("f") => {
                 if self.current - self.start > 1 {
@@ -233,7 +376,17 @@ impl<'a> Scanner<'a> {
             Some("o") => self.check_keyword(1, 1, "r", TokenType::Or),
             Some("p") => self.check_keyword(1, 4, "rint", TokenType::Print),
             Some("r") => self.check_keyword(1, 5, "eturn", TokenType::Return),
-            Some("s") => self.check_keyword(1, 4, "uper", TokenType::Super),
+            Some("s") => {
+                if self.current - self.start > 1 {
+                    match self.src.get(self.start + 1..self.start + 2) {
+                        Some("u") => self.check_keyword(2, 3, "per", TokenType::Super),
+                        Some("w") => self.check_keyword(2, 4, "itch", TokenType::Switch),
+                        _ => TokenType::Identifier,
+                    }
+                } else {
+                    TokenType::Identifier
+                }
+            }
             Some("t") => {
                 if self.current - self.start > 1 {
                     match self.src.get(self.start + 1..self.start + 2) {
@@ -264,3 +417,128 @@ impl<'a> Scanner<'a> {
         TokenType::Identifier
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::Scanner;
+    use crate::token::TokenType;
+
+    fn first_token(src: &str) -> TokenType {
+        Scanner::new(src).scan_token().ty
+    }
+
+    #[test]
+    fn it_does_not_confuse_identifiers_with_f_keywords() {
+        let cases = [
+            ("f", TokenType::Identifier),
+            ("fo", TokenType::Identifier),
+            ("foo", TokenType::Identifier),
+            ("for", TokenType::For),
+            ("fun", TokenType::Fun),
+            ("fun2", TokenType::Identifier),
+            ("func", TokenType::Identifier),
+            ("false", TokenType::False),
+            ("falsey", TokenType::Identifier),
+        ];
+
+        for (src, expected) in cases {
+            assert_eq!(first_token(src), expected, "scanning `{}`", src);
+        }
+    }
+
+    #[test]
+    fn it_distinguishes_integer_and_float_literals() {
+        assert_eq!(first_token("7"), TokenType::Number);
+        assert_eq!(first_token("7.5"), TokenType::Float);
+    }
+
+    #[test]
+    fn it_requires_digits_on_both_sides_of_a_decimal_point() {
+        let token = Scanner::new("1.;").scan_token();
+
+        assert_eq!(token.ty, TokenType::Error);
+        assert_eq!(token.lexme, "Expect a digit after '.'.");
+
+        assert_eq!(first_token("1.5"), TokenType::Float);
+    }
+
+    #[test]
+    fn it_scans_an_exponent_as_part_of_a_float() {
+        assert_eq!(first_token("1e10"), TokenType::Float);
+        assert_eq!(first_token("2.5E-1"), TokenType::Float);
+        assert_eq!(first_token(".5e+2"), TokenType::Float);
+
+        let token = Scanner::new("1e;").scan_token();
+
+        assert_eq!(token.ty, TokenType::Error);
+        assert_eq!(token.lexme, "Expect digits in exponent.");
+    }
+
+    #[test]
+    fn it_scans_a_leading_dot_float_where_an_operand_starts() {
+        let token = Scanner::new(".5").scan_token();
+        assert_eq!(token.ty, TokenType::Float);
+        assert_eq!(token.lexme, ".5");
+
+        let mut scanner = Scanner::new("a.b");
+        assert_eq!(scanner.scan_token().ty, TokenType::Identifier);
+        assert_eq!(scanner.scan_token().ty, TokenType::Dot);
+        assert_eq!(scanner.scan_token().ty, TokenType::Identifier);
+
+        let mut scanner = Scanner::new("print -.5;");
+        assert_eq!(scanner.scan_token().ty, TokenType::Print);
+        assert_eq!(scanner.scan_token().ty, TokenType::Minus);
+        assert_eq!(scanner.scan_token().ty, TokenType::Float);
+    }
+
+    #[test]
+    fn it_scans_hexadecimal_and_binary_literals() {
+        let token = Scanner::new("0xFF;").scan_token();
+
+        assert_eq!(token.ty, TokenType::Number);
+        assert_eq!(token.lexme, "0xFF");
+
+        assert_eq!(first_token("0b101"), TokenType::Number);
+        assert_eq!(first_token("0"), TokenType::Number);
+    }
+
+    #[test]
+    fn it_scans_underscores_between_digits() {
+        let token = Scanner::new("1_000;").scan_token();
+
+        assert_eq!(token.ty, TokenType::Number);
+        assert_eq!(token.lexme, "1_000");
+
+        assert_eq!(first_token("1_000.000_1"), TokenType::Float);
+        // A leading underscore makes an identifier rather than a number
+        assert_eq!(first_token("_1"), TokenType::Identifier);
+    }
+
+    #[test]
+    fn it_rejects_misplaced_digit_separators() {
+        for src in ["1__0", "1_", "1_.5", "1.5_"] {
+            assert_eq!(first_token(src), TokenType::Error, "scanning `{}`", src);
+        }
+
+        assert_eq!(
+            Scanner::new("1__0").scan_token().lexme,
+            "Invalid digit separator."
+        );
+    }
+
+    #[test]
+    fn it_rejects_malformed_hexadecimal_and_binary_literals() {
+        for src in ["0x", "0xG", "0b", "0b102"] {
+            assert_eq!(first_token(src), TokenType::Error, "scanning `{}`", src);
+        }
+
+        assert_eq!(
+            Scanner::new("0xG").scan_token().lexme,
+            "Invalid hexadecimal literal."
+        );
+        assert_eq!(
+            Scanner::new("0b2").scan_token().lexme,
+            "Invalid binary literal."
+        );
+    }
+}
@@ -4,6 +4,8 @@ pub struct Token<'a> {
     pub lexme: &'a str,
     pub length: usize,
     pub line: usize,
+    /// The offset of the lexme in the source
+    pub start: usize,
 }
 
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Hash)]
@@ -12,6 +14,8 @@ pub enum TokenType {
     RightParen,
     LeftBrace,
     RightBrace,
+    LeftBracket,
+    RightBracket,
     Comma,
     Dot,
     Minus,
@@ -30,6 +34,7 @@ pub enum TokenType {
     Identifier,
     String,
     Number,
+    Float,
     And,
     Class,
     Else,
@@ -51,4 +56,7 @@ pub enum TokenType {
     QuestionMark,
     Colon,
     Type,
+    Switch,
+    Case,
+    Default,
 }
@@ -1,22 +1,103 @@
 use std::fmt::{self, Display};
 
-use ast::prelude::{Literal, Spanned, SymbolId};
+use ast::prelude::{self as a, Literal, Spanned, SymbolId};
 
+/// A program after name resolution and type checking.
+/// Type aliases have been expanded so they don't appear in the resolved program
 #[derive(Debug)]
-pub enum Expression {
+pub struct Program {
+    pub functions: Vec<Spanned<Function>>,
+    pub consts: Vec<Spanned<Const>>,
+    pub traits: Vec<Spanned<Trait>>,
+}
+
+#[derive(Debug)]
+pub struct Function {
+    pub name: Spanned<SymbolId>,
+    pub params: Vec<Spanned<FunctionParam>>,
+    pub returns: Spanned<Type>,
+    pub body: Spanned<Statement>,
+}
+
+#[derive(Debug)]
+pub struct Trait {
+    pub name: Spanned<SymbolId>,
+    pub methods: Vec<Spanned<TraitMethod>>,
+}
+
+/// A method signature, its type is always a `Type::Function`
+#[derive(Debug)]
+pub struct TraitMethod {
+    pub name: Spanned<SymbolId>,
+    pub ty: Type,
+}
+
+#[derive(Debug)]
+pub struct FunctionParam {
+    pub name: Spanned<SymbolId>,
+    pub ty: Spanned<Type>,
+}
+
+#[derive(Debug)]
+pub struct Const {
+    pub name: Spanned<SymbolId>,
+    pub ty: Type,
+    pub initializer: Spanned<Expression>,
+}
+
+#[derive(Debug)]
+pub enum Statement {
+    Expression(Spanned<Expression>),
+    While {
+        cond: Spanned<Expression>,
+        body: Box<Spanned<Statement>>,
+    },
+    If {
+        cond: Spanned<Expression>,
+        then: Box<Spanned<Statement>>,
+        else_: Option<Box<Spanned<Statement>>>,
+    },
+    /// Statements after one that always leaves the block are dropped
+    Block(Vec<Spanned<Statement>>),
+    Return(Option<Spanned<Expression>>),
+    Break,
+    Continue,
+    Let {
+        identifier: Spanned<SymbolId>,
+        ty: Type,
+        init: Option<Spanned<Expression>>,
+    },
+}
+
+/// An expression and the type it evaluates to
+#[derive(Debug)]
+pub struct Expression {
+    pub kind: ExpressionKind,
+    pub ty: Type,
+}
+
+#[derive(Debug)]
+pub enum ExpressionKind {
     Literal(Literal),
     Ternary {
         cond: Box<Spanned<Expression>>,
         lhs: Box<Spanned<Expression>>,
         rhs: Box<Spanned<Expression>>,
     },
-    Identifier(Spanned<SymbolId>),
+    Identifier {
+        name: Spanned<SymbolId>,
+        binding: Binding,
+    },
     Binary {
         op: Spanned<BinaryOp>,
         lhs: Box<Spanned<Expression>>,
         rhs: Box<Spanned<Expression>>,
     },
-    Grouping(Box<Spanned<Expression>>),
+    Logical {
+        op: Spanned<LogicalOp>,
+        lhs: Box<Spanned<Expression>>,
+        rhs: Box<Spanned<Expression>>,
+    },
     Call {
         callee: Box<Spanned<Expression>>,
         args: Vec<Spanned<Expression>>,
@@ -25,21 +106,45 @@ pub enum Expression {
         op: Spanned<UnaryOp>,
         rhs: Box<Spanned<Expression>>,
     },
+    Closure {
+        params: Vec<Spanned<FunctionParam>>,
+        body: Box<Spanned<Statement>>,
+    },
     Error,
 }
 
-#[derive(Debug)]
+/// What a name was declared as
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Binding {
+    /// A variable declared with `let`
+    Local,
+    Param,
+    Function,
+    Const,
+    /// A type alias or a trait
+    Type,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum BinaryOp {
     Plus,
     Minus,
     Slash,
     Star,
+    BangEqual,
     EqualEqual,
     Greater,
+    GreaterEqual,
     Less,
+    LessEqual,
     Assignment,
 }
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LogicalOp {
+    And,
+    Or,
+}
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum UnaryOp {
     Bang,
     Plus,
@@ -62,5 +167,178 @@ pub enum Type {
         returns: Box<Spanned<Type>>,
     },
     Void,
+    /// The type of the `nil` literal
+    Nil,
     Error,
 }
+
+impl Type {
+    /// Structural comparison of two types that ignores spans.
+    /// The error type is equivalent to every type so a single mistake is only reported once
+    pub fn equivalent(&self, other: &Type) -> bool {
+        match (self, other) {
+            (Type::Error, _) | (_, Type::Error) => true,
+            (Type::Named(lhs), Type::Named(rhs)) => lhs.value() == rhs.value(),
+            (
+                Type::Array {
+                    ty: lhs,
+                    length: lhs_length,
+                },
+                Type::Array {
+                    ty: rhs,
+                    length: rhs_length,
+                },
+            ) => lhs_length == rhs_length && lhs.equivalent(rhs),
+            (
+                Type::Function {
+                    params: lhs_params,
+                    returns: lhs_returns,
+                },
+                Type::Function {
+                    params: rhs_params,
+                    returns: rhs_returns,
+                },
+            ) => {
+                lhs_params.len() == rhs_params.len()
+                    && lhs_params
+                        .iter()
+                        .zip(rhs_params)
+                        .all(|(lhs, rhs)| lhs.equivalent(rhs))
+                    && lhs_returns.equivalent(rhs_returns)
+            }
+            (Type::Void, Type::Void) | (Type::Nil, Type::Nil) => true,
+            _ => false,
+        }
+    }
+
+    /// Whether a value of type `found` can be stored where `self` is expected.
+    /// An array without a length accepts an array of any length
+    pub fn accepts(&self, found: &Type) -> bool {
+        match (self, found) {
+            (
+                Type::Array {
+                    ty: expected,
+                    length: None,
+                },
+                Type::Array { ty: found, .. },
+            ) => expected.accepts(found),
+            (
+                Type::Array {
+                    ty: expected,
+                    length: Some(expected_length),
+                },
+                Type::Array {
+                    ty: found,
+                    length: Some(found_length),
+                },
+            ) => expected_length == found_length && expected.accepts(found),
+            _ => self.equivalent(found),
+        }
+    }
+}
+
+impl From<a::BinaryOp> for BinaryOp {
+    fn from(op: a::BinaryOp) -> Self {
+        match op {
+            a::BinaryOp::Plus => BinaryOp::Plus,
+            a::BinaryOp::Minus => BinaryOp::Minus,
+            a::BinaryOp::Slash => BinaryOp::Slash,
+            a::BinaryOp::Star => BinaryOp::Star,
+            a::BinaryOp::BangEqual => BinaryOp::BangEqual,
+            a::BinaryOp::EqualEqual => BinaryOp::EqualEqual,
+            a::BinaryOp::Greater => BinaryOp::Greater,
+            a::BinaryOp::GreaterEqual => BinaryOp::GreaterEqual,
+            a::BinaryOp::Less => BinaryOp::Less,
+            a::BinaryOp::LessEqual => BinaryOp::LessEqual,
+            a::BinaryOp::Assignment => BinaryOp::Assignment,
+        }
+    }
+}
+
+impl From<a::LogicalOp> for LogicalOp {
+    fn from(op: a::LogicalOp) -> Self {
+        match op {
+            a::LogicalOp::And => LogicalOp::And,
+            a::LogicalOp::Or => LogicalOp::Or,
+        }
+    }
+}
+
+impl From<a::UnaryOp> for UnaryOp {
+    fn from(op: a::UnaryOp) -> Self {
+        match op {
+            a::UnaryOp::Bang => UnaryOp::Bang,
+            a::UnaryOp::Plus => UnaryOp::Plus,
+            a::UnaryOp::Minus => UnaryOp::Minus,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use ast::prelude::{Position, Span, Spanned, SymbolDB};
+
+    use super::Type;
+
+    fn span(column: u32) -> Span {
+        Span::new(
+            Position::new(1, column, column as usize),
+            Position::new(1, column + 1, column as usize + 1),
+        )
+    }
+
+    #[test]
+    fn it_compares_types_ignoring_spans() {
+        let mut symbols = SymbolDB::default();
+        let number = symbols.intern("number");
+        let boolean = symbols.intern("boolean");
+
+        // fn(number) -> boolean
+        let function = |at: u32| Type::Function {
+            params: vec![Spanned::new(
+                Type::Named(Spanned::new(number, span(at))),
+                span(at),
+            )],
+            returns: Box::new(Spanned::new(
+                Type::Named(Spanned::new(boolean, span(at + 1))),
+                span(at + 1),
+            )),
+        };
+
+        assert!(function(1).equivalent(&function(10)));
+        assert!(Type::Named(Spanned::new(number, span(1)))
+            .equivalent(&Type::Named(Spanned::new(number, span(5)))));
+    }
+
+    #[test]
+    fn it_distinguishes_different_types() {
+        let mut symbols = SymbolDB::default();
+        let number = symbols.intern("number");
+        let boolean = symbols.intern("boolean");
+
+        let named = |symbol| Spanned::new(Type::Named(Spanned::new(symbol, span(1))), span(1));
+        let array = |length| Type::Array {
+            ty: Box::new(named(number)),
+            length,
+        };
+
+        assert!(!named(number).equivalent(&named(boolean)));
+        assert!(!array(Some(2)).equivalent(&array(Some(3))));
+        assert!(!Type::Function {
+            params: vec![named(number)],
+            returns: Box::new(named(boolean)),
+        }
+        .equivalent(&Type::Function {
+            params: vec![named(number), named(number)],
+            returns: Box::new(named(boolean)),
+        }));
+        assert!(!Type::Function {
+            params: vec![named(number)],
+            returns: Box::new(named(boolean)),
+        }
+        .equivalent(&Type::Function {
+            params: vec![named(number)],
+            returns: Box::new(named(number)),
+        }));
+    }
+}
@@ -0,0 +1,650 @@
+use crate::ast::resolved as r;
+use ::ast::prelude::{Literal, LiteralDB, Position, Span, Spanned, SymbolDB, SymbolId};
+use errors::Reporter;
+use vm::{chunk::Chunk, op::Op, Allocator, FunctionObject, ObjectPtr, Table, Value};
+
+/// The bytecode for a program, ready to be handed to the vm
+pub struct CodegenResult<'a> {
+    pub table: Table,
+    pub allocator: Allocator,
+    /// The top level script, it defines the consts and functions and then calls `main`
+    pub function: ObjectPtr<FunctionObject<'a>>,
+}
+
+/// A local variable and the depth of the block it was declared in
+#[derive(Debug, Clone, Copy)]
+struct Local {
+    name: SymbolId,
+    depth: usize,
+}
+
+/// The function whose chunk is being written
+struct FunctionState<'a> {
+    function: ObjectPtr<FunctionObject<'a>>,
+    /// Slot 0 holds the function being called so the first local is in slot 1
+    locals: Vec<Local>,
+    scope_depth: usize,
+}
+
+/// Lowers a resolved program to the vm's bytecode
+pub struct Codegen<'a> {
+    symbols: &'a SymbolDB,
+    /// The text of the program's string literals
+    literals: &'a LiteralDB,
+    reporter: Reporter,
+    table: Table,
+    allocator: Allocator,
+    current: FunctionState<'a>,
+    /// The node being lowered, its line is recorded with every byte written
+    span: Span,
+}
+
+impl<'a> Codegen<'a> {
+    pub fn new(symbols: &'a SymbolDB, literals: &'a LiteralDB) -> Self {
+        let mut allocator = Allocator::new();
+        let function = allocator.alloc(FunctionObject::new(None));
+
+        Self {
+            symbols,
+            literals,
+            reporter: Reporter::new(),
+            table: Table::new(),
+            allocator,
+            current: FunctionState {
+                function,
+                locals: Vec::new(),
+                scope_depth: 0,
+            },
+            span: Span::new(Position::new(1, 1, 0), Position::new(1, 1, 0)),
+        }
+    }
+
+    /// Generates the script for the program, returning the errors instead if a part of it can't be lowered yet
+    pub fn generate(mut self, program: &r::Program) -> Result<CodegenResult<'a>, Reporter> {
+        for const_def in &program.consts {
+            self.span = const_def.span();
+
+            self.expr(&const_def.initializer);
+
+            let name = self.identifier_constant(*const_def.name.value());
+            self.emit_bytes(Op::DEFINE_GLOBAL, name);
+        }
+
+        for function in &program.functions {
+            let object = self.function(function);
+
+            self.span = function.span();
+
+            let constant = self.make_constant(Value::object(object.into()));
+            self.emit_bytes(Op::CLOSURE, constant);
+
+            let name = self.identifier_constant(*function.name.value());
+            self.emit_bytes(Op::DEFINE_GLOBAL, name);
+        }
+
+        let main = program
+            .functions
+            .iter()
+            .find(|function| self.symbols.lookup(function.name.value()) == "main");
+
+        match main {
+            Some(main) => {
+                let main = self.identifier_constant(*main.name.value());
+
+                self.emit_bytes(Op::GET_GLOBAL, main);
+                self.emit_bytes(Op::CALL, 0);
+                self.emit(Op::POP);
+            }
+            None => self
+                .reporter
+                .error("The program has no `main` function", self.span),
+        }
+
+        self.emit_return();
+
+        if self.reporter.has_error() {
+            return Err(self.reporter);
+        }
+
+        Ok(CodegenResult {
+            table: self.table,
+            allocator: self.allocator,
+            function: self.current.function,
+        })
+    }
+
+    fn function(&mut self, function: &Spanned<r::Function>) -> ObjectPtr<FunctionObject<'a>> {
+        let name = self.symbols.lookup(function.name.value());
+
+        let name = self.allocator.alloc_string(name, &mut self.table);
+
+        let mut object = self.allocator.alloc(FunctionObject::new(Some(name)));
+
+        object.arity = function.params.len();
+
+        let enclosing = std::mem::replace(
+            &mut self.current,
+            FunctionState {
+                function: object,
+                locals: Vec::new(),
+                scope_depth: 1,
+            },
+        );
+
+        for param in &function.params {
+            self.add_local(*param.name.value());
+        }
+
+        self.stmt(&function.body);
+
+        self.span = function.span();
+        self.emit_return();
+
+        std::mem::replace(&mut self.current, enclosing).function
+    }
+
+    fn stmt(&mut self, stmt: &Spanned<r::Statement>) {
+        self.span = stmt.span();
+
+        match stmt.value() {
+            r::Statement::Expression(expr) => {
+                self.expr(expr);
+                self.emit(Op::POP);
+            }
+            r::Statement::While { cond, body } => {
+                let loop_start = self.chunk().code.len();
+
+                self.expr(cond);
+
+                let exit_jump = self.emit_jump(Op::JUMP_IF_FALSE);
+                self.emit(Op::POP);
+
+                self.stmt(body);
+                self.emit_loop(loop_start);
+
+                self.patch_jump(exit_jump);
+                self.emit(Op::POP);
+            }
+            r::Statement::If { cond, then, else_ } => {
+                self.expr(cond);
+
+                let then_jump = self.emit_jump(Op::JUMP_IF_FALSE);
+                self.emit(Op::POP);
+
+                self.stmt(then);
+
+                let else_jump = self.emit_jump(Op::JUMP);
+
+                self.patch_jump(then_jump);
+                self.emit(Op::POP);
+
+                if let Some(else_) = else_ {
+                    self.stmt(else_);
+                }
+
+                self.patch_jump(else_jump);
+            }
+            r::Statement::Block(stmts) => {
+                self.current.scope_depth += 1;
+
+                for stmt in stmts {
+                    self.stmt(stmt);
+                }
+
+                self.end_scope();
+            }
+            r::Statement::Return(expr) => {
+                match expr {
+                    Some(expr) => self.expr(expr),
+                    None => self.emit(Op::NIL),
+                }
+
+                self.emit(Op::RETURN);
+            }
+            r::Statement::Break | r::Statement::Continue => self
+                .reporter
+                .error("`break` and `continue` can't be compiled yet", stmt.span()),
+            r::Statement::Let {
+                identifier, init, ..
+            } => {
+                match init {
+                    Some(init) => self.expr(init),
+                    None => self.emit(Op::NIL),
+                }
+
+                // The value is left on the stack, in the local's slot
+                self.add_local(*identifier.value());
+            }
+        }
+    }
+
+    fn expr(&mut self, expr: &Spanned<r::Expression>) {
+        match &expr.value().kind {
+            r::ExpressionKind::Literal(literal) => self.literal(*literal),
+            r::ExpressionKind::Ternary { cond, lhs, rhs } => {
+                self.expr(cond);
+
+                let then_jump = self.emit_jump(Op::JUMP_IF_FALSE);
+                self.emit(Op::POP);
+
+                self.expr(lhs);
+
+                let else_jump = self.emit_jump(Op::JUMP);
+
+                self.patch_jump(then_jump);
+                self.emit(Op::POP);
+
+                self.expr(rhs);
+
+                self.patch_jump(else_jump);
+            }
+            r::ExpressionKind::Identifier { name, .. } => {
+                let (op, arg) = self.variable(*name.value(), false);
+                self.emit_bytes(op, arg);
+            }
+            r::ExpressionKind::Binary { op, lhs, rhs }
+                if *op.value() == r::BinaryOp::Assignment =>
+            {
+                match &lhs.value().kind {
+                    r::ExpressionKind::Identifier { name, .. } => {
+                        self.expr(rhs);
+
+                        let (op, arg) = self.variable(*name.value(), true);
+                        self.emit_bytes(op, arg);
+                    }
+                    r::ExpressionKind::Error => self.emit(Op::TRAP),
+                    _ => self.reporter.error("Invalid assignment target", lhs.span()),
+                }
+            }
+            r::ExpressionKind::Binary { op, lhs, rhs } => {
+                self.expr(lhs);
+                self.expr(rhs);
+
+                match op.value() {
+                    r::BinaryOp::Plus => self.emit(Op::ADD),
+                    r::BinaryOp::Minus => self.emit(Op::SUBTRACT),
+                    r::BinaryOp::Slash => self.emit(Op::DIVIDE),
+                    r::BinaryOp::Star => self.emit(Op::MULTIPLY),
+                    r::BinaryOp::BangEqual => {
+                        self.emit(Op::EQUAL);
+                        self.emit(Op::NOT);
+                    }
+                    r::BinaryOp::EqualEqual => self.emit(Op::EQUAL),
+                    r::BinaryOp::Greater => self.emit(Op::GREATER),
+                    r::BinaryOp::GreaterEqual => {
+                        self.emit(Op::LESS);
+                        self.emit(Op::NOT);
+                    }
+                    r::BinaryOp::Less => self.emit(Op::LESS),
+                    r::BinaryOp::LessEqual => {
+                        self.emit(Op::GREATER);
+                        self.emit(Op::NOT);
+                    }
+                    r::BinaryOp::Assignment => unreachable!(),
+                }
+            }
+            r::ExpressionKind::Logical { op, lhs, rhs } => {
+                self.expr(lhs);
+
+                let end_jump = match op.value() {
+                    r::LogicalOp::And => self.emit_jump(Op::JUMP_IF_FALSE),
+                    r::LogicalOp::Or => {
+                        let else_jump = self.emit_jump(Op::JUMP_IF_FALSE);
+                        let end_jump = self.emit_jump(Op::JUMP);
+
+                        self.patch_jump(else_jump);
+
+                        end_jump
+                    }
+                };
+
+                self.emit(Op::POP);
+                self.expr(rhs);
+
+                self.patch_jump(end_jump);
+            }
+            r::ExpressionKind::Call { callee, args } => {
+                self.expr(callee);
+
+                for arg in args {
+                    self.expr(arg);
+                }
+
+                if args.len() > u8::MAX as usize {
+                    self.reporter
+                        .error("Can't have more than 255 arguments.", expr.span());
+                }
+
+                self.emit_bytes(Op::CALL, args.len() as u8);
+            }
+            r::ExpressionKind::Unary { op, rhs } => {
+                self.expr(rhs);
+
+                match op.value() {
+                    r::UnaryOp::Bang => self.emit(Op::NOT),
+                    r::UnaryOp::Minus => self.emit(Op::NEGATE),
+                    r::UnaryOp::Plus => {}
+                }
+            }
+            r::ExpressionKind::Closure { .. } => self
+                .reporter
+                .error("Closures can't be compiled yet", expr.span()),
+            // The resolver has already reported whatever this was, running it is a runtime error
+            r::ExpressionKind::Error => self.emit(Op::TRAP),
+        }
+    }
+
+    fn literal(&mut self, literal: Literal) {
+        match literal {
+            Literal::Number(value) => self.emit_constant(Value::number(value)),
+            Literal::String(id) => {
+                let text = self.literals.lookup(&id);
+
+                let string = self.allocator.alloc_string(text, &mut self.table);
+
+                self.emit_constant(Value::object(string.into()))
+            }
+            Literal::Bool(true) => self.emit(Op::TRUE),
+            Literal::Bool(false) => self.emit(Op::FALSE),
+            Literal::Nil => self.emit(Op::NIL),
+        }
+    }
+
+    /// The instruction and operand that reads, or with `assign` writes, the named variable
+    fn variable(&mut self, name: SymbolId, assign: bool) -> (Op, u8) {
+        match self.resolve_local(name) {
+            Some(slot) if assign => (Op::SET_LOCAL, slot),
+            Some(slot) => (Op::GET_LOCAL, slot),
+            None if assign => (Op::SET_GLOBAL, self.identifier_constant(name)),
+            None => (Op::GET_GLOBAL, self.identifier_constant(name)),
+        }
+    }
+
+    fn resolve_local(&self, name: SymbolId) -> Option<u8> {
+        self.current
+            .locals
+            .iter()
+            .rposition(|local| local.name == name)
+            .map(|index| index as u8 + 1)
+    }
+
+    fn add_local(&mut self, name: SymbolId) {
+        if self.current.locals.len() == u8::MAX as usize {
+            self.reporter
+                .error("Too many local variables in function.", self.span);
+            return;
+        }
+
+        let depth = self.current.scope_depth;
+        self.current.locals.push(Local { name, depth });
+    }
+
+    fn end_scope(&mut self) {
+        self.current.scope_depth -= 1;
+
+        while let Some(local) = self.current.locals.last() {
+            if local.depth <= self.current.scope_depth {
+                break;
+            }
+
+            self.emit(Op::POP);
+            self.current.locals.pop();
+        }
+    }
+
+    fn identifier_constant(&mut self, name: SymbolId) -> u8 {
+        let name = self.symbols.lookup(&name);
+
+        let string = self.allocator.alloc_string(name, &mut self.table);
+
+        self.make_constant(Value::object(string.into()))
+    }
+
+    fn make_constant(&mut self, value: Value) -> u8 {
+        let constant = self.chunk_mut().add_constant(value);
+
+        if constant > u8::MAX as usize {
+            self.reporter
+                .error("Too many constants in one chunk.", self.span);
+            return 0;
+        }
+
+        constant as u8
+    }
+
+    fn emit_constant(&mut self, value: Value) {
+        let constant = self.make_constant(value);
+        self.emit_bytes(Op::CONSTANT, constant);
+    }
+
+    fn emit_return(&mut self) {
+        self.emit(Op::NIL);
+        self.emit(Op::RETURN);
+    }
+
+    fn emit_jump(&mut self, op: Op) -> usize {
+        self.emit(op);
+        self.emit_byte(0xff);
+        self.emit_byte(0xff);
+
+        self.chunk().code.len() - 2
+    }
+
+    fn patch_jump(&mut self, offset: usize) {
+        let jump = self.chunk().code.len() - offset - 2;
+
+        if jump > u16::MAX as usize {
+            self.reporter
+                .error("Too much code to jump over.", self.span);
+        }
+
+        self.chunk_mut().code[offset] = ((jump >> 8) & 0xff) as u8;
+        self.chunk_mut().code[offset + 1] = (jump & 0xff) as u8;
+    }
+
+    fn emit_loop(&mut self, loop_start: usize) {
+        self.emit(Op::LOOP);
+
+        let offset = self.chunk().code.len() - loop_start + 2;
+
+        if offset > u16::MAX as usize {
+            self.reporter.error("Loop body too large.", self.span);
+        }
+
+        self.emit_byte(((offset >> 8) & 0xff) as u8);
+        self.emit_byte((offset & 0xff) as u8);
+    }
+
+    fn emit(&mut self, op: Op) {
+        self.emit_byte(op as u8);
+    }
+
+    fn emit_bytes(&mut self, op: Op, operand: u8) {
+        self.emit(op);
+        self.emit_byte(operand);
+    }
+
+    fn emit_byte(&mut self, byte: u8) {
+        let line = self.span.start.line as usize;
+        self.chunk_mut().write(byte, line);
+    }
+
+    fn chunk(&self) -> &Chunk {
+        &self.current.function.chunk
+    }
+
+    fn chunk_mut(&mut self) -> &mut Chunk {
+        &mut self.current.function.chunk
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use ::ast::prelude::{ItemKind, Spanned};
+    use errors::Level;
+    use syntax::Parser;
+    use vm::{CapturedOutput, VM};
+
+    use crate::{ast::resolved::Binding, Codegen, CodegenResult, Resolver};
+
+    /// Resolves and lowers `src` with the vm's `println` native in scope, handing the bytecode to `f`
+    fn generate<T>(src: &str, f: impl FnOnce(CodegenResult) -> T) -> T {
+        let (ast, mut symbols) = Parser::new(src).parse().unwrap();
+
+        let println = Spanned::new(symbols.intern("println"), ast.functions[0].name.span());
+
+        let mut resolver = Resolver::new(symbols);
+        resolver.declare_item(println, ItemKind::Value, Binding::Function, false);
+
+        let (program, errors) = resolver.resolve_program(&ast);
+        assert!(errors.diagnostics().is_empty());
+
+        let symbols = resolver.into_symbols();
+
+        match Codegen::new(&symbols, &ast.literals).generate(&program) {
+            Ok(result) => f(result),
+            Err(errors) => panic!("{:?}", errors.diagnostics()),
+        }
+    }
+
+    /// Resolves, lowers and runs `src`
+    fn run(src: &str) -> String {
+        generate(
+            src,
+            |CodegenResult {
+                 table,
+                 allocator,
+                 function,
+             }| {
+                let output = CapturedOutput::default();
+
+                let mut vm = VM::new(table, allocator);
+                vm.set_output(Box::new(output.clone()));
+                vm.interpret(function).unwrap();
+
+                output.contents()
+            },
+        )
+    }
+
+    #[test]
+    fn it_runs_a_resolved_program() {
+        assert_eq!(
+            run("
+                const base: number := 40;
+
+                fn answer() -> number {
+                    let a := base;
+                    let b := a + 2 * 3 - 4;
+
+                    return b;
+                }
+
+                fn main() {
+                    let result := answer();
+
+                    if result > 41 {
+                        println(result);
+                    } else {
+                        println(0);
+                    }
+                }"),
+            "42\n"
+        );
+    }
+
+    #[test]
+    fn an_unresolved_name_is_reported_once_and_traps_when_run() {
+        let src = "fn main() { let a := missing + 1; missing := a; }";
+
+        let (ast, symbols) = Parser::new(src).parse().unwrap();
+
+        let mut resolver = Resolver::new(symbols);
+        let (program, errors) = resolver.resolve_program(&ast);
+
+        let reported = errors
+            .diagnostics()
+            .iter()
+            .map(|diagnostic| diagnostic.msg.clone())
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            reported,
+            ["Unknown variable `missing`", "Unknown variable `missing`"]
+        );
+
+        let symbols = resolver.into_symbols();
+
+        let CodegenResult {
+            table,
+            allocator,
+            function,
+        } = match Codegen::new(&symbols, &ast.literals).generate(&program) {
+            Ok(result) => result,
+            Err(errors) => panic!("{:?}", errors.diagnostics()),
+        };
+
+        let mut vm = VM::new(table, allocator);
+
+        assert_eq!(
+            vm.interpret(function).unwrap_err().to_string(),
+            "Runtime error: Reached erroneous code."
+        );
+    }
+
+    #[test]
+    fn a_program_with_a_trait_is_resolved_and_lowered() {
+        let src = "
+            trait Show {
+                fn show(width: number) -> string;
+            }
+
+            fn main() {}";
+
+        let (ast, symbols) = Parser::new(src).parse().unwrap();
+
+        let mut resolver = Resolver::new(symbols);
+        let (program, errors) = resolver.resolve_program(&ast);
+
+        assert!(errors
+            .diagnostics()
+            .iter()
+            .all(|diagnostic| diagnostic.level != Level::Error));
+        assert_eq!(program.traits.len(), 1);
+
+        let symbols = resolver.into_symbols();
+
+        let CodegenResult {
+            table,
+            allocator,
+            function,
+        } = match Codegen::new(&symbols, &ast.literals).generate(&program) {
+            Ok(result) => result,
+            Err(errors) => panic!("{:?}", errors.diagnostics()),
+        };
+
+        VM::new(table, allocator).interpret(function).unwrap();
+    }
+
+    #[test]
+    fn groupings_compile_to_the_same_bytecode_as_their_contents() {
+        let code = |src| generate(src, |result| result.function.chunk.code.clone());
+
+        assert_eq!(
+            code("const a: number := ((1 + 2)); fn main() { println(a); }"),
+            code("const a: number := 1 + 2; fn main() { println(a); }")
+        );
+    }
+
+    #[test]
+    fn it_interpolates_string_variables() {
+        assert_eq!(
+            run(r#"
+                fn main() {
+                    let name := "vision";
+                    println("hi $name!");
+                }"#),
+            "hi vision!\n"
+        );
+    }
+}
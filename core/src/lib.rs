@@ -1,22 +1,95 @@
 use ::ast::prelude::{Program, SymbolDB};
+pub use codegen::{Codegen, CodegenResult};
 pub use resolve::Resolver;
 
-mod ast {
+pub mod ast {
     pub mod resolved;
 }
+mod codegen;
 mod resolve;
 mod scope_map;
-mod visitor;
 
-pub fn construct_ir(src: &str, (ast, symbols): (Program, SymbolDB)) -> Option<()> {
+/// Resolves and type checks the program, returning the resolved program if there were no diagnostics
+pub fn construct_ir(
+    src: &str,
+    (ast, symbols): (Program, SymbolDB),
+) -> Option<(ast::resolved::Program, SymbolDB)> {
     let mut resolver = Resolver::new(symbols);
 
-    let errors = resolver.resolve_program(&ast);
+    let (program, errors) = resolver.resolve_program(&ast);
 
     if errors.has_error() {
         errors.emit(src);
         None
     } else {
-        Some(())
+        Some((program, resolver.into_symbols()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        ast::resolved::{BinaryOp, ExpressionKind, Statement, Type},
+        construct_ir,
+    };
+    use syntax::Parser;
+
+    #[test]
+    fn it_returns_the_resolved_program() {
+        let src = "
+            type n = number;
+
+            fn main() -> n {
+                let a := 10;
+                let b: n := a * 2;
+
+                return a + b;
+            }";
+
+        let (program, symbols) = construct_ir(src, Parser::new(src).parse().unwrap()).unwrap();
+
+        let type_name = |ty: &Type| match ty {
+            Type::Named(name) => symbols.lookup(name.value()).to_string(),
+            ty => format!("{:?}", ty),
+        };
+
+        let main = program.functions[0].value();
+
+        assert_eq!(symbols.lookup(main.name.value()), "main");
+        assert_eq!(type_name(main.returns.value()), "number");
+
+        let body = match main.body.value() {
+            Statement::Block(body) => body,
+            stmt => panic!("expected a block, found {:?}", stmt),
+        };
+
+        assert_eq!(body.len(), 3);
+
+        match body[1].value() {
+            Statement::Let { ty, init, .. } => {
+                assert_eq!(type_name(ty), "number");
+                assert_eq!(type_name(&init.as_ref().unwrap().value().ty), "number");
+            }
+            stmt => panic!("expected a let statement, found {:?}", stmt),
+        }
+
+        match body[2].value() {
+            Statement::Return(Some(expr)) => {
+                assert_eq!(type_name(&expr.value().ty), "number");
+
+                match &expr.value().kind {
+                    ExpressionKind::Binary { op, .. } => assert_eq!(*op.value(), BinaryOp::Plus),
+                    kind => panic!("expected a binary expression, found {:?}", kind),
+                }
+            }
+            stmt => panic!("expected a return, found {:?}", stmt),
+        }
+    }
+
+    #[test]
+    fn it_returns_nothing_when_the_program_has_errors() {
+        let src = "fn main() { return a; }";
+
+        assert!(construct_ir(src, Parser::new(src).parse().unwrap()).is_none());
     }
 }
@@ -1,11 +1,12 @@
 use crate::{
     ast::resolved::{self as r},
     scope_map::StackedMap,
-    visitor::Visitor,
 };
-use ::ast::prelude::{self as a, ItemKind, Span, Spanned, SymbolDB, SymbolId, DEFAULT_TYPES};
+use ::ast::prelude::{
+    self as a, ItemKind, Position, Span, Spanned, SymbolDB, SymbolId, DEFAULT_TYPES,
+};
 use errors::Reporter;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum State {
@@ -15,11 +16,14 @@ pub enum State {
 }
 
 /// Information at a local variable declared in a block
-#[derive(Copy, Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone)]
 pub struct LocalData {
     state: State,
     reads: usize,
     span: Span,
+    /// The type of the value or, for a type alias, the type it stands for
+    ty: r::Type,
+    binding: r::Binding,
 }
 
 pub struct Resolver {
@@ -28,6 +32,12 @@ pub struct Resolver {
     reporter: Reporter,
     symbols: SymbolDB,
     data: StackedMap<(SymbolId, ItemKind), LocalData>,
+    /// The declared return type of the function being resolved
+    return_type: Option<r::Type>,
+    /// Opt-in lint that warns about `while true` loops that can never exit
+    lint_infinite_loops: bool,
+    /// How many loops enclose the statement being resolved within the current function
+    loop_depth: usize,
 }
 
 impl Resolver {
@@ -44,9 +54,22 @@ impl Resolver {
             exported_items: HashSet::new(),
             symbols,
             data: StackedMap::new(),
+            return_type: None,
+            lint_infinite_loops: false,
+            loop_depth: 0,
         }
     }
 
+    /// Hands back the symbols so the names in the resolved program can be looked up
+    pub fn into_symbols(self) -> SymbolDB {
+        self.symbols
+    }
+
+    /// Warn when a `while true` loop has no `break` or `return` that would end it
+    pub fn lint_infinite_loops(&mut self, enabled: bool) {
+        self.lint_infinite_loops = enabled;
+    }
+
     pub fn add_item(
         &mut self,
         item: &Spanned<SymbolId>,
@@ -74,43 +97,94 @@ impl Resolver {
 
     /// The resolver takes the ast, checks that all referenced variables etc are defined and then
     /// it will return a typed syntax tree, the typed syntax tree is the ast tree annotated with all types
-    pub fn resolve_program(&mut self, program: &a::Program) -> Reporter {
+    pub fn resolve_program(&mut self, program: &a::Program) -> (r::Program, Reporter) {
         // We begin a scope so we can report the top level unused items;
         self.begin_scope();
         // We support forward declarations so grab the fowared references so we can use them later
         for type_alias in &program.type_alias {
-            self.declare_item(type_alias.name, ItemKind::Type, false)
+            self.declare_item(type_alias.name, ItemKind::Type, r::Binding::Type, false)
+        }
+
+        for trait_ in &program.traits {
+            self.declare_item(trait_.name, ItemKind::Type, r::Binding::Type, false)
         }
 
         for const_def in &program.consts {
-            self.declare_item(const_def.name, ItemKind::Value, false)
+            self.declare_item(const_def.name, ItemKind::Value, r::Binding::Const, false)
         }
 
         for function in &program.functions {
-            self.declare_item(function.name, ItemKind::Value, false)
+            self.declare_item(function.name, ItemKind::Value, r::Binding::Function, false)
         }
 
-        for type_alias in &program.type_alias {
-            self.visit_type_alias(type_alias);
-            self.define(type_alias.name, ItemKind::Type)
+        let graph = alias_graph(&program.type_alias);
+        let cyclic = self.check_alias_cycles(&program.type_alias, &graph);
+
+        // An alias is defined after the aliases it refers to, so it can use one declared later
+        for type_alias in alias_order(&program.type_alias, &graph) {
+            let ty = self.visit_type_alias(type_alias);
+
+            if cyclic.contains(type_alias.name.value()) {
+                self.define(type_alias.name, ItemKind::Type, r::Type::Error)
+            } else {
+                self.define(type_alias.name, ItemKind::Type, ty)
+            }
+        }
+
+        let mut traits = Vec::with_capacity(program.traits.len());
+
+        for trait_ in &program.traits {
+            let ty = r::Type::Named(trait_.name);
+            self.define(trait_.name, ItemKind::Type, ty);
+
+            traits.push(Spanned::new(self.visit_trait(trait_), trait_.span()));
+        }
+
+        // The signatures are needed before any body is checked so calls to functions declared later type check
+        for function in &program.functions {
+            let signature = self.function_signature(function);
+            self.set_type(function.name, ItemKind::Value, signature)
         }
 
+        let mut consts = Vec::with_capacity(program.consts.len());
+
         for const_def in &program.consts {
-            self.visit_const(const_def);
-            self.define(const_def.name, ItemKind::Value)
+            let resolved = self.visit_const(const_def);
+            self.define(const_def.name, ItemKind::Value, resolved.ty.clone());
+
+            consts.push(Spanned::new(resolved, const_def.span()));
         }
 
+        let mut functions = Vec::with_capacity(program.functions.len());
+
         for function in &program.functions {
-            self.visit_function(function);
-            self.define(function.name, ItemKind::Value)
+            let resolved = self.visit_function(function);
+
+            let signature = self.type_of(function.name, ItemKind::Value);
+            self.define(function.name, ItemKind::Value, signature);
+
+            functions.push(Spanned::new(resolved, function.span()));
         }
 
         self.end_scope();
 
-        self.reporter.clone()
+        (
+            r::Program {
+                functions,
+                consts,
+                traits,
+            },
+            self.reporter.clone(),
+        )
     }
 
-    pub fn declare_item(&mut self, ident: Spanned<SymbolId>, kind: ItemKind, exported: bool) {
+    pub fn declare_item(
+        &mut self,
+        ident: Spanned<SymbolId>,
+        kind: ItemKind,
+        binding: r::Binding,
+        exported: bool,
+    ) {
         if self.data.get(&(*ident, kind)).is_some() {
             let name = self.symbols.lookup(ident.value());
 
@@ -132,11 +206,13 @@ impl Resolver {
                 state: State::Declared,
                 reads: 0,
                 span: ident.span(),
+                ty: r::Type::Error,
+                binding,
             },
         )
     }
 
-    pub fn declare(&mut self, ident: Spanned<SymbolId>, kind: ItemKind) {
+    pub fn declare(&mut self, ident: Spanned<SymbolId>, kind: ItemKind, binding: r::Binding) {
         let key = (*ident, kind);
 
         if self.data.get(&key).is_some() {
@@ -151,17 +227,46 @@ impl Resolver {
                 state: State::Declared,
                 reads: 0,
                 span: ident.span(),
+                ty: r::Type::Error,
+                binding,
             },
         )
     }
 
+    /// Declares a parameter in the function's scope, `declared` holds the parameters before it in the list
+    fn declare_param(
+        &mut self,
+        declared: &mut HashSet<SymbolId>,
+        name: Spanned<SymbolId>,
+        ty: r::Type,
+    ) {
+        // A parameter named `_` is never read so it isn't brought into scope
+        if self.symbols.lookup(name.value()) == "_" {
+            return;
+        }
+
+        if !declared.insert(*name.value()) {
+            let msg = format!(
+                "The parameter `{}` is declared multiple times",
+                self.symbols.lookup(name.value())
+            );
+            self.reporter.error(msg, name.span());
+            return;
+        }
+
+        self.declare(name, ItemKind::Value, r::Binding::Param);
+        self.define(name, ItemKind::Value, ty);
+    }
+
     fn begin_scope(&mut self) {
         self.data.begin_scope();
     }
 
     fn end_scope(&mut self) {
         for ((name, _), state) in self.data.end_scope_iter() {
-            let LocalData { reads, state, span } = state;
+            let LocalData {
+                reads, state, span, ..
+            } = state;
 
             let name = self.symbols.lookup(&name);
 
@@ -172,129 +277,741 @@ impl Resolver {
         }
     }
 
-    fn define(&mut self, name: Spanned<SymbolId>, kind: ItemKind) {
+    fn define(&mut self, name: Spanned<SymbolId>, kind: ItemKind, ty: r::Type) {
+        // Items can be read before they are defined, i.e a call to a function declared further down
+        let (reads, binding) = self
+            .data
+            .get(&(*name.value(), kind))
+            .map_or((0, r::Binding::Local), |data| (data.reads, data.binding));
+
         self.data.update(
             (*name.value(), kind),
             LocalData {
                 state: State::Defined,
-                reads: 0,
+                reads,
                 span: name.span(),
+                ty,
+                binding,
             },
         )
     }
+
+    fn set_type(&mut self, name: Spanned<SymbolId>, kind: ItemKind, ty: r::Type) {
+        if let Some(data) = self.data.get_mut(&(*name.value(), kind)) {
+            data.ty = ty;
+        }
+    }
+
+    /// The type recorded for a name, unknown names are given the error type
+    fn type_of(&self, name: Spanned<SymbolId>, kind: ItemKind) -> r::Type {
+        self.data
+            .get(&(*name.value(), kind))
+            .map_or(r::Type::Error, |data| data.ty.clone())
+    }
+
+    fn function_signature(&mut self, function: &Spanned<a::Function>) -> r::Type {
+        let params = function
+            .params
+            .iter()
+            .map(|param| self.visit_type(&param.ty))
+            .collect();
+
+        let returns = match function.returns.as_ref() {
+            Some(returns) => self.visit_type(returns),
+            None => Spanned::new(r::Type::Void, function.name.span()),
+        };
+
+        r::Type::Function {
+            params,
+            returns: Box::new(returns),
+        }
+    }
+
+    /// Does the statement unconditionally transfer control out of the enclosing block
+    fn diverges(&self, stmt: &Spanned<a::Statement>) -> bool {
+        match stmt.value() {
+            a::Statement::Return(_) | a::Statement::Break | a::Statement::Continue => true,
+            a::Statement::Block(stmts) => stmts.iter().any(|stmt| self.diverges(stmt)),
+            _ => false,
+        }
+    }
+
+    fn named_type(&mut self, name: &str, span: Span) -> r::Type {
+        r::Type::Named(Spanned::new(self.symbols.intern(name), span))
+    }
+
+    fn is_named(&self, ty: &r::Type, name: &str) -> bool {
+        match ty {
+            r::Type::Named(symbol) => self.symbols.lookup(symbol.value()) == name,
+            _ => false,
+        }
+    }
+
+    fn is_numeric(&self, ty: &r::Type) -> bool {
+        self.is_named(ty, "number") || self.is_named(ty, "float")
+    }
+
+    /// Reports a mismatch between the expected and the found type
+    fn expect_type(&self, expected: &r::Type, found: &r::Type, span: Span) {
+        if !expected.accepts(found) {
+            let msg = format!(
+                "Mismatched types: expected `{}`, found `{}`",
+                self.type_name(expected),
+                self.type_name(found)
+            );
+
+            self.reporter.error(msg, span)
+        }
+    }
+
+    /// Renders a type the way it would be written in source
+    fn type_name(&self, ty: &r::Type) -> String {
+        match ty {
+            r::Type::Named(name) => self.symbols.lookup(name.value()).to_string(),
+            r::Type::Array { ty, length } => match length {
+                Some(length) => format!("[{};{}]", self.type_name(ty), length),
+                None => format!("[{}]", self.type_name(ty)),
+            },
+            r::Type::Function { params, returns } => {
+                let params = params
+                    .iter()
+                    .map(|param| self.type_name(param))
+                    .collect::<Vec<_>>()
+                    .join(",");
+
+                match returns.value() {
+                    r::Type::Void => format!("fn({})", params),
+                    returns => format!("fn({}) -> {}", params, self.type_name(returns)),
+                }
+            }
+            r::Type::Void => "void".to_string(),
+            r::Type::Nil => "nil".to_string(),
+            r::Type::Error => "{unknown}".to_string(),
+        }
+    }
 }
 
 impl<'ast> Resolver {
-    fn visit_stmt(&mut self, stmt: &'ast Spanned<a::Statement>) {
-        match stmt.value() {
-            a::Statement::Expression(expr) => self.visit_expr(expr),
+    fn visit_stmt(&mut self, stmt: &'ast Spanned<a::Statement>) -> Spanned<r::Statement> {
+        let span = stmt.span();
+
+        let resolved = match stmt.value() {
+            a::Statement::Expression(expr) => r::Statement::Expression(self.visit_expr(expr)),
             a::Statement::While { cond, body } => {
-                self.visit_expr(cond);
-                self.visit_stmt(body);
-            }
-            a::Statement::If { cond, then, else_ } => {
-                self.visit_expr(cond);
-                self.visit_stmt(then);
+                let cond_expr = self.visit_expr(cond);
 
-                if let Some(else_) = else_ {
-                    self.visit_stmt(else_)
+                self.loop_depth += 1;
+                let body_stmt = self.visit_stmt(body);
+                self.loop_depth -= 1;
+
+                if self.lint_infinite_loops && is_literal_true(cond) && !exits_loop(body) {
+                    self.reporter
+                        .warn("This loop never exits, it has no `break` or `return`", span);
+                }
+
+                r::Statement::While {
+                    cond: cond_expr,
+                    body: Box::new(body_stmt),
                 }
             }
+            a::Statement::If { cond, then, else_ } => r::Statement::If {
+                cond: self.visit_expr(cond),
+                then: Box::new(self.visit_stmt(then)),
+                else_: else_.as_ref().map(|else_| Box::new(self.visit_stmt(else_))),
+            },
             a::Statement::Block(stmts) => {
+                let mut resolved = Vec::with_capacity(stmts.len());
+
+                let mut diverged = false;
+
                 self.begin_scope();
-                for stmt in stmts {
-                    self.visit_stmt(stmt)
+                for (i, stmt) in stmts.iter().enumerate() {
+                    resolved.push(self.visit_stmt(stmt));
+
+                    // Anything after a statement that always leaves the block can never run. It's
+                    // still resolved so mistakes in it are reported, the warning is only given once
+                    if !diverged && self.diverges(stmt) {
+                        diverged = true;
+
+                        if let Some(dead) = stmts.get(i + 1) {
+                            self.reporter.warn("Unreachable code", dead.span());
+                        }
+                    }
                 }
                 self.end_scope();
+
+                r::Statement::Block(resolved)
             }
             a::Statement::Return(expr) => {
-                if let Some(expr) = expr {
-                    self.visit_expr(expr);
+                let expr = expr.as_ref().map(|expr| self.visit_expr(expr));
+
+                match (self.return_type.clone(), &expr) {
+                    (Some(r::Type::Void), Some(expr)) => self.reporter.error(
+                        "Can't return a value from a function without a return type",
+                        expr.span(),
+                    ),
+                    (Some(r::Type::Void | r::Type::Error) | None, None) | (None, Some(_)) => {}
+                    (Some(expected), Some(expr)) => {
+                        self.expect_type(&expected, &expr.value().ty, expr.span())
+                    }
+                    (Some(expected), None) => {
+                        let msg = format!(
+                            "Expected a return value of type `{}`",
+                            self.type_name(&expected)
+                        );
+                        self.reporter.error(msg, span)
+                    }
                 }
+
+                r::Statement::Return(expr)
+            }
+            a::Statement::Break => {
+                if self.loop_depth == 0 {
+                    self.reporter.error("`break` outside of a loop", span);
+                }
+
+                r::Statement::Break
+            }
+            a::Statement::Continue => {
+                if self.loop_depth == 0 {
+                    self.reporter.error("`continue` outside of a loop", span);
+                }
+
+                r::Statement::Continue
             }
-            a::Statement::Break | a::Statement::Continue => {}
             a::Statement::Let {
                 identifier,
                 ty,
                 init,
             } => {
-                self.declare(*identifier, ItemKind::Value);
+                self.declare(*identifier, ItemKind::Value, r::Binding::Local);
 
-                if let Some(ty) = ty {
-                    self.visit_type(ty)
-                }
+                let expected = ty.as_ref().map(|ty| self.visit_type(ty).into_value());
 
-                if let Some(init) = init {
-                    self.visit_expr(init);
+                let init = init.as_ref().map(|init| self.visit_expr(init));
+
+                let ty = match (expected, &init) {
+                    (Some(expected), Some(init)) => {
+                        self.expect_type(&expected, &init.value().ty, init.span());
+                        expected
+                    }
+                    (Some(ty), None) => ty,
+                    (None, Some(init)) => init.value().ty.clone(),
+                    (None, None) => r::Type::Error,
+                };
+
+                self.define(*identifier, ItemKind::Value, ty.clone());
+
+                r::Statement::Let {
+                    identifier: *identifier,
+                    ty,
+                    init,
                 }
-                self.define(*identifier, ItemKind::Value)
             }
-        }
+        };
+
+        Spanned::new(resolved, span)
     }
 
-    fn visit_expr(&mut self, expression: &'ast Spanned<a::Expression>) {
-        match expression.value() {
-            a::Expression::Literal(_) => {}
+    fn visit_expr(&mut self, expression: &'ast Spanned<a::Expression>) -> Spanned<r::Expression> {
+        let span = expression.span();
+
+        let (kind, ty) = match expression.value() {
+            a::Expression::Literal(literal) => {
+                let ty = match literal {
+                    a::Literal::String(_) => self.named_type("string", span),
+                    a::Literal::Number(_) => self.named_type("number", span),
+                    a::Literal::Bool(_) => self.named_type("boolean", span),
+                    a::Literal::Nil => r::Type::Nil,
+                };
+
+                (r::ExpressionKind::Literal(*literal), ty)
+            }
             a::Expression::Ternary { cond, lhs, rhs } => {
-                self.visit_expr(cond);
-                self.visit_expr(lhs);
-                self.visit_expr(rhs)
+                let cond = self.visit_expr(cond);
+                let lhs = self.visit_expr(lhs);
+                let rhs = self.visit_expr(rhs);
+
+                self.expect_type(&lhs.value().ty, &rhs.value().ty, rhs.span());
+
+                let ty = lhs.value().ty.clone();
+
+                (
+                    r::ExpressionKind::Ternary {
+                        cond: Box::new(cond),
+                        lhs: Box::new(lhs),
+                        rhs: Box::new(rhs),
+                    },
+                    ty,
+                )
+            }
+            a::Expression::Identifier(name) => {
+                let name = self.visit_name(name, ItemKind::Value);
+
+                match self.data.get(&(*name.value(), ItemKind::Value)) {
+                    Some(data) => (
+                        r::ExpressionKind::Identifier {
+                            name,
+                            binding: data.binding,
+                        },
+                        data.ty.clone(),
+                    ),
+                    // An unknown name has already been reported
+                    None => (r::ExpressionKind::Error, r::Type::Error),
+                }
+            }
+            a::Expression::Binary { op, lhs, rhs } => {
+                let lhs = self.visit_expr(lhs);
+                let rhs = self.visit_expr(rhs);
+
+                let ty =
+                    self.visit_binary(op, lhs.value().ty.clone(), rhs.value().ty.clone(), span);
+
+                (
+                    r::ExpressionKind::Binary {
+                        op: Spanned::new((*op.value()).into(), op.span()),
+                        lhs: Box::new(lhs),
+                        rhs: Box::new(rhs),
+                    },
+                    ty,
+                )
+            }
+            a::Expression::Logical { op, lhs, rhs } => {
+                let lhs = self.visit_expr(lhs);
+                let rhs = self.visit_expr(rhs);
+
+                (
+                    r::ExpressionKind::Logical {
+                        op: Spanned::new((*op.value()).into(), op.span()),
+                        lhs: Box::new(lhs),
+                        rhs: Box::new(rhs),
+                    },
+                    self.named_type("boolean", span),
+                )
+            }
+            // Parentheses only exist to shape the tree, so they're dropped and just the span
+            // of the grouping is kept
+            a::Expression::Grouping(expr) => {
+                let expr = self.visit_expr(expr).into_value();
+
+                (expr.kind, expr.ty)
+            }
+            a::Expression::Call { callee, args } => self.visit_call(callee, args, span),
+            a::Expression::Unary { op, rhs } => {
+                let rhs = self.visit_expr(rhs);
+                let rhs_ty = &rhs.value().ty;
+
+                let ty = match op.value() {
+                    a::UnaryOp::Bang => self.named_type("boolean", span),
+                    a::UnaryOp::Plus | a::UnaryOp::Minus => {
+                        if !self.is_numeric(rhs_ty) && !matches!(rhs_ty, r::Type::Error) {
+                            let msg = format!(
+                                "Unary operator `{}` cannot be applied to `{}`",
+                                op,
+                                self.type_name(rhs_ty)
+                            );
+                            self.reporter.error(msg, span);
+
+                            r::Type::Error
+                        } else {
+                            rhs_ty.clone()
+                        }
+                    }
+                };
+
+                (
+                    r::ExpressionKind::Unary {
+                        op: Spanned::new((*op.value()).into(), op.span()),
+                        rhs: Box::new(rhs),
+                    },
+                    ty,
+                )
             }
-            a::Expression::Identifier(name) => self.visit_name(name, ItemKind::Value),
-            a::Expression::Binary { lhs, rhs, .. } => {
-                self.visit_expr(lhs);
-                self.visit_expr(rhs)
+            a::Expression::Closure { params, body } => self.visit_closure(params, body),
+            a::Expression::Error => (r::ExpressionKind::Error, r::Type::Error),
+        };
+
+        Spanned::new(r::Expression { kind, ty }, span)
+    }
+
+    /// The closure body is resolved in a new scope inside the current one so it can use the variables around it
+    fn visit_closure(
+        &mut self,
+        params: &'ast [Spanned<a::FunctionParam>],
+        body: &'ast Spanned<a::Statement>,
+    ) -> (r::ExpressionKind, r::Type) {
+        self.begin_scope();
+
+        let mut resolved_params = Vec::with_capacity(params.len());
+        let mut param_types = Vec::with_capacity(params.len());
+        let mut declared = HashSet::new();
+
+        for param in params {
+            let ty = self.visit_type(&param.ty);
+
+            self.declare_param(&mut declared, param.name, ty.value().clone());
+
+            param_types.push(ty.clone());
+            resolved_params.push(Spanned::new(
+                r::FunctionParam {
+                    name: param.name,
+                    ty,
+                },
+                param.span(),
+            ));
+        }
+
+        let return_type = self.return_type.take();
+        let loop_depth = std::mem::replace(&mut self.loop_depth, 0);
+
+        // Only an expression body has a return type we can infer
+        let (body, returns) = match body.value() {
+            a::Statement::Return(Some(expr)) => {
+                let expr = self.visit_expr(expr);
+                let returns = expr.value().ty.clone();
+
+                (
+                    Spanned::new(r::Statement::Return(Some(expr)), body.span()),
+                    returns,
+                )
             }
-            a::Expression::Grouping(expr) => self.visit_expr(expr),
-            a::Expression::Call { callee, args } => {
-                self.visit_expr(callee);
-                for arg in args {
-                    self.visit_expr(arg);
+            _ => (self.visit_stmt(body), r::Type::Error),
+        };
+
+        self.return_type = return_type;
+        self.loop_depth = loop_depth;
+
+        self.end_scope();
+
+        let ty = r::Type::Function {
+            params: param_types,
+            returns: Box::new(Spanned::new(returns, body.span())),
+        };
+
+        (
+            r::ExpressionKind::Closure {
+                params: resolved_params,
+                body: Box::new(body),
+            },
+            ty,
+        )
+    }
+
+    /// Resolves a call by the shape of its callee. Every call is a function call for now, a method
+    /// call through a property access (`obj.method(...)`) is told apart here once classes exist.
+    fn visit_call(
+        &mut self,
+        callee: &'ast Spanned<a::Expression>,
+        args: &'ast [Spanned<a::Expression>],
+        span: Span,
+    ) -> (r::ExpressionKind, r::Type) {
+        self.visit_function_call(callee, args, span)
+    }
+
+    /// A call of a function value, the arguments are checked against the parameters of its type
+    fn visit_function_call(
+        &mut self,
+        callee: &'ast Spanned<a::Expression>,
+        args: &'ast [Spanned<a::Expression>],
+        span: Span,
+    ) -> (r::ExpressionKind, r::Type) {
+        let callee = self.visit_expr(callee);
+
+        let args = args
+            .iter()
+            .map(|arg| self.visit_expr(arg))
+            .collect::<Vec<_>>();
+
+        let ty = match callee.value().ty.clone() {
+            r::Type::Function { params, returns } => {
+                if params.len() != args.len() {
+                    let msg = format!(
+                        "Expected {} arguments but found {}",
+                        params.len(),
+                        args.len()
+                    );
+                    self.reporter.error(msg, span);
+                } else {
+                    for (param, arg) in params.iter().zip(&args) {
+                        self.expect_type(param, &arg.value().ty, arg.span())
+                    }
                 }
+
+                returns.into_value()
+            }
+            r::Type::Error => r::Type::Error,
+            ty => {
+                let msg = format!("Cannot call a value of type `{}`", self.type_name(&ty));
+                self.reporter.error(msg, callee.span());
+                r::Type::Error
+            }
+        };
+
+        (
+            r::ExpressionKind::Call {
+                callee: Box::new(callee),
+                args,
+            },
+            ty,
+        )
+    }
+
+    /// Checks that the operands of a binary operator are compatible and returns the type it produces
+    fn visit_binary(
+        &mut self,
+        op: &Spanned<a::BinaryOp>,
+        lhs: r::Type,
+        rhs: r::Type,
+        span: Span,
+    ) -> r::Type {
+        if matches!(lhs, r::Type::Error) || matches!(rhs, r::Type::Error) {
+            return r::Type::Error;
+        }
+
+        let valid = match op.value() {
+            a::BinaryOp::Plus => {
+                lhs.equivalent(&rhs) && (self.is_numeric(&lhs) || self.is_named(&lhs, "string"))
             }
-            a::Expression::Unary { rhs, .. } => self.visit_expr(rhs),
-            a::Expression::Error => {}
+            a::BinaryOp::Minus | a::BinaryOp::Slash | a::BinaryOp::Star => {
+                lhs.equivalent(&rhs) && self.is_numeric(&lhs)
+            }
+            a::BinaryOp::Greater
+            | a::BinaryOp::GreaterEqual
+            | a::BinaryOp::Less
+            | a::BinaryOp::LessEqual => lhs.equivalent(&rhs) && self.is_numeric(&lhs),
+            a::BinaryOp::EqualEqual | a::BinaryOp::BangEqual | a::BinaryOp::Assignment => {
+                lhs.equivalent(&rhs)
+            }
+        };
+
+        if !valid {
+            let msg = format!(
+                "Binary operator `{}` cannot be applied to `{}` and `{}`",
+                op,
+                self.type_name(&lhs),
+                self.type_name(&rhs)
+            );
+            self.reporter.error(msg, span);
+
+            return r::Type::Error;
+        }
+
+        match op.value() {
+            a::BinaryOp::Plus
+            | a::BinaryOp::Minus
+            | a::BinaryOp::Slash
+            | a::BinaryOp::Star
+            | a::BinaryOp::Assignment => lhs,
+            a::BinaryOp::Greater
+            | a::BinaryOp::GreaterEqual
+            | a::BinaryOp::Less
+            | a::BinaryOp::LessEqual
+            | a::BinaryOp::EqualEqual
+            | a::BinaryOp::BangEqual => self.named_type("boolean", span),
         }
     }
 
-    fn visit_function(&mut self, function: &'ast Spanned<a::Function>) {
+    fn visit_function(&mut self, function: &'ast Spanned<a::Function>) -> r::Function {
         self.begin_scope();
 
-        for param in &function.params {
-            self.visit_function_param(param)
-        }
+        // The parameter and return types were resolved when the signature was built
+        let previous = self.return_type.take();
+
+        // A loop around a function's declaration doesn't let its body break out of that loop
+        let loop_depth = std::mem::replace(&mut self.loop_depth, 0);
+
+        let (param_types, returns) = match self.type_of(function.name, ItemKind::Value) {
+            r::Type::Function { params, returns } => (params, returns.into_value()),
+            _ => (vec![], r::Type::Error),
+        };
+
+        // A function without a return type returns void
+        self.return_type = Some(returns.clone());
+
+        let mut declared = HashSet::new();
+
+        let params = function
+            .params
+            .iter()
+            .enumerate()
+            .map(|(i, param)| {
+                let ty = param_types
+                    .get(i)
+                    .cloned()
+                    .unwrap_or_else(|| Spanned::new(r::Type::Error, param.ty.span()));
+
+                self.declare_param(&mut declared, param.name, ty.value().clone());
+
+                Spanned::new(
+                    r::FunctionParam {
+                        name: param.name,
+                        ty,
+                    },
+                    param.span(),
+                )
+            })
+            .collect();
+
+        let body = self.visit_stmt(&function.body);
 
-        if let Some(returns) = function.returns.as_ref() {
-            self.visit_type(returns);
+        let returns_value = !matches!(self.return_type, None | Some(r::Type::Void));
+
+        if returns_value && !always_returns(&function.body) {
+            let end = function.body.span().end;
+            let closing_brace = Position::new(end.line, end.column - 1, end.absolute - 1);
+
+            self.reporter
+                .error("Missing return", Span::new(closing_brace, end));
         }
 
-        self.visit_stmt(&function.body);
+        self.return_type = previous;
+        self.loop_depth = loop_depth;
 
         self.end_scope();
+
+        let returns_span = function
+            .returns
+            .as_ref()
+            .map_or(function.name.span(), |returns| returns.span());
+
+        r::Function {
+            name: function.name,
+            params,
+            returns: Spanned::new(returns, returns_span),
+            body,
+        }
+    }
+
+    fn visit_const(&mut self, const_: &'ast Spanned<a::Const>) -> r::Const {
+        let expected = const_
+            .ty
+            .as_ref()
+            .map(|ty| self.visit_type(ty).into_value());
+
+        let initializer = self.visit_expr(&const_.initializer);
+
+        let ty = match expected {
+            Some(expected) => {
+                self.expect_type(&expected, &initializer.value().ty, initializer.span());
+                expected
+            }
+            None => initializer.value().ty.clone(),
+        };
+
+        r::Const {
+            name: const_.name,
+            ty,
+            initializer,
+        }
     }
 
-    fn visit_const(&mut self, const_: &'ast Spanned<a::Const>) {
-        if let Some(ref ty) = const_.ty {
-            self.visit_type(ty)
+    fn visit_trait(&mut self, trait_: &'ast Spanned<a::Trait>) -> r::Trait {
+        let mut seen = HashSet::new();
+
+        let methods = trait_
+            .methods
+            .iter()
+            .map(|method| {
+                if !seen.insert(*method.name.value()) {
+                    let msg = format!(
+                        "The method `{}` is defined multiple times",
+                        self.symbols.lookup(method.name.value())
+                    );
+                    self.reporter.error(msg, method.name.span());
+                }
+
+                let params = method
+                    .params
+                    .iter()
+                    .map(|param| self.visit_type(&param.ty))
+                    .collect();
+
+                let returns = match method.returns.as_ref() {
+                    Some(returns) => self.visit_type(returns),
+                    None => Spanned::new(r::Type::Void, method.name.span()),
+                };
+
+                let ty = r::Type::Function {
+                    params,
+                    returns: Box::new(returns),
+                };
+
+                Spanned::new(
+                    r::TraitMethod {
+                        name: method.name,
+                        ty,
+                    },
+                    method.span(),
+                )
+            })
+            .collect();
+
+        r::Trait {
+            name: trait_.name,
+            methods,
         }
-        self.visit_expr(&const_.initializer);
     }
 
-    fn visit_trait(&mut self, trait_: &'ast Spanned<a::Trait>) {
-        unimplemented!();
+    /// Reports every cycle of type aliases that refer to each other and returns the aliases in a cycle
+    fn check_alias_cycles(
+        &mut self,
+        aliases: &[Spanned<a::TypeAlias>],
+        graph: &HashMap<SymbolId, Vec<SymbolId>>,
+    ) -> HashSet<SymbolId> {
+        let mut cyclic = HashSet::new();
+
+        for alias in aliases {
+            let name = *alias.name.value();
+
+            if cyclic.contains(&name) {
+                continue;
+            }
+
+            if let Some(cycle) = find_cycle(graph, name) {
+                let members = cycle
+                    .iter()
+                    .map(|member| format!("`{}`", self.symbols.lookup(member)))
+                    .collect::<Vec<_>>()
+                    .join(" -> ");
+
+                let msg = format!("cyclic type alias `{}`", self.symbols.lookup(&name));
+
+                self.reporter.error_with_note(
+                    msg,
+                    alias.name.span(),
+                    format!("the cycle is {}", members),
+                );
+
+                cyclic.extend(cycle);
+            }
+        }
+
+        cyclic
     }
 
-    fn visit_type_alias(&mut self, type_: &'ast Spanned<a::TypeAlias>) {
-        self.visit_type(&type_.ty);
+    fn visit_type_alias(&mut self, type_: &'ast Spanned<a::TypeAlias>) -> r::Type {
+        self.visit_type(&type_.ty).into_value()
     }
 
     fn visit_type(&mut self, type_: &'ast Spanned<a::Type>) -> Spanned<r::Type> {
         let span = type_.span();
         match type_.value() {
             a::Type::Identifier(name) => {
-                Spanned::new(r::Type::Named(self.visit_name(name, ItemKind::Type)), span)
+                let name = self.visit_name(name, ItemKind::Type);
+
+                // A type alias stands for the type it was defined as
+                match self.data.get(&(*name.value(), ItemKind::Type)) {
+                    Some(alias) => Spanned::new(alias.ty.clone(), span),
+                    None => Spanned::new(r::Type::Named(name), span),
+                }
             }
-            a::Type::Array { ty, .. } => self.visit_type(ty),
+            a::Type::Array { ty, length } => Spanned::new(
+                r::Type::Array {
+                    ty: Box::new(self.visit_type(ty)),
+                    length: *length,
+                },
+                span,
+            ),
             a::Type::Function { params, returns } => {
                 let mut resolved_params = Vec::with_capacity(params.len());
                 for param in params {
@@ -332,12 +1049,34 @@ impl<'ast> Resolver {
         } //check for ident name in local scope
 
         if !self.items.contains(&key) {
-            let msg = format!(
-                "Unknown identifier `{}`",
-                self.symbols.lookup(ident.value())
-            );
+            let name = self.symbols.lookup(ident.value());
 
-            self.reporter.error(msg, ident.span());
+            let (msg, other) = match kind {
+                ItemKind::Value => (format!("Unknown variable `{}`", name), ItemKind::Type),
+                ItemKind::Type => (format!("Unknown type `{}`", name), ItemKind::Value),
+            };
+
+            let other_key = (key.0, other);
+
+            if self.items.contains(&other_key) || self.data.get(&other_key).is_some() {
+                let note = match kind {
+                    ItemKind::Value => format!("`{}` is a type, not a value", name),
+                    ItemKind::Type => format!("`{}` is a value, not a type", name),
+                };
+
+                self.reporter.error_with_note(msg, ident.span(), note);
+
+                return Spanned::new(self.symbols.intern("?"), ident.span());
+            }
+
+            match self.suggest(name, kind) {
+                Some(suggestion) => self.reporter.error_with_help(
+                    msg,
+                    ident.span(),
+                    format!("did you mean `{}`?", suggestion),
+                ),
+                None => self.reporter.error(msg, ident.span()),
+            }
 
             return Spanned::new(self.symbols.intern("?"), ident.span());
         }
@@ -345,35 +1084,272 @@ impl<'ast> Resolver {
         name
     }
 
-    fn visit_function_param(&mut self, param: &'ast Spanned<ast::prelude::FunctionParam>) {
-        self.visit_type(&param.ty);
+    /// The known name of the same kind closest to `name`, if any is within two edits of it
+    fn suggest(&self, name: &str, kind: ItemKind) -> Option<&str> {
+        let items = self.items.iter();
+        let locals = self
+            .data
+            .table
+            .iter()
+            .filter(|(_, scopes)| !scopes.is_empty())
+            .map(|(key, _)| key);
+
+        items
+            .chain(locals)
+            .filter(|(_, item_kind)| *item_kind == kind)
+            .map(|(symbol, _)| symbol)
+            .map(|symbol| self.symbols.lookup(symbol))
+            .filter(|candidate| *candidate != "?")
+            .map(|candidate| (edit_distance(name, candidate), candidate))
+            .filter(|(distance, _)| *distance <= 2)
+            .min()
+            .map(|(_, candidate)| candidate)
     }
 }
 
-#[cfg(test)]
-mod test {
-    use ast::prelude::ItemKind;
-    use errors::Level;
-    use syntax::Parser;
+/// Collects the names of the aliases a type refers to
+/// Maps each type alias to the aliases its definition refers to
+fn alias_graph(aliases: &[Spanned<a::TypeAlias>]) -> HashMap<SymbolId, Vec<SymbolId>> {
+    let names: HashSet<SymbolId> = aliases.iter().map(|alias| *alias.name.value()).collect();
 
-    use crate::Resolver;
+    let mut graph = HashMap::new();
 
-    struct ExpectedDiagnostic {
-        level: Level,
-        msg: &'static str,
+    for alias in aliases {
+        let mut references = Vec::new();
+        alias_references(alias.ty.value(), &names, &mut references);
+        graph.insert(*alias.name.value(), references);
     }
 
-    macro_rules! setup_reporter {
-        ($file:expr) => {{
-            let file = $file;
-
-            let parser = Parser::new(file);
+    graph
+}
 
-            let (program, symbols) = parser.parse().unwrap();
+/// The aliases with every alias after the ones it refers to. Aliases in a cycle come in the order
+/// the walk reaches them
+fn alias_order<'a>(
+    aliases: &'a [Spanned<a::TypeAlias>],
+    graph: &HashMap<SymbolId, Vec<SymbolId>>,
+) -> Vec<&'a Spanned<a::TypeAlias>> {
+    fn visit<'a>(
+        name: SymbolId,
+        by_name: &HashMap<SymbolId, &'a Spanned<a::TypeAlias>>,
+        graph: &HashMap<SymbolId, Vec<SymbolId>>,
+        visited: &mut HashSet<SymbolId>,
+        order: &mut Vec<&'a Spanned<a::TypeAlias>>,
+    ) {
+        if !visited.insert(name) {
+            return;
+        }
 
-            let mut resolver = Resolver::new(symbols);
+        for reference in graph.get(&name).into_iter().flatten() {
+            visit(*reference, by_name, graph, visited, order);
+        }
 
-            let errors = resolver.resolve_program(&program);
+        order.push(by_name[&name]);
+    }
+
+    let by_name = aliases
+        .iter()
+        .map(|alias| (*alias.name.value(), alias))
+        .collect::<HashMap<_, _>>();
+
+    let mut visited = HashSet::new();
+    let mut order = Vec::with_capacity(aliases.len());
+
+    for alias in aliases {
+        visit(
+            *alias.name.value(),
+            &by_name,
+            graph,
+            &mut visited,
+            &mut order,
+        );
+    }
+
+    order
+}
+
+fn alias_references(ty: &a::Type, aliases: &HashSet<SymbolId>, references: &mut Vec<SymbolId>) {
+    match ty {
+        a::Type::Identifier(name) => {
+            if aliases.contains(name.value()) {
+                references.push(*name.value())
+            }
+        }
+        a::Type::Array { ty, .. } => alias_references(ty.value(), aliases, references),
+        a::Type::Function { params, returns } => {
+            for param in params {
+                alias_references(param.value(), aliases, references)
+            }
+
+            if let Some(returns) = returns {
+                alias_references(returns.value(), aliases, references)
+            }
+        }
+        a::Type::Void | a::Type::Error => {}
+    }
+}
+
+/// The path of aliases leading from `start` back to itself, if there is one
+fn find_cycle(graph: &HashMap<SymbolId, Vec<SymbolId>>, start: SymbolId) -> Option<Vec<SymbolId>> {
+    fn walk(
+        graph: &HashMap<SymbolId, Vec<SymbolId>>,
+        start: SymbolId,
+        path: &mut Vec<SymbolId>,
+        visited: &mut HashSet<SymbolId>,
+    ) -> bool {
+        let current = *path.last().unwrap();
+
+        for next in graph.get(&current).into_iter().flatten() {
+            if *next == start {
+                path.push(start);
+                return true;
+            }
+
+            if visited.insert(*next) {
+                path.push(*next);
+
+                if walk(graph, start, path, visited) {
+                    return true;
+                }
+
+                path.pop();
+            }
+        }
+
+        false
+    }
+
+    let mut path = vec![start];
+
+    if walk(graph, start, &mut path, &mut HashSet::new()) {
+        Some(path)
+    } else {
+        None
+    }
+}
+
+/// The levenshtein distance between two strings
+fn edit_distance(lhs: &str, rhs: &str) -> usize {
+    let rhs: Vec<char> = rhs.chars().collect();
+    let mut previous: Vec<usize> = (0..=rhs.len()).collect();
+
+    for (i, l) in lhs.chars().enumerate() {
+        let mut current = vec![i + 1; rhs.len() + 1];
+
+        for (j, r) in rhs.iter().enumerate() {
+            let substitution = previous[j] + if l == *r { 0 } else { 1 };
+
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+
+        previous = current;
+    }
+
+    previous[rhs.len()]
+}
+
+/// Is the expression the literal `true`, ignoring any parentheses around it
+fn is_literal_true(expr: &Spanned<a::Expression>) -> bool {
+    match expr.value() {
+        a::Expression::Literal(a::Literal::Bool(true)) => true,
+        a::Expression::Grouping(expr) => is_literal_true(expr),
+        _ => false,
+    }
+}
+
+/// Does any path through the loop body leave the loop
+fn exits_loop(stmt: &Spanned<a::Statement>) -> bool {
+    match stmt.value() {
+        a::Statement::Break | a::Statement::Return(_) => true,
+        a::Statement::Block(stmts) => stmts.iter().any(exits_loop),
+        a::Statement::If { then, else_, .. } => {
+            exits_loop(then) || else_.as_ref().is_some_and(|else_| exits_loop(else_))
+        }
+        // A `break` in a nested loop only leaves that loop
+        a::Statement::While { body, .. } => returns(body),
+        a::Statement::Continue | a::Statement::Expression(_) | a::Statement::Let { .. } => false,
+    }
+}
+
+/// Does any path through the statement return from the function
+fn returns(stmt: &Spanned<a::Statement>) -> bool {
+    match stmt.value() {
+        a::Statement::Return(_) => true,
+        a::Statement::Block(stmts) => stmts.iter().any(returns),
+        a::Statement::If { then, else_, .. } => {
+            returns(then) || else_.as_ref().is_some_and(|else_| returns(else_))
+        }
+        a::Statement::While { body, .. } => returns(body),
+        a::Statement::Break
+        | a::Statement::Continue
+        | a::Statement::Expression(_)
+        | a::Statement::Let { .. } => false,
+    }
+}
+
+/// Does every path through the statement end in a `return`.
+/// Loops might not run at all so they never count as returning
+fn always_returns(stmt: &Spanned<a::Statement>) -> bool {
+    match stmt.value() {
+        a::Statement::Return(_) => true,
+        a::Statement::Block(stmts) => stmts.iter().any(always_returns),
+        a::Statement::If {
+            then,
+            else_: Some(else_),
+            ..
+        } => always_returns(then) && always_returns(else_),
+        a::Statement::If { else_: None, .. }
+        | a::Statement::While { .. }
+        | a::Statement::Break
+        | a::Statement::Continue
+        | a::Statement::Expression(_)
+        | a::Statement::Let { .. } => false,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use ast::prelude::ItemKind;
+    use errors::Level;
+    use syntax::Parser;
+
+    use crate::{
+        ast::resolved::{BinaryOp, Binding, ExpressionKind, Statement, Type},
+        Resolver,
+    };
+
+    struct ExpectedDiagnostic {
+        level: Level,
+        msg: &'static str,
+    }
+
+    macro_rules! setup_reporter {
+        ($file:expr) => {{
+            let file = $file;
+
+            let parser = Parser::new(file);
+
+            let (program, symbols) = parser.parse().unwrap();
+
+            let mut resolver = Resolver::new(symbols);
+
+            let (_, errors) = resolver.resolve_program(&program);
+
+            (errors, resolver)
+        }};
+
+        ($file:expr, $setup:expr) => {{
+            let file = $file;
+
+            let parser = Parser::new(file);
+
+            let (program, symbols) = parser.parse().unwrap();
+
+            let mut resolver = Resolver::new(symbols);
+
+            $setup(&mut resolver);
+
+            let (_, errors) = resolver.resolve_program(&program);
 
             (errors, resolver)
         }};
@@ -400,7 +1376,7 @@ mod test {
     #[test]
     fn it_works() {
         let (reporter, _) = setup_reporter!(
-            "fn main() {
+            "fn main() -> number {
                 let a := 10;
                 let b := 10;
 
@@ -462,7 +1438,7 @@ mod test {
 
         let expected = [ExpectedDiagnostic {
             level: Level::Error,
-            msg: "Unknown identifier `b`",
+            msg: "Unknown variable `b`",
         }];
 
         let mut found = 0;
@@ -529,4 +1505,931 @@ mod test {
 
         assert!(!reporter.has_error())
     }
+
+    #[test]
+    fn it_errors_on_adding_a_number_and_a_string() {
+        let (reporter, _) = setup_reporter!(
+            "
+                fn main() {
+                    let a := 1 + \"s\";
+                    return a;
+                }"
+        );
+
+        assert_diagnostics!(
+            [ExpectedDiagnostic {
+                level: Level::Error,
+                msg: "Binary operator `+` cannot be applied to `number` and `string`",
+            }],
+            reporter
+        )
+    }
+
+    #[test]
+    fn it_errors_on_interpolating_a_number_into_a_string() {
+        let (reporter, _) = setup_reporter!(
+            "
+                fn main() {
+                    let n := 1;
+                    let a := \"n is $n\";
+                    return a;
+                }"
+        );
+
+        assert_diagnostics!(
+            [ExpectedDiagnostic {
+                level: Level::Error,
+                msg: "Binary operator `+` cannot be applied to `string` and `number`",
+            }],
+            reporter
+        )
+    }
+
+    #[test]
+    fn it_errors_on_a_mismatched_let_annotation() {
+        let (reporter, _) = setup_reporter!(
+            "
+                fn main() {
+                    let x: number := \"s\";
+                    return x;
+                }"
+        );
+
+        assert_diagnostics!(
+            [ExpectedDiagnostic {
+                level: Level::Error,
+                msg: "Mismatched types: expected `number`, found `string`",
+            }],
+            reporter
+        )
+    }
+
+    #[test]
+    fn it_errors_on_the_wrong_number_of_arguments() {
+        let (reporter, _) = setup_reporter!(
+            "
+                fn add(a: number, b: number) -> number {
+                    return 1;
+                }
+
+                fn main() {
+                    return add(1);
+                }"
+        );
+
+        assert_diagnostics!(
+            [ExpectedDiagnostic {
+                level: Level::Error,
+                msg: "Expected 2 arguments but found 1",
+            }],
+            reporter
+        )
+    }
+
+    #[test]
+    fn it_accepts_a_well_typed_program() {
+        let (reporter, _) = setup_reporter!(
+            "
+                type id = number;
+
+                fn main() -> string {
+                    let a: id := one() + 2;
+                    let b: boolean := a > 1;
+                    let c := \"a\" + \"b\";
+
+                    return b == true ? c : \"d\";
+                }
+
+                fn one() -> number {
+                    return 1;
+                }"
+        );
+
+        assert!(!reporter.has_error())
+    }
+
+    #[test]
+    fn it_warns_on_unreachable_code_after_return() {
+        let (reporter, _) = setup_reporter!(
+            "
+                fn main() -> number {
+                    let a := 10;
+                    return a;
+                    let b := 1;
+                }"
+        );
+
+        // The dead `let` is still resolved, so `b` is reported as unused too
+        assert_diagnostics!(
+            [
+                ExpectedDiagnostic {
+                    level: Level::Warn,
+                    msg: "Unreachable code",
+                },
+                ExpectedDiagnostic {
+                    level: Level::Warn,
+                    msg: "Unused variable `b`",
+                }
+            ],
+            reporter
+        );
+
+        assert_eq!(reporter.diagnostics().len(), 2);
+    }
+
+    #[test]
+    fn it_still_resolves_the_code_after_a_return() {
+        let (reporter, _) = setup_reporter!(
+            "
+                fn main() {
+                    let a := 1;
+                    return;
+                    let y := missing;
+                    let z := a;
+                    return;
+                    let w := y + z;
+                }"
+        );
+
+        assert_diagnostics!(
+            [
+                ExpectedDiagnostic {
+                    level: Level::Warn,
+                    msg: "Unreachable code",
+                },
+                ExpectedDiagnostic {
+                    level: Level::Error,
+                    msg: "Unknown variable `missing`",
+                },
+                ExpectedDiagnostic {
+                    level: Level::Warn,
+                    msg: "Unused variable `w`",
+                }
+            ],
+            reporter
+        );
+
+        // `a`, `y` and `z` are read in the dead code, and only the first dead statement is flagged
+        assert_eq!(reporter.diagnostics().len(), 3);
+    }
+
+    #[test]
+    fn it_does_not_warn_after_a_return_in_a_branch() {
+        let (reporter, _) = setup_reporter!(
+            "
+                fn main() -> number {
+                    let a := 10;
+
+                    if a > 1 {
+                        return a;
+                    } else {
+                        let b := a;
+                        return b;
+                    }
+
+                    while a < 1 {
+                        return a;
+                    }
+
+                    return a;
+                }"
+        );
+
+        assert!(!reporter.has_error())
+    }
+
+    #[test]
+    fn it_warns_on_a_while_true_loop_without_a_break() {
+        let (reporter, _) = setup_reporter!(
+            "
+                fn main() {
+                    let a := 10;
+
+                    while (true) {
+                        a := a + 1;
+                    }
+                }",
+            |resolver: &mut Resolver| resolver.lint_infinite_loops(true)
+        );
+
+        assert_diagnostics!(
+            [ExpectedDiagnostic {
+                level: Level::Warn,
+                msg: "This loop never exits, it has no `break` or `return`",
+            }],
+            reporter
+        )
+    }
+
+    #[test]
+    fn it_does_not_warn_on_a_while_true_loop_with_a_break() {
+        let (reporter, _) = setup_reporter!(
+            "
+                fn main() {
+                    let a := 10;
+
+                    while (true) {
+                        if a > 20 {
+                            break;
+                        }
+
+                        a := a + 1;
+                    }
+                }",
+            |resolver: &mut Resolver| resolver.lint_infinite_loops(true)
+        );
+
+        assert!(!reporter.has_error())
+    }
+
+    #[test]
+    fn it_allows_break_and_continue_inside_a_loop() {
+        let (reporter, _) = setup_reporter!(
+            "
+                fn main() {
+                    let a := 10;
+
+                    while a > 1 {
+                        if a > 5 {
+                            continue;
+                        }
+
+                        break;
+                    }
+                }"
+        );
+
+        assert!(!reporter.has_error())
+    }
+
+    #[test]
+    fn it_errors_on_break_and_continue_outside_of_a_loop() {
+        let (reporter, _) = setup_reporter!(
+            "
+                fn main() {
+                    let a := 10;
+
+                    if a > 5 {
+                        continue;
+                    }
+
+                    break;
+                }"
+        );
+
+        assert_diagnostics!(
+            [
+                ExpectedDiagnostic {
+                    level: Level::Error,
+                    msg: "`continue` outside of a loop",
+                },
+                ExpectedDiagnostic {
+                    level: Level::Error,
+                    msg: "`break` outside of a loop",
+                }
+            ],
+            reporter
+        )
+    }
+
+    #[test]
+    fn it_accepts_a_function_that_returns_on_every_path() {
+        let (reporter, _) = setup_reporter!(
+            "
+                fn sign() -> number {
+                    let a := 1;
+
+                    if a > 0 {
+                        return 1;
+                    } else if a < 0 {
+                        return 0 - 1;
+                    } else {
+                        return 0;
+                    }
+                }
+
+                fn main() -> number {
+                    return sign();
+                }"
+        );
+
+        assert!(!reporter.has_error())
+    }
+
+    #[test]
+    fn it_errors_on_a_missing_return() {
+        let (reporter, _) = setup_reporter!(
+            "
+                fn sign() -> number {
+                    let a := 1;
+
+                    if a > 0 {
+                        return 1;
+                    }
+                }
+
+                fn main() {
+                    return sign();
+                }"
+        );
+
+        assert_diagnostics!(
+            [ExpectedDiagnostic {
+                level: Level::Error,
+                msg: "Missing return",
+            }],
+            reporter
+        )
+    }
+
+    #[test]
+    fn it_resolves_captured_variables_in_a_closure() {
+        let (reporter, _) = setup_reporter!(
+            "
+                fn main() -> number {
+                    let a := 1;
+                    let f := |x: number| x + a;
+                    let g: number := f(2);
+
+                    return g;
+                }"
+        );
+
+        assert!(!reporter.has_error())
+    }
+
+    #[test]
+    fn it_errors_on_break_inside_a_closure_in_a_loop() {
+        let (reporter, _) = setup_reporter!(
+            "
+                fn main() {
+                    let a := 1;
+
+                    while a > 0 {
+                        let f := |x: number| {
+                            break;
+                        };
+
+                        return f(a);
+                    }
+                }"
+        );
+
+        assert_diagnostics!(
+            [ExpectedDiagnostic {
+                level: Level::Error,
+                msg: "`break` outside of a loop",
+            }],
+            reporter
+        )
+    }
+
+    #[test]
+    fn it_resolves_the_operand_types_of_a_binary_expression() {
+        let src = "
+            const limit: number := 10;
+
+            fn main() -> number {
+                let a := 2;
+
+                return limit * a;
+            }";
+
+        let (program, symbols) = Parser::new(src).parse().unwrap();
+        let mut resolver = Resolver::new(symbols);
+
+        let (program, _) = resolver.resolve_program(&program);
+        let symbols = resolver.into_symbols();
+
+        let type_name = |ty: &Type| match ty {
+            Type::Named(name) => symbols.lookup(name.value()).to_string(),
+            ty => format!("{:?}", ty),
+        };
+
+        let body = match program.functions[0].body.value() {
+            Statement::Block(body) => body,
+            stmt => panic!("expected a block, found {:?}", stmt),
+        };
+
+        let (op, lhs, rhs) = match body[1].value() {
+            Statement::Return(Some(expr)) => match &expr.value().kind {
+                ExpressionKind::Binary { op, lhs, rhs } => (*op.value(), lhs.value(), rhs.value()),
+                expr => panic!("expected a binary expression, found {:?}", expr),
+            },
+            stmt => panic!("expected a return, found {:?}", stmt),
+        };
+
+        assert_eq!(op, BinaryOp::Star);
+
+        assert_eq!(type_name(&lhs.ty), "number");
+        assert!(matches!(
+            lhs.kind,
+            ExpressionKind::Identifier {
+                binding: Binding::Const,
+                ..
+            }
+        ));
+
+        assert_eq!(type_name(&rhs.ty), "number");
+        assert!(matches!(
+            rhs.kind,
+            ExpressionKind::Identifier {
+                binding: Binding::Local,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn it_resolves_the_function_named_by_a_call() {
+        let src = "
+            fn add(a: number, b: number) -> number {
+                return a + b;
+            }
+
+            fn main() -> number {
+                return add(1, 2);
+            }";
+
+        let (program, symbols) = Parser::new(src).parse().unwrap();
+        let mut resolver = Resolver::new(symbols);
+
+        let (program, errors) = resolver.resolve_program(&program);
+        assert!(!errors.has_error());
+
+        let symbols = resolver.into_symbols();
+
+        let body = match program.functions[1].body.value() {
+            Statement::Block(body) => body,
+            stmt => panic!("expected a block, found {:?}", stmt),
+        };
+
+        let call = match body[0].value() {
+            Statement::Return(Some(expr)) => expr.value(),
+            stmt => panic!("expected a return, found {:?}", stmt),
+        };
+
+        let (callee, args) = match &call.kind {
+            ExpressionKind::Call { callee, args } => (callee.value(), args),
+            expr => panic!("expected a call, found {:?}", expr),
+        };
+
+        match &callee.kind {
+            ExpressionKind::Identifier { name, binding } => {
+                assert_eq!(symbols.lookup(name.value()), "add");
+                assert_eq!(*binding, Binding::Function);
+            }
+            expr => panic!("expected the name of a function, found {:?}", expr),
+        }
+
+        assert_eq!(args.len(), 2);
+        assert!(matches!(&call.ty, Type::Named(name) if symbols.lookup(name.value()) == "number"));
+    }
+
+    #[test]
+    fn it_resolves_a_trait() {
+        let (reporter, _) = setup_reporter!(
+            "
+                trait Show {
+                    fn show() -> string;
+                    fn pad(width: number) -> string;
+                }
+
+                fn main() {}"
+        );
+
+        assert!(reporter
+            .diagnostics()
+            .iter()
+            .all(|diagnostic| diagnostic.level != Level::Error));
+    }
+
+    #[test]
+    fn it_errors_on_a_duplicate_trait_method() {
+        let (reporter, _) = setup_reporter!(
+            "
+                trait Show {
+                    fn show() -> string;
+                    fn show(width: number) -> string;
+                }
+
+                fn main() {}"
+        );
+
+        assert_diagnostics!(
+            [ExpectedDiagnostic {
+                level: Level::Error,
+                msg: "The method `show` is defined multiple times",
+            }],
+            reporter
+        )
+    }
+
+    #[test]
+    fn it_errors_on_a_duplicate_parameter() {
+        let src = "fn add(x: number, x: number) -> number { return x; } fn main() {}";
+
+        let (reporter, _) = setup_reporter!(src);
+
+        let diagnostics = reporter.diagnostics();
+
+        let duplicate = diagnostics
+            .iter()
+            .find(|diagnostic| diagnostic.msg == "The parameter `x` is declared multiple times")
+            .expect("the duplicate should be reported");
+
+        assert_eq!(duplicate.level, Level::Error);
+        assert_eq!(duplicate.span().start.absolute, src.rfind("x:").unwrap());
+    }
+
+    #[test]
+    fn it_warns_on_an_unused_parameter() {
+        let (reporter, _) = setup_reporter!(
+            "fn id(x: number, y: number) -> number { return x; } fn main() { id(1, 2); }"
+        );
+
+        assert_diagnostics!(
+            [ExpectedDiagnostic {
+                level: Level::Warn,
+                msg: "Unused variable `y`",
+            }],
+            reporter
+        )
+    }
+
+    #[test]
+    fn it_errors_on_returning_a_value_from_a_void_function() {
+        let (reporter, _) = setup_reporter!(
+            "
+                fn main() {
+                    return 1;
+                }"
+        );
+
+        assert_diagnostics!(
+            [ExpectedDiagnostic {
+                level: Level::Error,
+                msg: "Can't return a value from a function without a return type",
+            }],
+            reporter
+        )
+    }
+
+    #[test]
+    fn it_errors_on_a_bare_return_from_a_function_with_a_return_type() {
+        let (reporter, _) = setup_reporter!(
+            "
+                fn one() -> number {
+                    return;
+                }
+
+                fn main() {
+                    one();
+                }"
+        );
+
+        assert_diagnostics!(
+            [ExpectedDiagnostic {
+                level: Level::Error,
+                msg: "Expected a return value of type `number`",
+            }],
+            reporter
+        )
+    }
+
+    #[test]
+    fn it_errors_on_an_array_length_mismatch() {
+        let (reporter, _) = setup_reporter!(
+            "
+                fn main(values: [number;3]) -> [number;2] {
+                    let pair: [number;2] := values;
+                    return pair;
+                }"
+        );
+
+        assert_diagnostics!(
+            [ExpectedDiagnostic {
+                level: Level::Error,
+                msg: "Mismatched types: expected `[number;2]`, found `[number;3]`",
+            }],
+            reporter
+        )
+    }
+
+    #[test]
+    fn it_accepts_an_array_of_matching_length() {
+        let (reporter, _) = setup_reporter!(
+            "
+                fn main(values: [number;2]) -> [number;2] {
+                    let pair: [number;2] := values;
+                    return pair;
+                }"
+        );
+
+        assert!(!reporter.has_error())
+    }
+
+    #[test]
+    fn it_accepts_an_array_of_any_length_for_an_unsized_array() {
+        let (reporter, _) = setup_reporter!(
+            "
+                fn main(values: [number;3]) -> [number] {
+                    let all: [number] := values;
+                    return all;
+                }"
+        );
+
+        assert!(!reporter.has_error())
+    }
+
+    #[test]
+    fn it_accepts_a_compatible_function_argument() {
+        let (reporter, _) = setup_reporter!(
+            "
+                fn is_zero(n: number) -> boolean {
+                    return n == 0;
+                }
+
+                fn check(f: fn(number) -> boolean) -> boolean {
+                    return f(0);
+                }
+
+                fn main() -> boolean {
+                    return check(is_zero);
+                }"
+        );
+
+        assert!(!reporter.has_error())
+    }
+
+    #[test]
+    fn it_errors_on_an_incompatible_function_argument() {
+        let (reporter, _) = setup_reporter!(
+            "
+                fn double(n: number) -> number {
+                    return n * 2;
+                }
+
+                fn check(f: fn(number) -> boolean) -> boolean {
+                    return f(0);
+                }
+
+                fn main() -> boolean {
+                    return check(double);
+                }"
+        );
+
+        assert_diagnostics!(
+            [ExpectedDiagnostic {
+                level: Level::Error,
+                msg: "Mismatched types: expected `fn(number) -> boolean`, found `fn(number) -> number`",
+            }],
+            reporter
+        )
+    }
+
+    #[test]
+    fn it_infers_the_type_of_a_const_without_an_annotation() {
+        let src = "
+            const X := 10;
+            const Y := \"s\";
+
+            fn main() -> number {
+                return X;
+            }";
+
+        let (program, symbols) = Parser::new(src).parse().unwrap();
+        let mut resolver = Resolver::new(symbols);
+
+        let (program, reporter) = resolver.resolve_program(&program);
+        let symbols = resolver.into_symbols();
+
+        let type_name = |ty: &Type| match ty {
+            Type::Named(name) => symbols.lookup(name.value()).to_string(),
+            ty => format!("{:?}", ty),
+        };
+
+        assert_eq!(type_name(&program.consts[0].ty), "number");
+        assert_eq!(type_name(&program.consts[1].ty), "string");
+
+        assert_diagnostics!(
+            [ExpectedDiagnostic {
+                level: Level::Warn,
+                msg: "Unused variable `Y`",
+            }],
+            reporter
+        )
+    }
+
+    #[test]
+    fn it_gives_a_const_with_an_invalid_initializer_the_error_type() {
+        let src = "
+            const X := unknown;
+
+            fn main() -> number {
+                return X + 1;
+            }";
+
+        let (program, symbols) = Parser::new(src).parse().unwrap();
+        let mut resolver = Resolver::new(symbols);
+
+        let (program, reporter) = resolver.resolve_program(&program);
+
+        assert!(matches!(program.consts[0].ty, Type::Error));
+        assert_eq!(reporter.diagnostics().len(), 1);
+    }
+
+    #[test]
+    fn it_suggests_a_known_name_for_a_typo() {
+        let (reporter, _) = setup_reporter!(
+            "
+                fn main() -> number {
+                    let count := 10;
+
+                    return cout;
+                }"
+        );
+
+        let diagnostics = reporter.diagnostics();
+        let unknown = diagnostics
+            .iter()
+            .find(|diagnostic| diagnostic.msg == "Unknown variable `cout`")
+            .unwrap();
+
+        assert_eq!(
+            unknown.notes,
+            vec![(Level::Help, "did you mean `count`?".to_string())]
+        );
+    }
+
+    #[test]
+    fn it_does_not_suggest_a_distant_name() {
+        let (reporter, _) = setup_reporter!(
+            "
+                fn main() -> number {
+                    let count := 10;
+
+                    return total + count;
+                }"
+        );
+
+        let diagnostics = reporter.diagnostics();
+        let unknown = diagnostics
+            .iter()
+            .find(|diagnostic| diagnostic.msg == "Unknown variable `total`")
+            .unwrap();
+
+        assert!(unknown.notes.is_empty());
+    }
+
+    #[test]
+    fn it_notes_when_a_value_is_used_as_a_type() {
+        let (reporter, _) = setup_reporter!(
+            "
+                fn count() -> number {
+                    return 1;
+                }
+
+                fn main() -> number {
+                    let x: count := 1;
+                    return x + count();
+                }"
+        );
+
+        let diagnostics = reporter.diagnostics();
+        let unknown = diagnostics
+            .iter()
+            .find(|diagnostic| diagnostic.msg == "Unknown type `count`")
+            .unwrap();
+
+        assert_eq!(
+            unknown.notes,
+            vec![(Level::Note, "`count` is a value, not a type".to_string())]
+        );
+    }
+
+    #[test]
+    fn it_notes_when_a_type_is_used_as_a_value() {
+        let (reporter, _) = setup_reporter!(
+            "
+                type Meters = number;
+
+                fn main() -> number {
+                    let x: Meters := 1;
+                    return x + Meters;
+                }"
+        );
+
+        let diagnostics = reporter.diagnostics();
+        let unknown = diagnostics
+            .iter()
+            .find(|diagnostic| diagnostic.msg == "Unknown variable `Meters`")
+            .unwrap();
+
+        assert_eq!(
+            unknown.notes,
+            vec![(Level::Note, "`Meters` is a type, not a value".to_string())]
+        );
+    }
+
+    #[test]
+    fn it_errors_on_a_type_alias_that_refers_to_itself() {
+        let (reporter, _) = setup_reporter!(
+            "
+                type A = A;
+
+                fn main() {}"
+        );
+
+        let diagnostics = reporter.diagnostics();
+        let cyclic = diagnostics
+            .iter()
+            .find(|diagnostic| diagnostic.msg == "cyclic type alias `A`")
+            .unwrap();
+
+        assert_eq!(cyclic.level, Level::Error);
+        assert_eq!(
+            cyclic.notes,
+            vec![(Level::Note, "the cycle is `A` -> `A`".to_string())]
+        );
+    }
+
+    #[test]
+    fn it_errors_on_type_aliases_that_refer_to_each_other() {
+        let (reporter, _) = setup_reporter!(
+            "
+                type A = [B];
+                type B = fn(A);
+
+                fn main() {}"
+        );
+
+        let diagnostics = reporter.diagnostics();
+        let cyclic = diagnostics
+            .iter()
+            .filter(|diagnostic| diagnostic.msg.starts_with("cyclic type alias"))
+            .collect::<Vec<_>>();
+
+        assert_eq!(cyclic.len(), 1);
+        assert_eq!(cyclic[0].msg, "cyclic type alias `A`");
+        assert_eq!(
+            cyclic[0].notes,
+            vec![(Level::Note, "the cycle is `A` -> `B` -> `A`".to_string())]
+        );
+    }
+
+    #[test]
+    fn it_resolves_a_type_alias_that_refers_to_one_declared_later() {
+        let (reporter, _) = setup_reporter!(
+            "
+                type A = B;
+                type B = number;
+
+                fn main() {
+                    let x: A := \"str\";
+                }"
+        );
+
+        let diagnostics = reporter.diagnostics();
+
+        assert!(diagnostics.iter().any(|diagnostic| diagnostic.msg
+            == "Mismatched types: expected `number`, found `string`"
+            && diagnostic.level == Level::Error));
+    }
+
+    #[test]
+    fn it_warns_once_on_a_parameter_that_is_never_read() {
+        let (reporter, _) = setup_reporter!(
+            "
+                fn zero(x: number) -> number {
+                    return 0;
+                }
+
+                fn main() {
+                    zero(1);
+                }"
+        );
+
+        let diagnostics = reporter.diagnostics();
+
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].level, Level::Warn);
+        assert_eq!(diagnostics[0].msg, "Unused variable `x`");
+    }
+
+    #[test]
+    fn it_does_not_warn_on_parameters_named_underscore() {
+        let (reporter, _) = setup_reporter!(
+            "
+                fn first(x: number, _: number, _: number) -> number {
+                    return x;
+                }
+
+                fn main() {
+                    first(1, 2, 3);
+                }"
+        );
+
+        assert!(!reporter.has_error())
+    }
 }
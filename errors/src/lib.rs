@@ -5,21 +5,44 @@ use ast::prelude::Span;
 use std::cell::Ref;
 use std::cell::RefCell;
 use std::fmt::{self, Display};
+use std::io::{self, Write};
 use std::iter::repeat;
 use std::rc::Rc;
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 pub struct Diagnostic {
     pub msg: String,
     pub level: Level,
+    /// Follow up notes and help that are rendered under the source snippet
+    pub notes: Vec<(Level, String)>,
     span: Span,
 }
 
+impl Diagnostic {
+    pub fn span(&self) -> Span {
+        self.span
+    }
+}
+
 #[derive(Debug, PartialEq)]
 pub enum Level {
     Warn,
     Error,
     RunTimeError,
+    Note,
+    Help,
+}
+
+impl Level {
+    fn color(&self) -> Color {
+        match *self {
+            Level::Warn => Yellow,
+            Level::Error => Red,
+            Level::RunTimeError => Purple,
+            Level::Note => Blue,
+            Level::Help => Blue,
+        }
+    }
 }
 
 impl Display for Level {
@@ -28,6 +51,8 @@ impl Display for Level {
             Level::Warn => write!(f, "{}", Yellow.bold().paint("warning")),
             Level::Error => write!(f, "{}", Red.bold().paint("error")),
             Level::RunTimeError => write!(f, "{}", Purple.bold().paint("Runtime Error")),
+            Level::Note => write!(f, "{}", Blue.bold().paint("note")),
+            Level::Help => write!(f, "{}", Blue.bold().paint("help")),
         }
     }
 }
@@ -55,6 +80,25 @@ impl Reporter {
             msg: msg.into(),
             span,
             level: Level::Error,
+            notes: Vec::new(),
+        })
+    }
+
+    pub fn error_with_note<T: Into<String>, N: Into<String>>(&self, msg: T, span: Span, note: N) {
+        self.diagnostics.borrow_mut().push(Diagnostic {
+            msg: msg.into(),
+            span,
+            level: Level::Error,
+            notes: vec![(Level::Note, note.into())],
+        })
+    }
+
+    pub fn error_with_help<T: Into<String>, H: Into<String>>(&self, msg: T, span: Span, help: H) {
+        self.diagnostics.borrow_mut().push(Diagnostic {
+            msg: msg.into(),
+            span,
+            level: Level::Error,
+            notes: vec![(Level::Help, help.into())],
         })
     }
 
@@ -63,6 +107,7 @@ impl Reporter {
             msg: msg.into(),
             span,
             level: Level::RunTimeError,
+            notes: Vec::new(),
         })
     }
 
@@ -71,13 +116,33 @@ impl Reporter {
             msg: msg.into(),
             span,
             level: Level::Warn,
+            notes: Vec::new(),
         })
     }
 
     pub fn emit(&self, input: &str) {
-        for diagnostic in self.diagnostics.borrow().iter() {
-            print(input, diagnostic)
+        let _ = self.emit_to(input, &mut io::stdout());
+    }
+
+    /// Writes the diagnostics in the order they appear in the source, an exact duplicate is only written once
+    pub fn emit_to(&self, input: &str, out: &mut dyn Write) -> io::Result<()> {
+        let diagnostics = self.diagnostics.borrow();
+
+        let mut ordered: Vec<&Diagnostic> = Vec::with_capacity(diagnostics.len());
+
+        for diagnostic in diagnostics.iter() {
+            if !ordered.contains(&diagnostic) {
+                ordered.push(diagnostic)
+            }
         }
+
+        ordered.sort_by_key(|diagnostic| diagnostic.span.start);
+
+        for diagnostic in ordered {
+            print(out, input, diagnostic)?
+        }
+
+        Ok(())
     }
 
     pub fn diagnostics(&self) -> Ref<Vec<Diagnostic>> {
@@ -93,10 +158,15 @@ impl Default for Reporter {
     }
 }
 
-fn print(input: &str, d: &Diagnostic) {
+fn print(out: &mut dyn Write, input: &str, d: &Diagnostic) -> io::Result<()> {
     let prefix = Blue.paint("| ");
 
-    println!("{}: {}", d.level, Fixed(252).bold().paint(d.msg.clone()));
+    writeln!(
+        out,
+        "{}: {}",
+        d.level,
+        Fixed(252).bold().paint(d.msg.clone())
+    )?;
 
     let span = d.span;
 
@@ -109,44 +179,32 @@ fn print(input: &str, d: &Diagnostic) {
     for (idx, line) in input.lines().enumerate().skip(start_line as usize) {
         let line = line;
         let line_idx = idx + 1;
-        println!("{:>4} {}{}", line_idx, prefix, line);
+        writeln!(out, "{:>4} {}{}", line_idx, prefix, line)?;
         if line_idx == span.start.line as usize {
             let end = if line_idx == span.end.line as usize {
                 span.end.column as usize
             } else {
                 line.len()
             };
-            let carets = repeat_string("^", end - span.start.column as usize);
+            // A zero-width or inverted span still gets a single caret
+            let width = end.saturating_sub(span.start.column as usize).max(1);
+            let carets = repeat_string("^", width);
 
-            let carets = match d.level {
-                Level::Warn => Yellow.bold().paint(carets),
-                Level::Error => Red.bold().paint(carets),
-                Level::RunTimeError => Purple.bold().paint(carets),
-            };
+            let carets = d.level.color().bold().paint(carets);
 
-            if span.start.column != 0 {
-                let whitespace = repeat_string(" ", span.start.column as usize - 1);
-                println!("     {}{}{}", prefix, whitespace, carets);
-            }
+            let whitespace = repeat_string(" ", (span.start.column as usize).saturating_sub(1));
+            writeln!(out, "     {}{}{}", prefix, whitespace, carets)?;
         } else if line_idx == span.end.line as usize {
             let carets = repeat_string("^", span.end.column as usize);
-            let carets = match d.level {
-                Level::Warn => Yellow.bold().paint(carets),
-                Level::Error => Red.bold().paint(carets),
-                Level::RunTimeError => Purple.bold().paint(carets),
-            };
-            println!("     {}{}", prefix, carets);
+            let carets = d.level.color().bold().paint(carets);
+            writeln!(out, "     {}{}", prefix, carets)?;
         } else if line_idx > span.start.line as usize
             && line_idx < span.end.line as usize
             && !line.is_empty()
         {
             let carets = repeat_string("^", line.len());
-            let carets = match d.level {
-                Level::Warn => Yellow.bold().paint(carets),
-                Level::Error => Red.bold().paint(carets),
-                Level::RunTimeError => Purple.bold().paint(carets),
-            };
-            println!("     {}{}", prefix, carets);
+            let carets = d.level.color().bold().paint(carets);
+            writeln!(out, "     {}{}", prefix, carets)?;
         }
 
         if line_idx >= span.end.line as usize + 3 {
@@ -154,9 +212,107 @@ fn print(input: &str, d: &Diagnostic) {
         }
     }
 
-    println!()
+    for (level, note) in &d.notes {
+        writeln!(out, "     {} {}: {}", Blue.paint("="), level, note)?;
+    }
+
+    writeln!(out)
 }
 
 fn repeat_string(s: &str, count: usize) -> String {
     repeat(s).take(count).collect()
 }
+
+#[cfg(test)]
+mod test {
+    use ast::prelude::{Position, Span};
+
+    use crate::Reporter;
+
+    fn span(line: u32, column: u32) -> Span {
+        Span::new(
+            Position::new(line, column, 0),
+            Position::new(line, column + 1, 0),
+        )
+    }
+
+    fn render(reporter: &Reporter, input: &str) -> String {
+        let mut out = Vec::new();
+
+        reporter.emit_to(input, &mut out).unwrap();
+
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn it_emits_an_exact_duplicate_once() {
+        let reporter = Reporter::new();
+
+        reporter.error("Unknown identifier `a`", span(1, 1));
+        reporter.error("Unknown identifier `a`", span(1, 1));
+
+        let output = render(&reporter, "a;");
+
+        assert_eq!(output.matches("Unknown identifier `a`").count(), 1);
+    }
+
+    #[test]
+    fn it_emits_diagnostics_in_source_order() {
+        let reporter = Reporter::new();
+
+        reporter.error("second", span(2, 1));
+        reporter.warn("third", span(2, 4));
+        reporter.error("first", span(1, 2));
+
+        let output = render(&reporter, "a b\nc d e\n");
+
+        let first = output.find("first").unwrap();
+        let second = output.find("second").unwrap();
+        let third = output.find("third").unwrap();
+
+        assert!(first < second && second < third);
+    }
+
+    #[test]
+    fn it_renders_a_span_starting_at_column_zero() {
+        let reporter = Reporter::new();
+
+        reporter.error(
+            "Unexpected end of input",
+            Span::new(Position::new(1, 0, 0), Position::new(1, 0, 0)),
+        );
+
+        let output = render(&reporter, "a;");
+
+        assert_eq!(output.matches('^').count(), 1);
+    }
+
+    #[test]
+    fn it_renders_an_inverted_span() {
+        let reporter = Reporter::new();
+
+        reporter.error(
+            "Expected an expression",
+            Span::new(Position::new(1, 3, 2), Position::new(1, 1, 0)),
+        );
+
+        let output = render(&reporter, "a b;");
+
+        assert_eq!(output.matches('^').count(), 1);
+    }
+
+    #[test]
+    fn it_renders_a_help_under_the_error() {
+        let reporter = Reporter::new();
+
+        reporter.error_with_help("Unknown identifier `b`", span(1, 1), "did you mean `a`?");
+
+        let output = render(&reporter, "b;");
+
+        let error = output.find("Unknown identifier `b`").unwrap();
+        let help = output.find("did you mean `a`?").unwrap();
+
+        assert!(output.contains("help"));
+        assert!(error < help);
+    }
+}
@@ -1,5 +1,7 @@
 use super::{parser::Precedence, Parser};
-use ast::prelude::{Expression, Literal, Spanned, Statement, Token};
+use ast::prelude::{
+    BinaryOp, Expression, Literal, ParamKind, Span, Spanned, Statement, Token, UnaryOp,
+};
 
 impl<'a> Parser<'a> {
     pub(crate) fn expression_statement(&mut self) -> Spanned<Statement> {
@@ -24,6 +26,16 @@ impl<'a> Parser<'a> {
         let start = op.span();
         let end = rhs.span();
 
+        // A negated number is kept as a single literal
+        if let (UnaryOp::Minus, Expression::Literal(Literal::Number(value))) =
+            (op.value(), rhs.value())
+        {
+            return Spanned::new(
+                Expression::Literal(Literal::Number(-value)),
+                start.merge(end),
+            );
+        }
+
         Spanned::new(
             Expression::Unary {
                 op,
@@ -78,6 +90,25 @@ impl<'a> Parser<'a> {
         )
     }
 
+    pub(crate) fn logical(&mut self, lhs: Spanned<Expression>) -> Spanned<Expression> {
+        let op = self.get_logical_op();
+
+        let rule = self.get_rule(*self.prev.value());
+        let expr = self.parse_with_precedence(rule.precedence.higher());
+
+        let start = lhs.span();
+
+        let end = expr.span();
+        Spanned::new(
+            Expression::Logical {
+                op,
+                lhs: Box::new(lhs),
+                rhs: Box::new(expr),
+            },
+            start.merge(end),
+        )
+    }
+
     pub(crate) fn call(&mut self, lhs: Spanned<Expression>) -> Spanned<Expression> {
         let mut args = Vec::new();
 
@@ -112,25 +143,146 @@ impl<'a> Parser<'a> {
         )
     }
 
+    /// closure -> "|" params "|" ( expression | block )
+    pub(crate) fn closure(&mut self) -> Spanned<Expression> {
+        let start = self.prev.span();
+
+        let params = self.parse_params(ParamKind::Closure);
+
+        self.consume(Token::Bar, "Expected `|` after the closure parameters");
+
+        let body = if self.match_token(Token::LeftBrace) {
+            self.block()
+        } else {
+            let expr = self.expression();
+            let span = expr.span();
+
+            Spanned::new(Statement::Return(Some(expr)), span)
+        };
+
+        let end = body.span();
+
+        Spanned::new(
+            Expression::Closure {
+                params,
+                body: Box::new(body),
+            },
+            start.merge(end),
+        )
+    }
+
     pub(crate) fn grouping(&mut self) -> Spanned<Expression> {
         let expr = self.expression();
 
-        self.consume(Token::RightParen, "Expect ')' after expression.");
-
         let start = expr.span();
-        let end = self.consume_get_span(Token::SemiColon, "Expected ';' after expression.");
+        let end = self.consume_get_span(Token::RightParen, "Expect ')' after expression.");
+
         Spanned::new(Expression::Grouping(Box::new(expr)), start.merge(end))
     }
 
     pub(crate) fn literal(&mut self) -> Spanned<Expression> {
+        let span = self.prev.span();
+        let text = &self.src[span.start.absolute..span.end.absolute];
+
+        if *self.prev.value() == Token::String && text.contains('$') {
+            return self.interpolated_string(span);
+        }
+
         let literal = match *self.prev.value() {
-            Token::Number => Literal::Number,
+            Token::Number => match text.replace('_', "").parse::<f64>() {
+                Ok(value) => Literal::Number(value),
+                Err(_) => return self.error("Invalid number literal."),
+            },
             Token::True => Literal::Bool(true),
             Token::False => Literal::Bool(false),
             Token::Nil => Literal::Nil,
-            Token::String => Literal::String,
+            Token::String => Literal::String(self.literals.intern(&text[1..text.len() - 1])),
             _ => unreachable!(),
         };
         Spanned::new(Expression::Literal(literal), self.prev.span())
     }
+
+    /// `"hello $name!"` is sugar for `"hello " + name + "!"`, so the variable has to be a string.
+    /// A `$` that isn't followed by the start of a name is kept as it is
+    fn interpolated_string(&mut self, span: Span) -> Spanned<Expression> {
+        let src = self.src;
+        let text = &src[span.start.absolute + 1..span.end.absolute - 1];
+
+        let mut pieces = Vec::new();
+        let mut chars = text.char_indices().peekable();
+        let mut position = span.start.shift("\"");
+        // Where the text since the last `$name` starts
+        let mut piece = (0, position);
+
+        while let Some((i, ch)) = chars.next() {
+            let starts_name = matches!(chars.peek(), Some((_, next)) if next.is_ascii_alphabetic() || *next == '_');
+
+            if ch != '$' || !starts_name {
+                position = position.shift(&text[i..i + ch.len_utf8()]);
+                continue;
+            }
+
+            // The first piece is kept even when empty so the `+`s always concatenate strings
+            if pieces.is_empty() || piece.0 < i {
+                pieces.push(self.string_piece(&text[piece.0..i], Span::new(piece.1, position)));
+            }
+
+            let dollar = position;
+            position = position.shift("$");
+
+            let name_start = (i + 1, position);
+            let mut name_end = i + 1;
+
+            while let Some(&(j, next)) = chars.peek() {
+                if !(next.is_ascii_alphanumeric() || next == '_') {
+                    break;
+                }
+
+                position = position.shift(&text[j..j + 1]);
+                name_end = j + 1;
+                chars.next();
+            }
+
+            let name = Spanned::new(
+                self.symbols.intern(&text[name_start.0..name_end]),
+                Span::new(name_start.1, position),
+            );
+
+            pieces.push(Spanned::new(
+                Expression::Identifier(name),
+                Span::new(dollar, position),
+            ));
+
+            piece = (name_end, position);
+        }
+
+        if piece.0 < text.len() {
+            pieces.push(self.string_piece(&text[piece.0..], Span::new(piece.1, position)));
+        }
+
+        let mut pieces = pieces.into_iter();
+        let first = pieces.next().unwrap();
+
+        let expr = pieces.fold(first, |lhs, rhs| {
+            let span = lhs.span().merge(rhs.span());
+
+            Spanned::new(
+                Expression::Binary {
+                    op: Spanned::new(BinaryOp::Plus, rhs.span()),
+                    lhs: Box::new(lhs),
+                    rhs: Box::new(rhs),
+                },
+                span,
+            )
+        });
+
+        Spanned::new(expr.into_value(), span)
+    }
+
+    fn string_piece(&mut self, text: &str, span: Span) -> Spanned<Expression> {
+        Spanned::new(
+            Expression::Literal(Literal::String(self.literals.intern(text))),
+            span,
+        )
+    }
 }
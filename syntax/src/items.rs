@@ -1,5 +1,6 @@
 use ast::prelude::{
-    Const, Function, FunctionParam, ParamKind, Spanned, Statement, Token, Type, TypeAlias,
+    Const, Function, FunctionParam, ParamKind, Spanned, Statement, Token, Trait, TraitMethod, Type,
+    TypeAlias,
 };
 
 use super::Parser;
@@ -18,6 +19,7 @@ impl<'a> Parser<'a> {
                 self.consume(Token::Number, "Expected an array length after `;`");
 
                 length = self.src[self.prev.span().start.absolute..self.prev.span().end.absolute]
+                    .replace('_', "")
                     .parse::<usize>()
                     .ok();
             }
@@ -124,6 +126,10 @@ impl<'a> Parser<'a> {
             self.while_statement()
         } else if self.match_token(Token::Return) {
             self.return_statement()
+        } else if self.match_token(Token::Break) {
+            self.loop_control(Statement::Break)
+        } else if self.match_token(Token::Continue) {
+            self.loop_control(Statement::Continue)
         } else if self.match_token(Token::For) {
             self.for_statement()
         } else if self.match_token(Token::Var) {
@@ -135,6 +141,9 @@ impl<'a> Parser<'a> {
 
     pub(crate) fn for_statement(&mut self) -> Spanned<Statement> {
         let cond = self.expression();
+
+        self.consume(Token::LeftBrace, "Expected `{` after the condition");
+
         let body = self.block();
 
         let start = cond.span();
@@ -151,6 +160,9 @@ impl<'a> Parser<'a> {
 
     pub(crate) fn while_statement(&mut self) -> Spanned<Statement> {
         let cond = self.expression();
+
+        self.consume(Token::LeftBrace, "Expected `{` after the condition");
+
         let body = self.block();
 
         let start = cond.span();
@@ -168,6 +180,9 @@ impl<'a> Parser<'a> {
     pub(crate) fn if_statement(&mut self) -> Spanned<Statement> {
         let start = self.prev.span();
         let cond = self.expression();
+
+        self.consume(Token::LeftBrace, "Expected `{` after the condition");
+
         let then = self.block();
 
         let mut else_ = None;
@@ -179,6 +194,8 @@ impl<'a> Parser<'a> {
                 self.advance();
                 else_ = Some(Box::new(self.if_statement()))
             } else {
+                self.consume(Token::LeftBrace, "Expected `{` after `else`");
+
                 else_ = Some(Box::new(self.block()))
             }
         };
@@ -198,7 +215,7 @@ impl<'a> Parser<'a> {
     pub(crate) fn return_statement(&mut self) -> Spanned<Statement> {
         let start = self.prev.span();
 
-        let ret_value = if self.match_token(Token::SemiColon) {
+        let ret_value = if self.check(Token::SemiColon) {
             None
         } else {
             Some(self.expression())
@@ -209,6 +226,18 @@ impl<'a> Parser<'a> {
         Spanned::new(Statement::Return(ret_value), start.merge(end))
     }
 
+    /// Parses the `;` that follows a `break` or a `continue`
+    pub(crate) fn loop_control(&mut self, statement: Statement) -> Spanned<Statement> {
+        let start = self.prev.span();
+
+        let end = self.consume_get_span(
+            Token::SemiColon,
+            "Expected ';' after a loop control statement.",
+        );
+
+        Spanned::new(statement, start.merge(end))
+    }
+
     pub(crate) fn block(&mut self) -> Spanned<Statement> {
         let mut block = Vec::new();
         let start = self.prev.span();
@@ -296,8 +325,61 @@ impl<'a> Parser<'a> {
         )
     }
 
-    pub(crate) fn trait_declaration(&mut self) {
-        todo!()
+    /// trait -> "trait" identifier "{" ( "fn" identifier "(" params ")" ( "->" type )? ";" )* "}"
+    pub(crate) fn trait_declaration(&mut self) -> Spanned<Trait> {
+        let start = self.prev.span();
+        self.consume(Token::Identifier, "Expected a trait name");
+
+        let name = self.get_identifier();
+
+        self.consume(Token::LeftBrace, "Expected `{` after the trait name");
+
+        let mut methods = Vec::new();
+
+        while !self.check(Token::RightBrace) && !self.check(Token::Eof) {
+            methods.push(self.trait_method());
+
+            if self.panic_mode {
+                break;
+            }
+        }
+
+        let end = self.consume_get_span(Token::RightBrace, "Expected `}` after the trait body");
+
+        Spanned::new(Trait { name, methods }, start.merge(end))
+    }
+
+    fn trait_method(&mut self) -> Spanned<TraitMethod> {
+        self.consume(Token::Fun, "Expected `fn`");
+
+        let start = self.prev.span();
+
+        self.consume(Token::Identifier, "Expected a method name");
+
+        let name = self.get_identifier();
+
+        self.consume(Token::LeftParen, "Expected '(' ");
+
+        let params = self.parse_params(ParamKind::Function);
+
+        self.consume(Token::RightParen, "Expected ')'");
+
+        let mut returns = None;
+
+        if self.match_token(Token::FunctionReturn) {
+            returns = Some(self.parse_type());
+        }
+
+        let end = self.consume_get_span(Token::SemiColon, "Expected `;` after a method signature");
+
+        Spanned::new(
+            TraitMethod {
+                name,
+                params,
+                returns,
+            },
+            start.merge(end),
+        )
     }
 
     pub(crate) fn let_statement(&mut self) -> Spanned<Statement> {
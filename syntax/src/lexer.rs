@@ -7,6 +7,8 @@ pub struct Lexer<'a> {
     pub(crate) lookahead: Option<Position>,
     pub(crate) start: Position,
     pub(crate) end: Position,
+    /// The kind of the last token returned, used to tell `.5` apart from a field access
+    pub(crate) previous: Option<Token>,
 }
 
 impl<'a> Lexer<'a> {
@@ -16,15 +18,27 @@ impl<'a> Lexer<'a> {
             lookahead: Some(Position::new(1, 1, 0)),
             start: Position::new(1, 1, 0),
             end: Position::new(1, 1, 0),
+            previous: None,
         }
     }
+
     pub fn next_token(&mut self) -> Spanned<Token> {
+        let token = self.lex();
+        self.previous = Some(*token.value());
+        token
+    }
+
+    fn lex(&mut self) -> Spanned<Token> {
         self.skip_whitespace();
 
         if self.is_at_end() {
             return self.make_token(Token::Eof);
         }
 
+        // `.5` is a number unless the `.` could be accessing a field of the previous operand
+        let fraction =
+            self.peek() == Some(".") && self.is_digit(self.peek_next()) && !self.follows_operand();
+
         let c = self.advance();
 
         match c {
@@ -34,6 +48,7 @@ impl<'a> Lexer<'a> {
                 "{" => self.make_token(Token::LeftBrace),
                 "}" => self.make_token(Token::RightBrace),
                 "," => self.make_token(Token::Comma),
+                "." if fraction => self.fraction(start),
                 "." => self.make_token(Token::Dot),
                 "-" => matches!(self, ">", Token::FunctionReturn, Token::Minus),
                 "+" => self.make_token(Token::Plus),
@@ -61,10 +76,7 @@ impl<'a> Lexer<'a> {
                 ch if ch >= "a" && ch <= "z" || ch >= "A" && ch <= "Z" || ch == "_" => {
                     self.identifier(start)
                 }
-                ch => {
-                    println!("err {:?}", ch);
-                    self.error_token("Unexpected character.")
-                }
+                _ => self.error_token("Unexpected character."),
             },
             None => self.make_token(Token::Eof),
         }
@@ -141,8 +153,10 @@ impl<'a> Lexer<'a> {
             return Some("\n");
         }
 
-        self.src
-            .get(self.start.absolute + 1..self.start.absolute + 2)
+        match self.lookahead {
+            Some(pos) => self.src.get(pos.absolute + 1..pos.absolute + 2),
+            None => None,
+        }
     }
 
     fn skip_whitespace(&mut self) {
@@ -203,21 +217,101 @@ impl<'a> Lexer<'a> {
     }
 
     fn number(&mut self, start: Position) -> Spanned<Token> {
-        while self.is_digit(self.peek()) {
-            self.advance();
+        if !self.digits() {
+            return self.error_token("Invalid digit separator.");
         }
 
-        if self.peek() == Some(".") && self.is_digit(self.peek_next()) {
+        if self.peek() == Some(".") {
             self.advance();
 
-            while self.is_digit(self.peek()) {
-                self.advance();
+            // `1.` would be read as a float by some and as a property access by others
+            if !self.is_digit(self.peek()) {
+                return self.error_token("Expect a digit after '.'.");
             }
+
+            return self.fraction(start);
+        }
+
+        if let Err(message) = self.exponent() {
+            return self.error_token(message);
+        }
+
+        Spanned::new(Token::Number, Span::new(start, self.end))
+    }
+
+    /// Lexes the digits after a decimal point, which has already been consumed
+    fn fraction(&mut self, start: Position) -> Spanned<Token> {
+        if !self.digits() {
+            return self.error_token("Invalid digit separator.");
+        }
+
+        if let Err(message) = self.exponent() {
+            return self.error_token(message);
         }
 
         Spanned::new(Token::Number, Span::new(start, self.end))
     }
 
+    /// Consumes an exponent such as `e10` or `E-3` if one follows, returns the error to report when
+    /// it has no digits
+    fn exponent(&mut self) -> Result<bool, &'static str> {
+        if !std::matches!(self.peek(), Some("e") | Some("E")) {
+            return Ok(false);
+        }
+
+        self.advance();
+
+        if std::matches!(self.peek(), Some("+") | Some("-")) {
+            self.advance();
+        }
+
+        if !self.is_digit(self.peek()) {
+            return Err("Expect digits in exponent.");
+        }
+
+        if !self.digits() {
+            return Err("Invalid digit separator.");
+        }
+
+        Ok(true)
+    }
+
+    /// Whether the last token can end an operand, in which case a `.` starts a field access
+    fn follows_operand(&self) -> bool {
+        std::matches!(
+            self.previous,
+            Some(
+                Token::Identifier
+                    | Token::Number
+                    | Token::String
+                    | Token::True
+                    | Token::False
+                    | Token::Nil
+                    | Token::This
+                    | Token::RightParen
+                    | Token::RightBracket
+            )
+        )
+    }
+
+    /// Consumes a run of digits that may be separated by single `_`s, returns false if a `_` is
+    /// doubled or ends the run
+    fn digits(&mut self) -> bool {
+        let mut separated = false;
+        let mut valid = true;
+
+        while self.is_digit(self.peek()) || self.peek() == Some("_") {
+            let separator = self.peek() == Some("_");
+
+            valid &= !(separator && separated);
+            separated = separator;
+
+            self.advance();
+        }
+
+        valid && !separated
+    }
+
     fn identifier(&mut self, start: Position) -> Spanned<Token> {
         while self.is_alpha(self.peek()) || self.is_digit(self.peek()) {
             self.advance();
@@ -230,16 +324,31 @@ impl<'a> Lexer<'a> {
         // We use absolute here to get the proper index
         match self.src.get(start.absolute..start.absolute + 1) {
             Some("a") => self.check_keyword(start, 2, "nd", Token::And),
+            Some("b") => self.check_keyword(start, 4, "reak", Token::Break),
             Some("c") => match self.src.get(start.absolute + 1..start.absolute + 2) {
                 Some("l") => self.check_keyword(start.shift("l"), 3, "ass", Token::Class),
-                Some("o") => self.check_keyword(start.shift("o"), 3, "nst", Token::Const),
+                Some("o") => match self.src.get(start.absolute + 2..start.absolute + 4) {
+                    Some("ns") => self.check_keyword(
+                        start.shift("o").shift("n").shift("s"),
+                        1,
+                        "t",
+                        Token::Const,
+                    ),
+                    Some("nt") => self.check_keyword(
+                        start.shift("o").shift("n").shift("t"),
+                        4,
+                        "inue",
+                        Token::Continue,
+                    ),
+                    _ => Token::Identifier,
+                },
                 _ => Token::Identifier,
             },
             Some("e") => self.check_keyword(start, 3, "lse", Token::Else),
             Some("f") => match self.src.get(start.absolute + 1..start.absolute + 2) {
                 Some("a") => self.check_keyword(start.shift("a"), 3, "lse", Token::False),
                 Some("o") => self.check_keyword(start.shift("o"), 1, "r", Token::For),
-                Some("n") => Token::Fun,
+                Some("n") => self.check_keyword(start.shift("n"), 0, "", Token::Fun),
                 _ => Token::Identifier,
             },
 
@@ -283,3 +392,144 @@ impl<'a> Lexer<'a> {
         Token::Identifier
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::Lexer;
+    use ast::prelude::Token;
+
+    fn first_token(src: &str) -> Token {
+        *Lexer::new(src).next_token().value()
+    }
+
+    fn tokens(src: &str) -> Vec<Token> {
+        let mut lexer = Lexer::new(src);
+        let mut tokens = Vec::new();
+
+        loop {
+            let token = *lexer.next_token().value();
+            tokens.push(token);
+
+            if token == Token::Eof {
+                return tokens;
+            }
+        }
+    }
+
+    #[test]
+    fn it_does_not_confuse_identifiers_with_f_keywords() {
+        let cases = [
+            ("f", Token::Identifier),
+            ("fo", Token::Identifier),
+            ("foo", Token::Identifier),
+            ("for", Token::For),
+            ("fn", Token::Fun),
+            ("fn2", Token::Identifier),
+            ("fun", Token::Identifier),
+            ("func", Token::Identifier),
+            ("false", Token::False),
+            ("falsey", Token::Identifier),
+        ];
+
+        for (src, expected) in cases {
+            assert_eq!(first_token(src), expected, "lexing `{}`", src);
+        }
+    }
+
+    #[test]
+    fn it_lexes_loop_control_keywords() {
+        let cases = [
+            ("break", Token::Break),
+            ("breaks", Token::Identifier),
+            ("continue", Token::Continue),
+            ("cont", Token::Identifier),
+            ("const", Token::Const),
+            ("constant", Token::Identifier),
+        ];
+
+        for (src, expected) in cases {
+            assert_eq!(first_token(src), expected, "lexing `{}`", src);
+        }
+    }
+
+    #[test]
+    fn it_reconstructs_the_text_of_a_token() {
+        let src = "let answer := 42;";
+        let mut lexer = Lexer::new(src);
+
+        let keyword = lexer.next_token();
+        let identifier = lexer.next_token();
+        let assignment = lexer.next_token();
+
+        assert_eq!(keyword.text(src), "let");
+        assert_eq!(identifier.text(src), "answer");
+        assert_eq!(identifier.value().canonical_str(), None);
+        assert_eq!(assignment.text(src), ":=");
+    }
+
+    #[test]
+    fn it_lexes_underscores_between_digits() {
+        assert_eq!(tokens("1_000"), [Token::Number, Token::Eof]);
+        assert_eq!(tokens("1_000.5"), [Token::Number, Token::Eof]);
+        assert_eq!(first_token("_1"), Token::Identifier);
+
+        for src in ["1__0", "1_", "1.5_"] {
+            assert_eq!(first_token(src), Token::Error, "lexing `{}`", src);
+        }
+    }
+
+    #[test]
+    fn it_lexes_a_float_as_one_number() {
+        assert_eq!(tokens("1.5"), [Token::Number, Token::Eof]);
+        assert_eq!(
+            tokens("1.5;"),
+            [Token::Number, Token::SemiColon, Token::Eof]
+        );
+    }
+
+    #[test]
+    fn it_requires_digits_on_both_sides_of_a_decimal_point() {
+        assert_eq!(tokens("1."), [Token::Error, Token::Eof]);
+        assert_eq!(tokens("1.5"), [Token::Number, Token::Eof]);
+    }
+
+    #[test]
+    fn it_lexes_an_exponent_as_part_of_a_number() {
+        assert_eq!(tokens("1e10"), [Token::Number, Token::Eof]);
+        assert_eq!(
+            tokens("2.5E-1;"),
+            [Token::Number, Token::SemiColon, Token::Eof]
+        );
+        assert_eq!(tokens("1e"), [Token::Error, Token::Eof]);
+    }
+
+    #[test]
+    fn it_lexes_a_leading_dot_float_where_an_operand_starts() {
+        assert_eq!(tokens(".5"), [Token::Number, Token::Eof]);
+        assert_eq!(
+            tokens("1 + .5"),
+            [Token::Number, Token::Plus, Token::Number, Token::Eof]
+        );
+        assert_eq!(
+            tokens("a.b"),
+            [Token::Identifier, Token::Dot, Token::Identifier, Token::Eof]
+        );
+        assert_eq!(
+            tokens("a.0"),
+            [Token::Identifier, Token::Dot, Token::Number, Token::Eof]
+        );
+    }
+
+    #[test]
+    fn it_skips_a_trailing_line_comment() {
+        assert_eq!(
+            tokens("a / b // divide them"),
+            [
+                Token::Identifier,
+                Token::Slash,
+                Token::Identifier,
+                Token::Eof
+            ]
+        );
+    }
+}
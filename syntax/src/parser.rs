@@ -3,8 +3,12 @@ use std::collections::HashMap;
 use crate::hashmap;
 
 use super::lexer::Lexer;
-use ast::prelude::{Expression, Position, Program, Span, Spanned, SymbolDB, Token};
+use ast::prelude::{Expression, LiteralDB, Position, Program, Span, Spanned, SymbolDB, Token};
 use errors::Reporter;
+
+/// How deeply expressions may nest before the parser gives up instead of overflowing the stack
+const DEFAULT_MAX_DEPTH: usize = 256;
+
 pub struct Parser<'a> {
     pub(crate) src: &'a str,
     pub(crate) lexer: Lexer<'a>,
@@ -15,6 +19,10 @@ pub struct Parser<'a> {
     pub(crate) reporter: Reporter,
     pub(crate) rules: HashMap<Token, ParseRule<'a>>,
     pub(crate) symbols: SymbolDB,
+    pub(crate) literals: LiteralDB,
+    /// How many expressions enclose the one being parsed
+    pub(crate) depth: usize,
+    pub(crate) max_depth: usize,
 }
 
 #[derive(Clone, Copy)]
@@ -58,6 +66,9 @@ impl<'a> Parser<'a> {
             ),
 
             symbols: SymbolDB::default(),
+            literals: LiteralDB::new(),
+            depth: 0,
+            max_depth: DEFAULT_MAX_DEPTH,
             rules: hashmap! {
                 Token::LeftParen => ParseRule {
                         prefix: Some(Parser::grouping),
@@ -103,7 +114,7 @@ impl<'a> Parser<'a> {
                 },
                 Token::Slash => ParseRule {
                     prefix: None,
-                    infix: None, // Some(Parser::binary),
+                    infix: Some(Parser::binary),
                     precedence: Precedence::Factor,
                 },
                 Token::Star => ParseRule {
@@ -149,12 +160,12 @@ impl<'a> Parser<'a> {
                 },
                 Token::And => ParseRule {
                     prefix: None,
-                    infix: Some(Parser::binary),
+                    infix: Some(Parser::logical),
                     precedence: Precedence::And,
                 },
                 Token::Or =>  ParseRule {
                     prefix: None,
-                    infix: Some(Parser::binary),
+                    infix: Some(Parser::logical),
                     precedence: Precedence::Or,
                 },
                 Token::QuestionMark => ParseRule {
@@ -162,6 +173,11 @@ impl<'a> Parser<'a> {
                     infix: Some(Parser::ternary),
                     precedence: Precedence::Assignment
                 },
+                Token::Bar => ParseRule {
+                    prefix: Some(Parser::closure),
+                    infix: None,
+                    precedence: Precedence::None,
+                },
                 Token::Identifier => ParseRule {
                     prefix: Some(Parser::identifier),
                     infix: None,
@@ -179,6 +195,8 @@ impl<'a> Parser<'a> {
                 Token::Fun => ParseRule::default(),
                 Token::If => ParseRule::default(),
                 Token::Class => ParseRule::default(),
+                Token::Break => ParseRule::default(),
+                Token::Continue => ParseRule::default(),
                 Token::Else => ParseRule::default(),
                 Token::Assignment => ParseRule {
                     prefix: None,
@@ -193,6 +211,8 @@ impl<'a> Parser<'a> {
                 Token::Colon => ParseRule::default(),
                 Token::LeftBracket => ParseRule::default(),
                 Token::RightBracket => ParseRule::default(),
+                // `$` only has a meaning inside a string
+                Token::Dollar => ParseRule::default(),
             },
         };
 
@@ -201,6 +221,11 @@ impl<'a> Parser<'a> {
         parser
     }
 
+    /// Limits how deeply expressions may nest
+    pub fn max_depth(&mut self, depth: usize) {
+        self.max_depth = depth;
+    }
+
     pub fn advance(&mut self) {
         std::mem::swap(&mut self.prev, &mut self.current);
 
@@ -227,7 +252,7 @@ impl<'a> Parser<'a> {
             } else if self.match_token(Token::Type) {
                 program.add_type_alias(self.type_alias())
             } else if self.match_token(Token::Trait) {
-                self.trait_declaration()
+                program.add_trait(self.trait_declaration())
             }
 
             if self.panic_mode {
@@ -240,6 +265,8 @@ impl<'a> Parser<'a> {
         if self.had_error {
             None
         } else {
+            program.literals = self.literals;
+
             Some((program, self.symbols))
         }
     }
@@ -286,3 +313,297 @@ impl<'a> Default for ParseRule<'a> {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use ast::prelude::{BinaryOp, Expression, Literal, LogicalOp, PrettyPrinter, Statement};
+
+    use super::Parser;
+
+    #[test]
+    fn it_parses_a_grouping_without_a_semicolon() {
+        let parser = Parser::new("fn main() { let a := (1 + 2) * 3; }");
+
+        let (program, _) = parser.parse().expect("program should parse");
+
+        let body = match program.functions[0].body.value() {
+            Statement::Block(body) => body,
+            stmt => panic!("expected a block, found {:?}", stmt),
+        };
+
+        match body[0].value() {
+            Statement::Let {
+                init: Some(init), ..
+            } => match init.value() {
+                Expression::Binary { op, lhs, .. } => {
+                    assert!(matches!(op.value(), BinaryOp::Star));
+                    assert!(matches!(lhs.value(), Expression::Grouping(_)));
+                }
+                expr => panic!("expected a binary expression, found {:?}", expr),
+            },
+            stmt => panic!("expected a let statement, found {:?}", stmt),
+        }
+    }
+
+    #[test]
+    fn it_parses_division_as_a_binary_expression() {
+        let parser = Parser::new("fn main() { let x := 6 / 2; }");
+
+        let (program, _) = parser.parse().expect("program should parse");
+
+        let body = match program.functions[0].body.value() {
+            Statement::Block(body) => body,
+            stmt => panic!("expected a block, found {:?}", stmt),
+        };
+
+        match body[0].value() {
+            Statement::Let {
+                init: Some(init), ..
+            } => match init.value() {
+                Expression::Binary { op, lhs, rhs } => {
+                    assert!(matches!(op.value(), BinaryOp::Slash));
+                    assert!(matches!(
+                        lhs.value(),
+                        Expression::Literal(Literal::Number(_))
+                    ));
+                    assert!(matches!(
+                        rhs.value(),
+                        Expression::Literal(Literal::Number(_))
+                    ));
+                }
+                expr => panic!("expected a binary expression, found {:?}", expr),
+            },
+            stmt => panic!("expected a let statement, found {:?}", stmt),
+        }
+    }
+
+    #[test]
+    fn it_ends_an_if_body_at_its_closing_brace() {
+        let parser = Parser::new("fn main() { if a { return 1; } else { return 2; } let b := 2; }");
+
+        let (program, _) = parser.parse().expect("program should parse");
+
+        let body = match program.functions[0].body.value() {
+            Statement::Block(body) => body,
+            stmt => panic!("expected a block, found {:?}", stmt),
+        };
+
+        assert_eq!(body.len(), 2);
+        assert!(matches!(
+            body[0].value(),
+            Statement::If { else_: Some(_), .. }
+        ));
+        assert!(matches!(body[1].value(), Statement::Let { .. }));
+    }
+
+    #[test]
+    fn it_pretty_prints_a_parsed_program() {
+        let src = "fn main() { let a := 10; while a > 1 { a := a - 1; } if a == 1 { return add(a, 2); } else { return; } }";
+
+        let (program, symbols) = Parser::new(src).parse().expect("program should parse");
+
+        let printed = PrettyPrinter::new(src, &symbols).print(&program);
+
+        assert!(printed.contains("fn main() {"));
+        assert!(printed.contains("add(a, 2)"));
+        assert_eq!(
+            printed,
+            "fn main() {
+    let a := 10;
+    while a > 1 {
+        a := a - 1;
+    }
+    if a == 1 {
+        return add(a, 2);
+    } else {
+        return;
+    }
+}
+"
+        );
+    }
+
+    #[test]
+    fn it_parses_a_closure() {
+        let parser = Parser::new("fn main() { let f := |x: number| x + 1; }");
+
+        let (program, _) = parser.parse().expect("program should parse");
+
+        let body = match program.functions[0].body.value() {
+            Statement::Block(body) => body,
+            stmt => panic!("expected a block, found {:?}", stmt),
+        };
+
+        match body[0].value() {
+            Statement::Let {
+                init: Some(init), ..
+            } => match init.value() {
+                Expression::Closure { params, body } => {
+                    assert_eq!(params.len(), 1);
+
+                    match body.value() {
+                        Statement::Return(Some(expr)) => assert!(matches!(
+                            expr.value(),
+                            Expression::Binary { op, .. } if matches!(op.value(), BinaryOp::Plus)
+                        )),
+                        stmt => panic!("expected an expression body, found {:?}", stmt),
+                    }
+                }
+                expr => panic!("expected a closure, found {:?}", expr),
+            },
+            stmt => panic!("expected a let statement, found {:?}", stmt),
+        }
+    }
+
+    #[test]
+    fn it_parses_and_tighter_than_or() {
+        let parser = Parser::new("fn main() { let x := a and b or c; }");
+
+        let (program, _) = parser.parse().expect("program should parse");
+
+        let body = match program.functions[0].body.value() {
+            Statement::Block(body) => body,
+            stmt => panic!("expected a block, found {:?}", stmt),
+        };
+
+        match body[0].value() {
+            Statement::Let {
+                init: Some(init), ..
+            } => match init.value() {
+                Expression::Logical { op, lhs, rhs } => {
+                    assert!(matches!(op.value(), LogicalOp::Or));
+                    assert!(matches!(
+                        lhs.value(),
+                        Expression::Logical { op, .. } if matches!(op.value(), LogicalOp::And)
+                    ));
+                    assert!(matches!(rhs.value(), Expression::Identifier(_)));
+                }
+                expr => panic!("expected a logical expression, found {:?}", expr),
+            },
+            stmt => panic!("expected a let statement, found {:?}", stmt),
+        }
+    }
+
+    #[test]
+    fn it_stores_the_value_of_literals() {
+        let parser = Parser::new("fn main() { let a := 42; let b := \"hi\"; }");
+
+        let (program, _) = parser.parse().expect("program should parse");
+
+        let body = match program.functions[0].body.value() {
+            Statement::Block(body) => body,
+            stmt => panic!("expected a block, found {:?}", stmt),
+        };
+
+        let literals = body
+            .iter()
+            .map(|stmt| match stmt.value() {
+                Statement::Let {
+                    init: Some(init), ..
+                } => match init.value() {
+                    Expression::Literal(literal) => *literal,
+                    expr => panic!("expected a literal, found {:?}", expr),
+                },
+                stmt => panic!("expected a let statement, found {:?}", stmt),
+            })
+            .collect::<Vec<_>>();
+
+        match literals[0] {
+            Literal::Number(value) => assert_eq!(value, 42.0),
+            literal => panic!("expected a number, found {:?}", literal),
+        }
+
+        match literals[1] {
+            Literal::String(id) => assert_eq!(program.literals.lookup(&id), "hi"),
+            literal => panic!("expected a string, found {:?}", literal),
+        }
+    }
+
+    #[test]
+    #[allow(clippy::approx_constant)]
+    fn it_stores_fractional_and_negative_numbers() {
+        let parser = Parser::new("fn main() { let x := 3.14; let y := -2.5; let z := -x; }");
+
+        let (program, _) = parser.parse().expect("program should parse");
+
+        let body = match program.functions[0].body.value() {
+            Statement::Block(body) => body,
+            stmt => panic!("expected a block, found {:?}", stmt),
+        };
+
+        let init = |index: usize| match body[index].value() {
+            Statement::Let {
+                init: Some(init), ..
+            } => init.value(),
+            stmt => panic!("expected a let statement, found {:?}", stmt),
+        };
+
+        assert!(matches!(init(0), Expression::Literal(Literal::Number(value)) if *value == 3.14));
+        assert!(matches!(init(1), Expression::Literal(Literal::Number(value)) if *value == -2.5));
+        assert!(matches!(init(2), Expression::Unary { .. }));
+    }
+
+    #[test]
+    fn it_reports_deeply_nested_expressions_instead_of_overflowing() {
+        let depth = 5000;
+        let src = format!(
+            "fn main() {{ let a := {}1{}; }}",
+            "(".repeat(depth),
+            ")".repeat(depth)
+        );
+
+        let mut parser = Parser::new(&src);
+        parser.max_depth(64);
+
+        let reporter = parser.reporter.clone();
+
+        assert!(parser.parse().is_none());
+        assert!(reporter
+            .diagnostics()
+            .iter()
+            .any(|diagnostic| diagnostic.msg == "Expression too deeply nested"));
+    }
+
+    #[test]
+    fn it_desugars_dollar_names_in_strings_to_concatenation() {
+        let src = r#"fn main() { let a := "hi $name!"; let b := "$name"; let c := "costs $5"; }"#;
+
+        let (program, symbols) = Parser::new(src).parse().expect("program should parse");
+
+        let printed = PrettyPrinter::new(src, &symbols).print(&program);
+
+        assert!(printed.contains(r#"let a := "hi " + name + "!";"#));
+        assert!(printed.contains(r#"let b := "" + name;"#));
+        assert!(printed.contains(r#"let c := "costs $5";"#));
+    }
+
+    #[test]
+    fn it_points_an_interpolated_name_at_its_place_in_the_string() {
+        let src = r#"fn main() { let a := "hi $name"; }"#;
+
+        let (program, _) = Parser::new(src).parse().expect("program should parse");
+
+        let body = match program.functions[0].body.value() {
+            Statement::Block(body) => body,
+            stmt => panic!("expected a block, found {:?}", stmt),
+        };
+
+        match body[0].value() {
+            Statement::Let {
+                init: Some(init), ..
+            } => match init.value() {
+                Expression::Binary { rhs, .. } => match rhs.value() {
+                    Expression::Identifier(name) => assert_eq!(name.view(src), Some("name")),
+                    expr => panic!("expected an identifier, found {:?}", expr),
+                },
+                expr => panic!("expected a binary expression, found {:?}", expr),
+            },
+            stmt => panic!("expected a let statement, found {:?}", stmt),
+        }
+    }
+
+    #[test]
+    fn it_reports_a_dollar_outside_a_string() {
+        assert!(Parser::new("fn main() { let a := $b; }").parse().is_none());
+    }
+}
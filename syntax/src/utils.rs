@@ -1,4 +1,4 @@
-use ast::prelude::{BinaryOp, Expression, Span, Spanned, SymbolId, Token, UnaryOp};
+use ast::prelude::{BinaryOp, Expression, LogicalOp, Span, Spanned, SymbolId, Token, UnaryOp};
 
 use super::parser::{ParseRule, Parser, Precedence};
 
@@ -92,6 +92,25 @@ impl<'a> Parser<'a> {
     }
 
     pub(crate) fn parse_with_precedence(&mut self, precedence: Precedence) -> Spanned<Expression> {
+        if self.depth >= self.max_depth {
+            self.error_at_current("Expression too deeply nested");
+
+            // Nothing after this point can be trusted so stop parsing altogether
+            while !self.check(Token::Eof) {
+                self.advance();
+            }
+
+            return Spanned::new(Expression::Error, self.current.span());
+        }
+
+        self.depth += 1;
+        let expr = self.parse_operators(precedence);
+        self.depth -= 1;
+
+        expr
+    }
+
+    fn parse_operators(&mut self, precedence: Precedence) -> Spanned<Expression> {
         self.advance();
 
         let prefix_rule = self.get_rule(*self.prev.value()).prefix;
@@ -153,4 +172,14 @@ impl<'a> Parser<'a> {
 
         Spanned::new(op, self.prev.span())
     }
+
+    pub(crate) fn get_logical_op(&mut self) -> Spanned<LogicalOp> {
+        let op = match *self.prev.value() {
+            Token::And => LogicalOp::And,
+            Token::Or => LogicalOp::Or,
+            _ => unreachable!(),
+        };
+
+        Spanned::new(op, self.prev.span())
+    }
 }
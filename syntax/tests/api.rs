@@ -0,0 +1,11 @@
+use syntax::Parser;
+
+#[test]
+fn the_crate_exposes_the_parser() {
+    let (program, symbols) = Parser::new("fn main() { let a := 1; }")
+        .parse()
+        .expect("program should parse");
+
+    assert_eq!(program.functions.len(), 1);
+    assert_eq!(symbols.lookup(program.functions[0].name.value()), "main");
+}
@@ -1,22 +1,59 @@
-use compiler::{compile, ParseResult};
+use compiler::{compile, ParseResult, Scanner, TokenType};
 use syntax::Parser;
 
 use core::construct_ir;
 use std::fs::File;
-use std::io::Read;
+use std::io::{Read, Write};
 use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, OnceLock};
 use std::{env, process::exit};
-use vm::{ClosureObject, Value, VM};
+use vm::{chunk::Chunk, ObjectType, VM};
+
+/// Set by `Ctrl-C` and shared with every vm so it can stop whatever is running
+static INTERRUPT: OnceLock<Arc<AtomicBool>> = OnceLock::new();
+
+fn interrupt_flag() -> &'static Arc<AtomicBool> {
+    INTERRUPT.get_or_init(|| Arc::new(AtomicBool::new(false)))
+}
+
+/// Makes `Ctrl-C` stop the running script, a second one before the first is handled exits
+fn install_interrupt_handler() {
+    extern "C" fn on_sigint(_: libc::c_int) {
+        if let Some(flag) = INTERRUPT.get() {
+            if flag.swap(true, Ordering::Relaxed) {
+                unsafe { libc::_exit(130) };
+            }
+        }
+    }
+
+    interrupt_flag();
+
+    unsafe {
+        libc::signal(
+            libc::SIGINT,
+            on_sigint as extern "C" fn(libc::c_int) as libc::sighandler_t,
+        );
+    }
+}
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     let args = env::args().collect::<Vec<String>>();
 
+    install_interrupt_handler();
+
     if args.len() == 1 {
         repl()?;
     } else if args.len() == 2 {
         run_file(&args[1])?;
+    } else if args.len() == 3 && args[1] == "--dump-ast" {
+        dump_ast(&args[2])?;
+    } else if args.len() == 3 && args[1] == "--dump-bytecode" {
+        dump_bytecode(&args[2])?;
+    } else if args.len() == 3 && args[1] == "--dump-tokens" {
+        dump_tokens(&args[2])?;
     } else {
-        println!("Usage: vision [script]");
+        println!("Usage: vision [--dump-ast | --dump-bytecode | --dump-tokens] [script]");
         std::process::exit(64);
     }
 
@@ -24,43 +61,54 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 }
 
 fn interpret(src: &str) -> Result<(), Box<dyn std::error::Error>> {
+    interpret_to(src, Box::new(std::io::stdout()))
+}
+
+/// Compiles and runs `src`, sending everything it prints to `out`
+fn interpret_to(src: &str, out: Box<dyn Write>) -> Result<(), Box<dyn std::error::Error>> {
     let ParseResult {
         function,
-        mut allocator,
+        allocator,
         table,
     } = compile(src).ok_or("Compile error")?;
 
     if function.is_null() {
         Err("Compile error".into())
     } else {
-        let function_ptr = function.as_function();
-
-        let closure = allocator.alloc(|next| ClosureObject::new(function_ptr, next));
-
         let mut vm = VM::new(table, allocator);
 
-        vm.push(Value::object(function.as_ptr_obj()));
-
-        vm.pop();
+        vm.set_output(out);
+        vm.set_interrupt(interrupt_flag().clone());
 
-        vm.push(Value::object(closure.clone().into()));
+        let result = vm.interpret(function);
 
-        vm.call(closure, 0);
+        #[cfg(feature = "profile")]
+        vm.dump_op_counts(&mut std::io::stderr())?;
 
-        vm.run()
+        result
     }
 }
 
+/// Runs each line as it is entered until `Ctrl-D`, errors are reported and the prompt comes back
 fn repl() -> Result<(), Box<dyn std::error::Error>> {
     let mut buffer = String::new();
 
     loop {
         print!("> ");
+        std::io::stdout().flush()?;
+
+        buffer.clear();
+
+        if std::io::stdin().read_line(&mut buffer)? == 0 {
+            println!();
+            break;
+        }
 
-        std::io::stdin().read_line(&mut buffer)?;
+        // A `Ctrl-C` at the prompt shouldn't stop the line entered after it
+        interrupt_flag().store(false, Ordering::Relaxed);
 
-        interpret(&buffer)?;
-        break;
+        // Errors are reported as they happen, all that's left is to prompt again
+        let _ = interpret(&buffer);
     }
 
     Ok(())
@@ -85,9 +133,111 @@ fn run_file(path: &dyn AsRef<Path>) -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
+/// Prints the tree the syntax parser builds for the file, without resolving it
+fn dump_ast(path: &dyn AsRef<Path>) -> Result<(), Box<dyn std::error::Error>> {
+    let mut file = File::open(path)?;
+
+    let mut buffer = String::with_capacity(1024);
+
+    file.read_to_string(&mut buffer)?;
+
+    let parser = Parser::new(&buffer);
+
+    let (ast, _) = match parser.parse() {
+        Some(program) => program,
+        None => exit(1),
+    };
+
+    println!("{:#?}", ast);
+
+    Ok(())
+}
+
+fn dump_bytecode(path: &dyn AsRef<Path>) -> Result<(), Box<dyn std::error::Error>> {
+    let mut file = File::open(path)?;
+
+    let mut buffer = String::with_capacity(1024);
+
+    file.read_to_string(&mut buffer)?;
+
+    let ParseResult { function, .. } = match compile(&buffer) {
+        Some(result) => result,
+        None => exit(65),
+    };
+
+    disassemble_all(&function.chunk, "<script>");
+
+    Ok(())
+}
+
+fn dump_tokens(path: &dyn AsRef<Path>) -> Result<(), Box<dyn std::error::Error>> {
+    let mut file = File::open(path)?;
+
+    let mut buffer = String::with_capacity(1024);
+
+    file.read_to_string(&mut buffer)?;
+
+    let mut scanner = Scanner::new(&buffer);
+
+    loop {
+        let token = scanner.scan_token();
+
+        println!(
+            "{:4} {:?} '{}' {}..{}",
+            token.line,
+            token.ty,
+            token.lexme,
+            token.start,
+            token.start + token.length
+        );
+
+        if token.ty == TokenType::Eof {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+/// Disassembles a chunk followed by every function nested in its constants
+fn disassemble_all(chunk: &Chunk, name: &str) {
+    chunk.disassemble(name);
+    println!();
+
+    for constant in &chunk.constants {
+        if constant.is_obj() && constant.obj_type() == ObjectType::Function {
+            let function = constant.as_function();
+
+            let name = match &function.name {
+                Some(name) => name.value().trim_end_matches('\0'),
+                None => "<fn>",
+            };
+
+            disassemble_all(&function.chunk, name);
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::interpret;
+    use crate::{interpret, interpret_to};
+    use compiler::{compile, ParseResult};
+    use vm::{chunk::Chunk, Arity, CapturedOutput, LineEnding, NumberFormat, Precision, Value, VM};
+
+    fn run(src: &str) -> String {
+        let output = CapturedOutput::default();
+
+        interpret_to(src, Box::new(output.clone())).unwrap();
+
+        output.contents()
+    }
+
+    /// Runs `src` expecting it to fail and returns the error
+    fn run_err(src: &str) -> String {
+        interpret_to(src, Box::new(CapturedOutput::default()))
+            .unwrap_err()
+            .to_string()
+    }
 
     #[test]
     fn it_works() {
@@ -113,4 +263,663 @@ mod tests {
         )
         .unwrap();
     }
+
+    #[test]
+    fn division_produces_a_float() {
+        assert_eq!(run("print 7 / 2;"), "3.5\n");
+    }
+
+    #[test]
+    fn division_with_a_whole_result_prints_without_a_fraction() {
+        assert_eq!(run("print 6 / 3;"), "2\n");
+    }
+
+    #[test]
+    fn integer_arithmetic_stays_integer() {
+        assert_eq!(run("print 7 * 2;"), "14\n");
+        assert_eq!(run("print 7 - 10;"), "-3\n");
+    }
+
+    #[test]
+    fn mixing_an_integer_and_a_float_promotes_to_float() {
+        assert_eq!(run("print 1 + 0.5;"), "1.5\n");
+        assert_eq!(run("print 2 == 2.0;"), "true\n");
+    }
+
+    #[test]
+    fn integer_overflow_is_a_runtime_error() {
+        assert!(interpret("print 9223372036854775807 + 1;").is_err());
+    }
+
+    #[test]
+    fn println_returns_its_argument() {
+        assert_eq!(run("var y = println(1 + 2); print y * 2;"), "3\n6\n");
+    }
+
+    #[test]
+    fn self_tail_calls_run_in_constant_stack() {
+        // Far deeper than the frame limit, so this only finishes if the calls became a loop
+        assert_eq!(
+            run(r#"
+            fun sum(n, acc) {
+                if (n == 0) return acc;
+                var next = acc + n;
+                return sum(n - 1, next);
+            }
+
+            print sum(100000, 0);
+            "#),
+            "5000050000\n"
+        );
+    }
+
+    #[test]
+    fn a_tail_call_through_a_rebound_name_calls_the_new_function() {
+        assert_eq!(
+            run(r#"
+            fun f(n) {
+                if (n == 0) return "f";
+                return f(n - 1);
+            }
+
+            fun h(n) { return "h" + str(n); }
+
+            var g = f;
+            f = h;
+            print g(3);
+            "#),
+            "h2\n"
+        );
+    }
+
+    #[test]
+    fn calls_that_are_not_in_tail_position_still_work() {
+        assert_eq!(
+            run(r#"
+            fun fib(n) {
+                if (n < 2) return n;
+                return fib(n - 1) + fib(n - 2);
+            }
+
+            print fib(15);
+            "#),
+            "610\n"
+        );
+    }
+
+    #[test]
+    fn a_deserialized_chunk_runs_to_the_same_result() {
+        let src = "print (1 + 2) * 3 - 7 / 2;";
+
+        let ParseResult {
+            mut function,
+            allocator,
+            table,
+        } = compile(src).unwrap();
+
+        let bytes = function.chunk.serialize();
+        function.chunk = Chunk::deserialize(&bytes).unwrap();
+
+        let output = CapturedOutput::default();
+        let mut vm = VM::new(table, allocator);
+
+        vm.set_output(Box::new(output.clone()));
+        vm.interpret(function).unwrap();
+
+        let printed = output.contents();
+
+        assert_eq!(printed, run(src));
+        assert_eq!(printed, "5.5\n");
+    }
+
+    #[test]
+    fn setting_the_interrupt_flag_stops_a_running_loop() {
+        let ParseResult {
+            function,
+            allocator,
+            table,
+        } = compile("var i = 0; while (true) { i = i + 1; }").unwrap();
+
+        let mut vm = VM::new(table, allocator);
+        let interrupt = vm.interrupt_handle();
+
+        let handle = std::thread::spawn(move || {
+            std::thread::sleep(std::time::Duration::from_millis(50));
+            interrupt.store(true, std::sync::atomic::Ordering::Relaxed);
+        });
+
+        let error = vm.interpret(function).unwrap_err();
+        handle.join().unwrap();
+
+        assert_eq!(error.to_string(), "Runtime error: Interrupted.");
+        assert!(!vm
+            .interrupt_handle()
+            .load(std::sync::atomic::Ordering::Relaxed));
+    }
+
+    fn run_with_natives(src: &str, register: impl FnOnce(&mut VM)) -> String {
+        let ParseResult {
+            function,
+            allocator,
+            table,
+        } = compile(src).unwrap();
+
+        let output = CapturedOutput::default();
+        let mut vm = VM::new(table, allocator);
+
+        vm.set_output(Box::new(output.clone()));
+        register(&mut vm);
+        vm.interpret(function).unwrap();
+
+        output.contents()
+    }
+
+    #[test]
+    fn scripts_can_call_registered_natives() {
+        fn double(_: &mut VM, arg_count: usize, args: *const Value) -> Value {
+            let args = unsafe { std::slice::from_raw_parts(args, arg_count) };
+
+            Value::int(args[0].as_int() * 2)
+        }
+
+        fn sum(args: &[Value]) -> Value {
+            Value::number(args.iter().map(Value::as_float).sum())
+        }
+
+        assert_eq!(
+            run_with_natives("print double(21); print sum(1, 2, 3.5);", |vm| {
+                vm.register_native("double", Arity::Exactly(1), double);
+                vm.register_slice_native("sum", Arity::Any, sum);
+            }),
+            "42\n6.5\n"
+        );
+    }
+
+    #[test]
+    fn calling_a_native_with_the_wrong_number_of_arguments_is_a_runtime_error() {
+        fn double(_: &mut VM, arg_count: usize, args: *const Value) -> Value {
+            let args = unsafe { std::slice::from_raw_parts(args, arg_count) };
+
+            Value::int(args[0].as_int() * 2)
+        }
+
+        let ParseResult {
+            function,
+            allocator,
+            table,
+        } = compile("double();").unwrap();
+
+        let mut vm = VM::new(table, allocator);
+        vm.register_native("double", Arity::Exactly(1), double);
+
+        assert_eq!(
+            vm.interpret(function).unwrap_err().to_string(),
+            "Runtime error: Expected 1 arguments but got 0"
+        );
+
+        assert_eq!(
+            run_err("clock(5);"),
+            "Runtime error: Expected 0 arguments but got 1"
+        );
+        assert_eq!(
+            run_err("assert(true, \"a\", \"b\");"),
+            "Runtime error: Expected 1 to 2 arguments but got 3"
+        );
+    }
+
+    #[test]
+    fn runtime_errors_name_the_type_that_was_found() {
+        assert_eq!(
+            run_err("print -\"a\";"),
+            "Runtime error: Operand must be a number, found `string`."
+        );
+        assert_eq!(
+            run_err("print 1 * nil;"),
+            "Runtime error: * operands must be numbers, found `int` and `nil`"
+        );
+        assert_eq!(
+            run_err("var a = true; a();"),
+            "Runtime error: Can only call functions and classes, found `bool`."
+        );
+    }
+
+    #[test]
+    fn runtime_errors_report_the_line_of_the_failing_instruction() {
+        let error = interpret_to(
+            "var a = 1;\nfun f(x) {\n  return -x;\n}\nprint a;\nf(\"b\");\n",
+            Box::new(CapturedOutput::default()),
+        )
+        .unwrap_err();
+
+        match error.downcast_ref::<vm::Error>() {
+            Some(vm::Error::RuntimeError { line, .. }) => assert_eq!(*line, 3),
+            _ => panic!("expected a runtime error, found {:?}", error),
+        }
+    }
+
+    #[test]
+    fn unbounded_recursion_is_a_runtime_error() {
+        assert_eq!(
+            run_err("fun f(n) { return f(n + 1) + 1; } f(0);"),
+            "Runtime error: Stack overflow."
+        );
+    }
+
+    #[test]
+    fn classes_have_fields_and_methods() {
+        assert_eq!(
+            run("class Point { init(x, y) { this.x = x; this.y = y; } sum() { return this.x + this.y; } }
+var p = Point(1, 2);
+print p.sum();
+p.x = 10;
+var sum = p.sum;
+print sum();
+print p;"),
+            "3\n12\nPoint instance\n"
+        );
+    }
+
+    #[test]
+    fn property_access_on_non_instances_is_a_runtime_error() {
+        assert_eq!(
+            run_err("var a = 1; print a.b;"),
+            "Runtime error: Only instances have properties, found `int`."
+        );
+        assert_eq!(
+            run_err("class A {} var a = A(); print a.b;"),
+            "Runtime error: Undefined property 'b'."
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "profile")]
+    fn reading_globals_is_the_hottest_op_in_a_loop_over_globals() {
+        let ParseResult {
+            function,
+            allocator,
+            table,
+        } = compile("var n = 100; var step = 1; var i = 0; while (i < n) { i = i + step; }")
+            .unwrap();
+
+        let mut vm = VM::new(table, allocator);
+        vm.interpret(function).unwrap();
+
+        let counts = vm.op_counts();
+
+        assert_eq!(counts[0], (vm::op::Op::GET_GLOBAL, 402));
+        assert!(counts
+            .iter()
+            .any(|(op, count)| *op == vm::op::Op::ADD && *count == 100));
+    }
+
+    #[test]
+    fn coverage_reports_the_lines_of_a_branch_that_never_ran() {
+        let ParseResult {
+            function,
+            allocator,
+            table,
+        } = compile(
+            "var a = false;
+if (a) {
+  print \"never\";
+}
+print \"done\";",
+        )
+        .unwrap();
+
+        let mut vm = VM::new(table, allocator);
+
+        vm.set_output(Box::new(CapturedOutput::default()));
+        vm.record_coverage(true);
+        vm.interpret(function).unwrap();
+
+        let report = vm.coverage().unwrap();
+
+        assert!(report.unexecuted.contains(&3));
+        assert!(report.executed.contains(&2));
+        assert!(report.executed.contains(&5));
+    }
+
+    #[test]
+    fn hexadecimal_and_binary_literals_are_integers() {
+        assert_eq!(run("print 0xFF;"), "255\n");
+        assert_eq!(run("print 0b101;"), "5\n");
+        assert_eq!(run_err("print 0xG;"), "Compile error");
+    }
+
+    #[test]
+    fn str_converts_values_to_strings() {
+        assert_eq!(run("print str(42) + \"!\";"), "42!\n");
+        assert_eq!(
+            run("print str(1.5) + str(true) + str(nil) + str(\"s\");"),
+            "1.5truenils\n"
+        );
+    }
+
+    #[test]
+    fn assert_only_stops_the_script_when_its_argument_is_falsey() {
+        assert_eq!(run("assert(true); assert(1 == 1, \"ok\"); print 1;"), "1\n");
+        assert_eq!(
+            run_err("assert(false);"),
+            "Runtime error: Assertion failed on line 1."
+        );
+        assert_eq!(
+            run_err("print 1;\nassert(nil, \"expected a value\");"),
+            "Runtime error: Assertion failed on line 2: expected a value"
+        );
+    }
+
+    #[test]
+    fn maps_store_values_under_string_keys() {
+        assert_eq!(
+            run(r#"
+                var m = {"a": 1, "b": 2};
+                print m["a"];
+                m["a"] = 3;
+                m["c" + "d"] = "four";
+                print m["a"];
+                print m["cd"];
+                print m["missing"];
+                print {"only": true};
+            "#),
+            "1\n3\nfour\nnil\n{\"only\": true}\n"
+        );
+    }
+
+    #[test]
+    fn an_array_literal_holds_its_items_in_order() {
+        assert_eq!(
+            run("var a = [1, \"two\", 3.5, [nil]]; print a; print [];"),
+            "[1, two, 3.5, [nil]]\n[]\n"
+        );
+    }
+
+    #[test]
+    fn an_index_assignment_can_be_read_back() {
+        assert_eq!(
+            run("var m = {\"a\": 1}; m[\"a\"] = 9; print m[\"a\"]; print m[\"b\"] = 2;"),
+            "9\n2\n"
+        );
+        assert_eq!(run_err("var m = {}; m[\"a\"] + 1 = 2;"), "Compile error");
+    }
+
+    #[test]
+    fn an_array_element_can_be_assigned_and_read_back() {
+        assert_eq!(
+            run("var a = [1, 2, 3]; a[0] = 9; print a[0]; print a[2] = 4; print a;"),
+            "9\n4\n[9, 2, 4]\n"
+        );
+        assert_eq!(run_err("var a = [1]; a[0] + 1 = 2;"), "Compile error");
+    }
+
+    #[test]
+    fn array_indices_must_be_integers_within_bounds() {
+        assert_eq!(
+            run_err("var a = [1, 2, 3]; a[3] = 9;"),
+            "Runtime error: Index 3 is out of bounds for an array of length 3."
+        );
+        assert_eq!(
+            run_err("print [][0];"),
+            "Runtime error: Index 0 is out of bounds for an array of length 0."
+        );
+        assert_eq!(
+            run_err("var a = [1]; a[\"0\"] = 2;"),
+            "Runtime error: Array indices must be integers, found `string`."
+        );
+    }
+
+    #[test]
+    fn a_negative_index_counts_back_from_the_end_of_an_array() {
+        assert_eq!(
+            run("var a = [10, 20, 30]; print a[-1]; a[-3] = 5; print a[0];"),
+            "30\n5\n"
+        );
+        assert_eq!(
+            run_err("var a = [10, 20, 30]; print a[-4];"),
+            "Runtime error: Index -4 is out of bounds for an array of length 3."
+        );
+        assert_eq!(
+            run_err("var a = [10, 20, 30]; a[-4] = 1;"),
+            "Runtime error: Index -4 is out of bounds for an array of length 3."
+        );
+        assert_eq!(
+            run_err("var a = [10, 20, 30]; print a[1.5];"),
+            "Runtime error: Array indices must be integers, found `number`."
+        );
+    }
+
+    #[test]
+    fn maps_only_take_string_keys() {
+        assert_eq!(
+            run_err("var m = {1: 2};"),
+            "Runtime error: Map keys must be strings, found `int`."
+        );
+        assert_eq!(
+            run_err("var m = {}; print m[nil];"),
+            "Runtime error: Map keys must be strings, found `nil`."
+        );
+        assert_eq!(
+            run_err("var m = 1; print m[\"a\"];"),
+            "Runtime error: Only arrays and maps can be indexed, found `int`."
+        );
+    }
+
+    #[test]
+    fn a_ternary_leaves_only_the_chosen_branch_on_the_stack() {
+        assert_eq!(run("print true ? 1 : 2;"), "1\n");
+        assert_eq!(run("print false ? 1 : 2;"), "2\n");
+        assert_eq!(
+            run("fun f() { var x = false ? 10 : 20; var y = 3; return x + y; } print f();"),
+            "23\n"
+        );
+    }
+
+    #[test]
+    fn a_switch_runs_only_the_matching_case() {
+        let src = |subject: &str| {
+            format!(
+                "switch ({}) {{ case 1: print \"one\"; case 2: var a = \"two\"; print a; default: print \"other\"; }} print \"done\";",
+                subject
+            )
+        };
+
+        assert_eq!(run(&src("2")), "two\ndone\n");
+        assert_eq!(run(&src("1 + 2")), "other\ndone\n");
+    }
+
+    #[test]
+    fn a_switch_without_a_match_or_default_runs_nothing() {
+        assert_eq!(
+            run("fun f(x) { var y = 1; switch (x) { case 1: y = 10; case 2: y = 20; } return y; } print f(3); print f(2);"),
+            "1\n20\n"
+        );
+    }
+
+    #[test]
+    fn numbers_can_have_an_exponent() {
+        assert_eq!(run("print 1e3;"), "1000\n");
+        assert_eq!(run("print 2.5e-1;"), "0.25\n");
+        assert_eq!(run("print 1_0E+1_0 == 100_000_000_000;"), "true\n");
+        assert_eq!(run_err("print 1e;"), "Compile error");
+    }
+
+    #[test]
+    fn a_closure_can_capture_two_upvalues() {
+        assert_eq!(
+            run("fun outer() { var a = 1; var b = 2; fun inner() { return a * 10 + b; } return inner; } print outer()();"),
+            "12\n"
+        );
+        assert_eq!(
+            run("fun counter() { var count = 0; var step = 2; fun next() { count = count + step; return count; } return next; } var c = counter(); c(); print c();"),
+            "4\n"
+        );
+    }
+
+    #[test]
+    fn a_captured_block_local_is_closed_over_when_the_block_ends() {
+        assert_eq!(
+            run("var get; var set; { var a = 1; fun g() { return a; } fun s(v) { a = v; } get = g; set = s; } set(5); print get();"),
+            "5\n"
+        );
+    }
+
+    #[test]
+    fn a_program_compiled_once_runs_in_two_vms() {
+        fn run_compiled(result: ParseResult) -> String {
+            let ParseResult {
+                function,
+                allocator,
+                table,
+            } = result;
+
+            let output = CapturedOutput::default();
+            let mut vm = VM::new(table, allocator);
+
+            vm.set_output(Box::new(output.clone()));
+            vm.interpret(function).unwrap();
+
+            output.contents()
+        }
+
+        let original = compile(
+            "var greeting = \"hi\"; fun greet(name) { fun exclaim() { return greeting + \" \" + name + \"!\"; } return exclaim(); } print greet(\"there\"); greeting = \"bye\"; print greet(\"now\");",
+        )
+        .unwrap();
+
+        let copy = original.duplicate();
+
+        assert_eq!(run_compiled(copy), "hi there!\nbye now!\n");
+        assert_eq!(run_compiled(original), "hi there!\nbye now!\n");
+    }
+
+    #[test]
+    fn calling_a_number_names_its_type() {
+        assert_eq!(
+            run_err("var a = 1.5; a(2);"),
+            "Runtime error: Can only call functions and classes, found `number`."
+        );
+    }
+
+    #[test]
+    fn an_initializer_cannot_return_a_value() {
+        assert_eq!(run_err("class A { init() { return 5; } }"), "Compile error");
+        assert_eq!(
+            run("class A { init() { this.a = 1; return; } } print A().a;"),
+            "1\n"
+        );
+    }
+
+    #[test]
+    fn sleep_blocks_for_a_number_of_milliseconds() {
+        assert_eq!(run("print sleep(1); print sleep(0.5);"), "nil\nnil\n");
+        assert_eq!(
+            run_err("sleep(-1);"),
+            "Runtime error: sleep expects a non-negative duration, found `-1`."
+        );
+        assert_eq!(
+            run_err("sleep(\"1\");"),
+            "Runtime error: sleep expects a number of milliseconds, found `string`."
+        );
+    }
+
+    #[test]
+    fn input_reads_a_line_at_a_time() {
+        assert_eq!(
+            run_with_natives("print input(); print input(); print input();", |vm| {
+                vm.set_input(Box::new(std::io::Cursor::new("hello\r\nworld")))
+            }),
+            "hello\nworld\nnil\n"
+        );
+    }
+
+    #[test]
+    fn an_embedder_can_choose_how_numbers_are_printed() {
+        let fixed = NumberFormat {
+            precision: Precision::Decimals(2),
+            ..NumberFormat::default()
+        };
+
+        assert_eq!(
+            run_with_natives(
+                "print 2.5; println(1 / 3.0); print str(4.0) + \"!\";",
+                |vm| { vm.set_number_format(fixed) }
+            ),
+            "2.50\n0.33\n4.00!\n"
+        );
+    }
+
+    #[test]
+    fn adding_a_string_to_something_else_suggests_str() {
+        assert_eq!(
+            run_err("print \"x\" + 1;"),
+            "Runtime error: Can't add `string` and `int`, convert the other operand with str() to join them."
+        );
+        assert_eq!(
+            run_err("print nil + \"x\";"),
+            "Runtime error: Can't add `nil` and `string`, convert the other operand with str() to join them."
+        );
+        assert_eq!(run("print \"x\" + str(1);"), "x1\n");
+    }
+
+    #[test]
+    fn an_empty_string_can_be_concatenated() {
+        assert_eq!(
+            run("print \"\" + \"abc\"; print \"abc\" + \"\"; print \"\" + \"\" == \"\";"),
+            "abc\nabc\ntrue\n"
+        );
+    }
+
+    #[test]
+    fn print_can_end_lines_with_crlf() {
+        assert_eq!(
+            run_with_natives("print 1; println(\"a\"); println();", |vm| {
+                vm.set_line_ending(LineEnding::CrLf)
+            }),
+            "1\r\na\r\n\r\n"
+        );
+    }
+
+    #[test]
+    fn an_if_does_not_leave_its_condition_on_the_stack() {
+        // A local declared after the `if` would read the condition if it were still on the stack
+        assert_eq!(
+            run("{ if (true) print \"then\"; var a = \"a\"; print a; }"),
+            "then\na\n"
+        );
+        assert_eq!(
+            run("{ if (false) print \"then\"; else print \"else\"; var a = \"a\"; print a; }"),
+            "else\na\n"
+        );
+        assert_eq!(
+            run("fun f() { if (true) {} var a = 1; return a; } print f();"),
+            "1\n"
+        );
+    }
+
+    #[test]
+    fn ints_and_floats_are_ordered_exactly_past_2_to_the_53() {
+        assert_eq!(
+            run("print 9007199254740993 > 9007199254740992.0;
+print 9007199254740993 < 9007199254740992.0;
+print 9007199254740992.0 < 9007199254740993;
+print 9007199254740993 == 9007199254740992.0;
+print 2 > 1.5; print -2 > -2.5; print -2 < -2.0; print 1 < 0.0 / 0.0;"),
+            "true\nfalse\ntrue\nfalse\ntrue\ntrue\nfalse\nfalse\n"
+        );
+    }
+
+    #[test]
+    fn underscores_can_separate_the_digits_of_a_number() {
+        assert_eq!(run("print 1_000;"), "1000\n");
+        assert_eq!(run("print 1_000.2_5;"), "1000.25\n");
+        assert_eq!(run_err("print 1__0;"), "Compile error");
+    }
+
+    #[test]
+    fn deeply_nested_expressions_are_a_compile_error() {
+        let depth = 5000;
+        let src = format!("print {}1{};", "(".repeat(depth), ")".repeat(depth));
+
+        assert_eq!(run_err(&src), "Compile error");
+    }
 }
@@ -0,0 +1,110 @@
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// Runs a line through the repl and returns everything the process wrote to stdout
+fn repl(line: &str) -> String {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_vision"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(line.as_bytes())
+        .unwrap();
+
+    let output = child.wait_with_output().unwrap();
+
+    assert!(output.status.success());
+
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn dump_bytecode_disassembles_every_function() {
+    let path = std::env::temp_dir().join("vision_dump_bytecode.vis");
+
+    std::fs::write(&path, "fun double(x) { return x * 2; } print double(21);").unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_vision"))
+        .arg("--dump-bytecode")
+        .arg(&path)
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+
+    assert!(stdout.contains("== <script> =="));
+    assert!(stdout.contains("== double =="));
+    assert!(stdout.contains("OP::MULTIPLY"));
+    assert!(stdout.contains("OP::RETURN"));
+    // The script is only disassembled, never run
+    assert!(!stdout.lines().any(|line| line == "42"));
+}
+
+#[test]
+fn dump_ast_prints_the_parsed_program() {
+    let path = std::env::temp_dir().join("vision_dump_ast.vis");
+
+    std::fs::write(&path, "fn main() { let a := 1; }").unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_vision"))
+        .arg("--dump-ast")
+        .arg(&path)
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+
+    assert!(stdout.starts_with("Program {"));
+    assert!(stdout.contains("Function {"));
+    assert!(stdout.contains("Let {"));
+}
+
+#[test]
+fn dump_tokens_lists_the_tokens_in_order() {
+    let path = std::env::temp_dir().join("vision_dump_tokens.vis");
+
+    std::fs::write(&path, "1 + 2;").unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_vision"))
+        .arg("--dump-tokens")
+        .arg(&path)
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+
+    assert_eq!(
+        stdout,
+        "   1 Number '1' 0..1
+   1 Plus '+' 2..3
+   1 Number '2' 4..5
+   1 SemiColon ';' 5..6
+   1 Eof '' 6..6
+"
+    );
+}
+
+#[test]
+fn a_program_only_prints_what_it_asks_to() {
+    assert_eq!(
+        repl("var a = 1.5; fun f(x) { return x * 2; } print f(21); print a;\n"),
+        "> 42\n1.5\n> \n"
+    );
+}
+
+#[test]
+fn the_repl_keeps_going_after_an_error_until_end_of_input() {
+    assert_eq!(repl("print -nil;\nprint 1;\n"), "> > 1\n> \n");
+}
@@ -1,14 +1,217 @@
-#[cfg(feature = "debug")]
-use crate::op::{self, Op};
+use crate::op::Op;
 use crate::value::Value;
-#[cfg(feature = "debug")]
 use crate::vm::print_value;
+use crate::ObjectType;
+use std::collections::HashMap;
+use std::fmt::{self, Display};
 use std::ops::Index;
+use std::rc::Rc;
+
+/// The bytes every serialized chunk starts with
+const MAGIC: &[u8; 4] = b"VSNC";
+/// Bumped whenever the layout of a serialized chunk changes
+const VERSION: u8 = 3;
+
+const TAG_NIL: u8 = 0;
+const TAG_BOOL: u8 = 1;
+const TAG_INT: u8 = 2;
+const TAG_NUMBER: u8 = 3;
+const TAG_OBJECT: u8 = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DecodeError {
+    /// The bytes don't start with the chunk magic header
+    BadMagic,
+    UnsupportedVersion(u8),
+    UnexpectedEof,
+    InvalidConstant(u8),
+    /// Object constants are written out but can't be loaded back yet
+    UnsupportedConstant(ObjectType),
+}
+
+impl Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::BadMagic => write!(f, "Not a serialized chunk"),
+            DecodeError::UnsupportedVersion(version) => {
+                write!(f, "Unsupported chunk version {}", version)
+            }
+            DecodeError::UnexpectedEof => write!(f, "Unexpected end of chunk"),
+            DecodeError::InvalidConstant(tag) => write!(f, "Invalid constant tag {}", tag),
+            DecodeError::UnsupportedConstant(ty) => {
+                write!(f, "{:?} constants can't be deserialized yet", ty)
+            }
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+/// Reads the fields of a serialized chunk in order
+struct Decoder<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Decoder<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], DecodeError> {
+        if self.bytes.len() < n {
+            return Err(DecodeError::UnexpectedEof);
+        }
+
+        let (taken, rest) = self.bytes.split_at(n);
+        self.bytes = rest;
+
+        Ok(taken)
+    }
+
+    fn u8(&mut self) -> Result<u8, DecodeError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, DecodeError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64, DecodeError> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn len(&mut self) -> Result<usize, DecodeError> {
+        Ok(self.u32()? as usize)
+    }
+
+    fn constants(&mut self) -> Result<Vec<Value>, DecodeError> {
+        let length = self.len()?;
+        let mut constants = Vec::with_capacity(length.min(u8::MAX as usize + 1));
+
+        for _ in 0..length {
+            let constant = match self.u8()? {
+                TAG_NIL => Value::nil(),
+                TAG_BOOL => Value::bool(self.u8()? != 0),
+                TAG_INT => Value::int(self.u64()? as i64),
+                TAG_NUMBER => Value::number(f64::from_bits(self.u64()?)),
+                TAG_OBJECT => {
+                    let ty = match self.u8()? {
+                        0 => ObjectType::String,
+                        1 => ObjectType::Function,
+                        2 => ObjectType::Native,
+                        3 => ObjectType::Closure,
+                        4 => ObjectType::UpValue,
+                        5 => ObjectType::Class,
+                        6 => ObjectType::Instance,
+                        7 => ObjectType::BoundMethod,
+                        8 => ObjectType::Map,
+                        9 => ObjectType::Array,
+                        _ => return Err(DecodeError::InvalidConstant(TAG_OBJECT)),
+                    };
+
+                    return Err(DecodeError::UnsupportedConstant(ty));
+                }
+                tag => return Err(DecodeError::InvalidConstant(tag)),
+            };
+
+            constants.push(constant);
+        }
+
+        Ok(constants)
+    }
+}
+
+/// Writes the length of `constants` followed by each constant as its tag byte and value
+fn write_constants(bytes: &mut Vec<u8>, constants: &[Value]) {
+    bytes.extend_from_slice(&(constants.len() as u32).to_le_bytes());
+
+    for constant in constants {
+        if constant.is_nil() {
+            bytes.push(TAG_NIL);
+        } else if constant.is_bool() {
+            bytes.push(TAG_BOOL);
+            bytes.push(constant.as_bool() as u8);
+        } else if constant.is_int() {
+            bytes.push(TAG_INT);
+            bytes.extend_from_slice(&constant.as_int().to_le_bytes());
+        } else if constant.is_number() {
+            bytes.push(TAG_NUMBER);
+            bytes.extend_from_slice(&constant.as_number().to_bits().to_le_bytes());
+        } else {
+            bytes.push(TAG_OBJECT);
+            bytes.push(constant.obj_type() as u8);
+        }
+    }
+}
+
+/// Builds the constants that every chunk of a program can refer to with `Op::SHARED_CONSTANT`, so a
+/// value used by many functions is only stored once. Once compilation ends the pool is frozen into
+/// `SharedConstants`.
+///
+/// Objects are never added, the collector doesn't trace the pool.
+#[derive(Debug, Default, PartialEq)]
+pub struct ConstantPool {
+    values: Vec<Value>,
+    /// Values are keyed by their exact representation, `Value`'s equality treats `1` and `1.0` as
+    /// the same constant
+    indices: HashMap<(u8, u64), usize>,
+}
+
+/// A frozen `ConstantPool`, read by the vm without any borrow checks
+pub type SharedConstants = Rc<[Value]>;
+
+impl ConstantPool {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the index of `value` in the pool, adding it if it isn't there yet
+    pub fn add(&mut self, value: Value) -> usize {
+        debug_assert!(!value.is_obj(), "objects can't be shared constants");
+
+        let key = if value.is_bool() {
+            (TAG_BOOL, value.as_bool() as u64)
+        } else if value.is_int() {
+            (TAG_INT, value.as_int() as u64)
+        } else if value.is_number() {
+            (TAG_NUMBER, value.as_number().to_bits())
+        } else {
+            (TAG_NIL, 0)
+        };
+
+        *self.indices.entry(key).or_insert_with(|| {
+            self.values.push(value);
+            self.values.len() - 1
+        })
+    }
+
+    pub fn get(&self, index: usize) -> Value {
+        self.values[index]
+    }
+
+    pub fn values(&self) -> &[Value] {
+        &self.values
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    pub fn freeze(self) -> SharedConstants {
+        self.values.into()
+    }
+}
+
 #[derive(Debug, PartialEq)]
 pub struct Chunk {
     pub code: Vec<u8>,
     pub constants: Vec<Value>,
-    pub lines: Vec<usize>,
+    /// The line of each byte of code as runs of `(line, count)`, consecutive bytes are almost always
+    /// on the same line
+    lines: Vec<(usize, usize)>,
+    /// The program-wide pool `Op::SHARED_CONSTANT` reads from, chunks that aren't part of a
+    /// compiled program don't have one
+    pub shared: Option<SharedConstants>,
 }
 
 impl Chunk {
@@ -17,12 +220,61 @@ impl Chunk {
             code: vec![],
             constants: vec![],
             lines: vec![],
+            shared: None,
         }
     }
 
+    pub fn shared_constant(&self, index: usize) -> Value {
+        self.shared
+            .as_ref()
+            .expect("a chunk with shared constants has a pool")[index]
+    }
+
     pub fn write(&mut self, byte: u8, line: usize) {
         self.code.push(byte);
-        self.lines.push(line);
+        self.push_lines(line, 1);
+    }
+
+    fn push_lines(&mut self, line: usize, count: usize) {
+        match self.lines.last_mut() {
+            Some((last, run)) if *last == line => *run += count,
+            _ => self.lines.push((line, count)),
+        }
+    }
+
+    /// The source line of the byte of code at `offset`, or 0 if there is no byte there
+    pub fn line_at(&self, offset: usize) -> usize {
+        let mut start = 0;
+
+        for &(line, count) in &self.lines {
+            start += count;
+
+            if offset < start {
+                return line;
+            }
+        }
+
+        0
+    }
+
+    /// The lines of the code as `(line, count)` runs, in the order the code was written
+    pub fn line_runs(&self) -> &[(usize, usize)] {
+        &self.lines
+    }
+
+    /// A copy of the chunk with each constant replaced by what `copy` returns for it, used to move a
+    /// chunk's objects to another heap. The copy reads its shared constants from `shared`
+    pub fn copy_with(
+        &self,
+        shared: Option<SharedConstants>,
+        copy: impl FnMut(Value) -> Value,
+    ) -> Chunk {
+        Chunk {
+            code: self.code.clone(),
+            constants: self.constants.iter().copied().map(copy).collect(),
+            lines: self.lines.clone(),
+            shared,
+        }
     }
 
     pub fn add_constant(&mut self, value: Value) -> usize {
@@ -30,7 +282,182 @@ impl Chunk {
         self.constants.len() - 1
     }
 
-    #[cfg(feature = "debug")]
+    /// Copies the code of `other` onto the end of this chunk, returning the offset it starts at.
+    ///
+    /// Constants of `other` are appended to this chunk's pool and every instruction that refers to one is
+    /// remapped. Local slots are shifted by `slot_offset` so the code can run inside a frame that already
+    /// has locals. Jumps are relative to the instruction so they are copied unchanged.
+    ///
+    /// Shared constants are copied unchanged, so `other` must use the same pool as this chunk.
+    ///
+    /// Returns `None`, leaving the chunk untouched, when the remapped operands don't fit in a byte or
+    /// the chunks have different shared pools.
+    pub fn append(&mut self, other: &Chunk, slot_offset: u8) -> Option<usize> {
+        let constant_offset = self.constants.len();
+
+        if let Some(shared) = &other.shared {
+            if !self
+                .shared
+                .as_ref()
+                .is_some_and(|own| Rc::ptr_eq(own, shared))
+            {
+                return None;
+            }
+        }
+
+        if constant_offset + other.constants.len() > u8::MAX as usize + 1 {
+            return None;
+        }
+
+        let mut code = Vec::with_capacity(other.code.len());
+
+        let mut offset = 0;
+
+        while offset < other.code.len() {
+            let instruction = other.code[offset];
+
+            code.push(instruction);
+
+            match unsafe { std::mem::transmute::<u8, Op>(instruction) } {
+                Op::CONSTANT
+                | Op::DEFINE_GLOBAL
+                | Op::GET_GLOBAL
+                | Op::SET_GLOBAL
+                | Op::CLASS
+                | Op::GET_PROPERTY
+                | Op::SET_PROPERTY
+                | Op::METHOD => {
+                    code.push(other.code[offset + 1] + constant_offset as u8);
+                    offset += 2;
+                }
+                Op::GET_LOCAL | Op::SET_LOCAL => {
+                    code.push(other.code[offset + 1].checked_add(slot_offset)?);
+                    offset += 2;
+                }
+                Op::CALL | Op::GET_UPVALUE | Op::SET_UPVALUE | Op::MAP_NEW | Op::ARRAY_NEW => {
+                    code.push(other.code[offset + 1]);
+                    offset += 2;
+                }
+                Op::JUMP | Op::JUMP_IF_FALSE | Op::LOOP | Op::SHARED_CONSTANT => {
+                    code.extend_from_slice(&other.code[offset + 1..offset + 3]);
+                    offset += 3;
+                }
+                Op::CLOSURE => {
+                    let constant = other.code[offset + 1];
+                    code.push(constant + constant_offset as u8);
+                    offset += 2;
+
+                    let function = other.constants[constant as usize].as_function();
+
+                    for _ in 0..function.upvalue_count {
+                        let is_local = other.code[offset];
+                        let index = other.code[offset + 1];
+
+                        code.push(is_local);
+
+                        if is_local == 1 {
+                            code.push(index.checked_add(slot_offset)?);
+                        } else {
+                            code.push(index);
+                        }
+
+                        offset += 2;
+                    }
+                }
+                _ => offset += 1,
+            }
+        }
+
+        let start = self.code.len();
+
+        self.code.extend(code);
+        for &(line, count) in &other.lines {
+            self.push_lines(line, count);
+        }
+        self.constants.extend_from_slice(&other.constants);
+
+        Some(start)
+    }
+
+    /// Encodes the chunk into a versioned binary format.
+    ///
+    /// The layout is the magic header and version byte followed by the code, the runs of lines, the
+    /// constant pool and the shared constants. Lengths and lines are little endian `u32`s, each run is
+    /// its line and count and each constant is a tag byte followed by its value. Object constants are only written
+    /// as their tag and type, `Chunk::deserialize` rejects them.
+    ///
+    /// The whole shared pool is written, a deserialized chunk gets a pool of its own.
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(MAGIC.len() + 1 + self.code.len() * 5);
+
+        bytes.extend_from_slice(MAGIC);
+        bytes.push(VERSION);
+
+        bytes.extend_from_slice(&(self.code.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&self.code);
+
+        bytes.extend_from_slice(&(self.lines.len() as u32).to_le_bytes());
+
+        for (line, count) in &self.lines {
+            bytes.extend_from_slice(&(*line as u32).to_le_bytes());
+            bytes.extend_from_slice(&(*count as u32).to_le_bytes());
+        }
+
+        write_constants(&mut bytes, &self.constants);
+
+        match &self.shared {
+            Some(shared) => write_constants(&mut bytes, shared),
+            None => write_constants(&mut bytes, &[]),
+        }
+
+        bytes
+    }
+
+    /// Decodes a chunk written by `Chunk::serialize`
+    pub fn deserialize(bytes: &[u8]) -> Result<Chunk, DecodeError> {
+        let mut decoder = Decoder { bytes };
+
+        if decoder
+            .take(MAGIC.len())
+            .map_err(|_| DecodeError::BadMagic)?
+            != MAGIC
+        {
+            return Err(DecodeError::BadMagic);
+        }
+
+        let version = decoder.u8()?;
+
+        if version != VERSION {
+            return Err(DecodeError::UnsupportedVersion(version));
+        }
+
+        let length = decoder.len()?;
+        let code = decoder.take(length)?.to_vec();
+
+        let length = decoder.len()?;
+        let mut lines = Vec::with_capacity(length.min(code.len()));
+
+        for _ in 0..length {
+            lines.push((decoder.u32()? as usize, decoder.u32()? as usize));
+        }
+
+        let constants = decoder.constants()?;
+
+        let shared = decoder.constants()?;
+        let shared = if shared.is_empty() {
+            None
+        } else {
+            Some(shared.into())
+        };
+
+        Ok(Chunk {
+            code,
+            constants,
+            lines,
+            shared,
+        })
+    }
+
     pub fn disassemble(&self, name: &str) {
         println!("== {} ==\n", name);
 
@@ -40,20 +467,24 @@ impl Chunk {
             i = self.disassemble_instruction(i);
         }
     }
-    #[cfg(feature = "debug")]
     pub fn disassemble_instruction(&self, offset: usize) -> usize {
         print!("{:04} ", offset);
 
-        if offset > 0 && self.lines.get(offset) == self.lines.get(offset - 1) {
+        if offset > 0 && self.line_at(offset) == self.line_at(offset - 1) {
             print!("   | ");
         } else {
-            print!("{:4} ", self.lines.get(offset).unwrap());
+            print!("{:4} ", self.line_at(offset));
         }
 
         let instruction = self.code[offset];
 
+        if instruction > Op::SWAP as u8 {
+            println!("Unknown opcode {}", instruction);
+            return offset + 1;
+        }
+
         unsafe {
-            match std::mem::transmute(instruction) {
+            match std::mem::transmute::<u8, Op>(instruction) {
                 Op::RETURN => self.simple_instruction("OP::RETURN", offset),
                 Op::CONSTANT => self.constant_instruction("OP::CONSTANT", offset),
                 Op::NEGATE => self.simple_instruction("OP::NEGATE", offset),
@@ -74,7 +505,7 @@ impl Chunk {
                 Op::GET_GLOBAL => self.constant_instruction("OP::GET_GLOBAL", offset),
                 Op::SET_GLOBAL => self.constant_instruction("OP::SET_GLOBAL", offset),
                 Op::GET_LOCAL => self.byte_instruction("OP::GET_LOCAL", offset),
-                Op::SET_LOCAL => self.byte_instruction("OP::GET_LOCAL", offset),
+                Op::SET_LOCAL => self.byte_instruction("OP::SET_LOCAL", offset),
                 Op::JUMP => self.jump_instruction("op::JUMP", 1, offset),
                 Op::JUMP_IF_FALSE => self.jump_instruction("op::JUMP_IF_FALSE", 1, offset),
                 Op::LOOP => self.jump_instruction("OP::LOOP", -1, offset),
@@ -99,8 +530,8 @@ impl Chunk {
                         let index = self.code[offset];
                         offset += 1;
 
-                        print!(
-                            "{:4}    |                     {} {}\n",
+                        println!(
+                            "{:4}    |                     {} {}",
                             offset - 2,
                             if is_local == 1 { "local" } else { "upvalue" },
                             index
@@ -112,19 +543,25 @@ impl Chunk {
                 Op::GET_UPVALUE => self.byte_instruction("OP::GET_UPVALUE", offset),
                 Op::SET_UPVALUE => self.byte_instruction("OP::SET_UPVALUE", offset),
                 Op::CLOSE_UPVALUE => self.simple_instruction("OP::CLOSE_UP_VALUE", offset),
-                _ => {
-                    println!("Unknown opcode {}", instruction);
-                    offset + 1
-                }
+                Op::CLASS => self.constant_instruction("OP::CLASS", offset),
+                Op::GET_PROPERTY => self.constant_instruction("OP::GET_PROPERTY", offset),
+                Op::SET_PROPERTY => self.constant_instruction("OP::SET_PROPERTY", offset),
+                Op::METHOD => self.constant_instruction("OP::METHOD", offset),
+                Op::MAP_NEW => self.byte_instruction("OP::MAP_NEW", offset),
+                Op::INDEX_GET => self.simple_instruction("OP::INDEX_GET", offset),
+                Op::INDEX_SET => self.simple_instruction("OP::INDEX_SET", offset),
+                Op::SHARED_CONSTANT => self.shared_constant_instruction(offset),
+                Op::ARRAY_NEW => self.byte_instruction("OP::ARRAY_NEW", offset),
+                Op::TRAP => self.simple_instruction("OP::TRAP", offset),
+                Op::DUP => self.simple_instruction("OP::DUP", offset),
+                Op::SWAP => self.simple_instruction("OP::SWAP", offset),
             }
         }
     }
-    #[cfg(feature = "debug")]
     fn simple_instruction(&self, name: &str, offset: usize) -> usize {
         println!("{}", name);
         offset + 1
     }
-    #[cfg(feature = "debug")]
     pub fn constant_instruction(&self, name: &str, offset: usize) -> usize {
         let constant = self.code[offset + 1];
         print!("{:16}{:4} '", name, constant);
@@ -132,13 +569,18 @@ impl Chunk {
         println!("'");
         offset + 2
     }
-    #[cfg(feature = "debug")]
+    fn shared_constant_instruction(&self, offset: usize) -> usize {
+        let constant = (self.code[offset + 1] as usize) << 8 | self.code[offset + 2] as usize;
+        print!("{:16}{:4} '", "OP::SHARED_CONSTANT", constant);
+        print_value(self.shared_constant(constant));
+        println!("'");
+        offset + 3
+    }
     pub(crate) fn byte_instruction(&self, arg: &str, offset: usize) -> usize {
         let slot = self.code[offset + 1];
         println!("{:16}{:4} ", arg, slot);
         offset + 2
     }
-    #[cfg(feature = "debug")]
     pub(crate) fn jump_instruction(&self, arg: &str, sign: isize, offset: usize) -> usize {
         let mut jump = ((self.code[offset + 1] as u16) << 8) as usize;
         jump |= self.code[offset + 2] as usize;
@@ -159,3 +601,217 @@ impl Index<usize> for Chunk {
         &self.code[index]
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::{Chunk, ConstantPool, DecodeError};
+    use crate::{
+        op::Op, value::Value, Allocator, FunctionObject, ObjectPtr, ObjectType, RawObject,
+    };
+
+    #[test]
+    fn it_remaps_constants_slots_and_keeps_jumps_relative() {
+        let mut caller = Chunk::new();
+        let first = caller.add_constant(Value::int(1));
+        caller.write(Op::CONSTANT as u8, 1);
+        caller.write(first as u8, 1);
+
+        let mut callee = Chunk::new();
+        let constant = callee.add_constant(Value::int(2));
+        callee.write(Op::GET_LOCAL as u8, 2);
+        callee.write(1, 2);
+        callee.write(Op::JUMP_IF_FALSE as u8, 2);
+        callee.write(0, 2);
+        callee.write(2, 2);
+        callee.write(Op::CONSTANT as u8, 3);
+        callee.write(constant as u8, 3);
+        callee.write(Op::RETURN as u8, 4);
+
+        let start = caller.append(&callee, 3).unwrap();
+
+        assert_eq!(start, 2);
+        assert_eq!(caller.constants, vec![Value::int(1), Value::int(2)]);
+        assert_eq!(
+            caller.code,
+            vec![
+                Op::CONSTANT as u8,
+                0,
+                Op::GET_LOCAL as u8,
+                4,
+                Op::JUMP_IF_FALSE as u8,
+                0,
+                2,
+                Op::CONSTANT as u8,
+                1,
+                Op::RETURN as u8,
+            ]
+        );
+        assert_eq!(caller.line_runs(), [(1, 2), (2, 5), (3, 2), (4, 1)]);
+
+        // The jump skips the constant in both chunks
+        let jump = start + 2;
+        let target = jump + 3 + 2;
+        assert_eq!(caller.code[target], Op::RETURN as u8);
+    }
+
+    #[test]
+    fn it_stores_a_run_of_bytes_on_one_line_once() {
+        let mut chunk = Chunk::new();
+
+        for _ in 0..1000 {
+            chunk.write(Op::NIL as u8, 7);
+        }
+
+        chunk.write(Op::POP as u8, 8);
+        chunk.write(Op::RETURN as u8, 10);
+
+        assert_eq!(chunk.line_runs(), [(7, 1000), (8, 1), (10, 1)]);
+
+        assert_eq!(chunk.line_at(0), 7);
+        assert_eq!(chunk.line_at(999), 7);
+        assert_eq!(chunk.line_at(1000), 8);
+        assert_eq!(chunk.line_at(1001), 10);
+        assert_eq!(chunk.line_at(1002), 0);
+    }
+
+    #[test]
+    fn it_refuses_to_overflow_the_constant_pool() {
+        let mut caller = Chunk::new();
+
+        for i in 0..200 {
+            caller.add_constant(Value::int(i));
+        }
+
+        let mut callee = Chunk::new();
+
+        for i in 0..100 {
+            callee.add_constant(Value::int(i));
+        }
+
+        callee.write(Op::RETURN as u8, 1);
+
+        assert_eq!(caller.append(&callee, 0), None);
+        assert!(caller.code.is_empty());
+    }
+
+    #[test]
+    fn it_round_trips_a_chunk() {
+        let mut chunk = Chunk::new();
+
+        for (i, constant) in [
+            Value::int(-7),
+            Value::number(2.5),
+            Value::bool(true),
+            Value::nil(),
+        ]
+        .iter()
+        .enumerate()
+        {
+            chunk.add_constant(*constant);
+            chunk.write(Op::CONSTANT as u8, i + 1);
+            chunk.write(i as u8, i + 1);
+        }
+
+        chunk.write(Op::RETURN as u8, 300);
+
+        let decoded = Chunk::deserialize(&chunk.serialize()).unwrap();
+
+        assert_eq!(decoded, chunk);
+    }
+
+    #[test]
+    fn it_shares_each_constant_once_and_writes_the_pool_out() {
+        let mut pool = ConstantPool::new();
+        let mut chunk = Chunk::new();
+
+        for value in [Value::int(1), Value::number(1.0), Value::int(1)] {
+            let index = pool.add(value);
+
+            chunk.write(Op::SHARED_CONSTANT as u8, 1);
+            chunk.write(0, 1);
+            chunk.write(index as u8, 1);
+        }
+
+        assert_eq!(pool.len(), 2);
+
+        chunk.shared = Some(pool.freeze());
+
+        assert!(chunk.shared_constant(1).is_number());
+
+        let decoded = Chunk::deserialize(&chunk.serialize()).unwrap();
+
+        assert_eq!(decoded, chunk);
+
+        // Shared operands can't be copied into a chunk with another pool
+        assert_eq!(Chunk::new().append(&chunk, 0), None);
+    }
+
+    #[test]
+    fn it_rejects_malformed_chunks() {
+        let mut bytes = Chunk::new().serialize();
+
+        assert_eq!(Chunk::deserialize(b"VSN"), Err(DecodeError::BadMagic));
+        assert_eq!(Chunk::deserialize(b"nope"), Err(DecodeError::BadMagic));
+        assert_eq!(
+            Chunk::deserialize(&bytes[..bytes.len() - 1]),
+            Err(DecodeError::UnexpectedEof)
+        );
+
+        bytes[4] = 99;
+        assert_eq!(
+            Chunk::deserialize(&bytes),
+            Err(DecodeError::UnsupportedVersion(99))
+        );
+    }
+
+    #[test]
+    fn it_disassembles_a_closure_and_its_upvalues_without_losing_its_place() {
+        let mut allocator = Allocator::new();
+
+        let mut function = allocator.alloc(FunctionObject::new(None));
+        function.upvalue_count = 2;
+
+        let mut chunk = Chunk::new();
+        let constant = chunk.add_constant(Value::object(function.into()));
+
+        // A closure capturing local slot 1 and the enclosing function's upvalue 0
+        chunk.write(Op::CLOSURE as u8, 1);
+        chunk.write(constant as u8, 1);
+        chunk.write(1, 1);
+        chunk.write(1, 1);
+        chunk.write(0, 1);
+        chunk.write(0, 1);
+        chunk.write(Op::GET_UPVALUE as u8, 2);
+        chunk.write(0, 2);
+        chunk.write(Op::SET_UPVALUE as u8, 2);
+        chunk.write(1, 2);
+        chunk.write(Op::CLOSE_UPVALUE as u8, 3);
+        chunk.write(Op::RETURN as u8, 3);
+
+        let mut offsets = vec![];
+        let mut offset = 0;
+
+        while offset < chunk.code.len() {
+            offsets.push(offset);
+            offset = chunk.disassemble_instruction(offset);
+        }
+
+        assert_eq!(offsets, [0, 6, 8, 10, 11]);
+        assert_eq!(offset, chunk.code.len());
+        assert_eq!(chunk.code[11], Op::RETURN as u8);
+    }
+
+    #[test]
+    fn it_flags_object_constants_as_unsupported() {
+        let mut chunk = Chunk::new();
+        let function: ObjectPtr<RawObject> =
+            Allocator::new().alloc(FunctionObject::new(None)).into();
+
+        chunk.add_constant(Value::object(function));
+
+        assert_eq!(
+            Chunk::deserialize(&chunk.serialize()),
+            Err(DecodeError::UnsupportedConstant(ObjectType::Function))
+        );
+    }
+}
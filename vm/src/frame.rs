@@ -1,21 +1,29 @@
-use crate::{Allocator, ClosureObject, FunctionObject, ObjectPtr, RawObject};
+use crate::{Allocator, ClosureObject, FunctionObject, ObjectPtr};
 #[derive(Debug)]
 pub struct CallFrame<'a> {
     pub closure: ObjectPtr<ClosureObject<'a>>,
     pub ip: usize,
+    /// The offset of the instruction being run, `ip` has already moved past it and its operands
+    pub instruction: usize,
     /// The slots field points into the VM’s value stack at the first slot that this function can use
     pub slots: usize,
 }
 
 impl<'a> CallFrame<'a> {
     pub fn new(allocator: &mut Allocator) -> Self {
-        let fn_object = allocator.alloc(|next| FunctionObject::new(None, next));
+        let fn_object = allocator.alloc(FunctionObject::new(None));
 
-        let closure = allocator.alloc(move |next| ClosureObject::new(fn_object, next));
+        let closure = allocator.alloc(ClosureObject::new(fn_object));
         Self {
             closure,
             ip: 0,
+            instruction: 0,
             slots: 0,
         }
     }
+
+    /// The source line of the instruction being run, or 0 if the chunk has no line for it
+    pub fn line(&self) -> usize {
+        self.closure.function.chunk.line_at(self.instruction)
+    }
 }
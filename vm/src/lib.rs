@@ -8,9 +8,11 @@ mod table;
 mod value;
 mod vm;
 pub use {
-    crate::vm::{Error, VM},
+    crate::vm::{
+        print_value, write_value, CapturedOutput, Checkpoint, CoverageReport, Error, LineEnding, VM,
+    },
     memory::Allocator,
     object::*,
     table::*,
-    value::Value,
+    value::{Formatted, NumberFormat, Precision, Value},
 };
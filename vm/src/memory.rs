@@ -1,26 +1,97 @@
-use crate::{ObjectPtr, RawObject};
-use std::fmt::Debug;
+use crate::{
+    object::{hash_string, ObjectTag},
+    ObjectPtr, RawObject, StringObject, Table, Value,
+};
+/// The number of bytes that can be allocated before the first collection
+const INITIAL_GC_THRESHOLD: usize = 1024 * 1024;
+/// How much the heap can grow, relative to what survived, before the next collection
+const GC_HEAP_GROW_FACTOR: usize = 2;
+
 #[derive(Debug, Clone, Copy)]
 pub struct Allocator {
     root: RawObject,
+    bytes_allocated: usize,
+    object_count: usize,
+    next_gc: usize,
 }
 
 impl Allocator {
     pub fn new() -> Self {
         let root = std::ptr::null::<RawObject>() as RawObject;
 
-        Self { root: root }
+        Self {
+            root: root,
+            bytes_allocated: 0,
+            object_count: 0,
+            next_gc: INITIAL_GC_THRESHOLD,
+        }
+    }
+
+    /// Moves `object` to the heap and links it into the list of every object, which is the only
+    /// place objects are created
+    pub fn alloc<T: ObjectTag>(&mut self, object: T) -> ObjectPtr<T> {
+        let ptr = Box::into_raw(Box::new(object)) as RawObject;
+
+        // Every object starts with its header so the pointer can be used as one
+        unsafe { (*ptr).next = self.root };
+
+        self.root = ptr;
+        self.bytes_allocated += std::mem::size_of::<T>();
+        self.object_count += 1;
+
+        ObjectPtr::new(ptr)
     }
 
-    pub fn alloc<T: ?Sized + Debug, F: FnOnce(RawObject) -> ObjectPtr<T>>(
+    /// Interns `string`, allocating a new string object only when `table` doesn't hold it yet
+    pub fn alloc_string<'a>(
         &mut self,
-        init_obj: F,
-    ) -> ObjectPtr<T> {
-        let allocated_obj = init_obj(self.root);
+        string: &str,
+        table: &mut Table,
+    ) -> ObjectPtr<StringObject<'a>> {
+        let mut buffer = String::with_capacity(string.len() + 1);
+
+        buffer.push_str(string);
+        buffer.push('\0');
+
+        self.alloc_owned_string(buffer, table)
+    }
+
+    /// Like `alloc_string` but takes ownership of `chars`, which must already end in a `\0`
+    pub fn alloc_owned_string<'a>(
+        &mut self,
+        chars: String,
+        table: &mut Table,
+    ) -> ObjectPtr<StringObject<'a>> {
+        let hash = hash_string(&chars);
+
+        if let Some(interned) = table.find_string(&chars, hash) {
+            return ObjectPtr::new(interned);
+        }
 
-        self.root = allocated_obj.raw();
+        let string = self.alloc(StringObject::new(chars, hash));
 
-        allocated_obj
+        table.set(string.raw(), Value::nil());
+
+        string
+    }
+
+    /// The size of the object headers allocated so far, not counting the strings and chunks they own
+    pub fn bytes_allocated(&self) -> usize {
+        self.bytes_allocated
+    }
+
+    pub fn object_count(&self) -> usize {
+        self.object_count
+    }
+
+    /// Whether enough has been allocated since the last collection that it's worth running another
+    pub fn should_collect(&self) -> bool {
+        self.bytes_allocated > self.next_gc
+    }
+
+    /// Raises the threshold after a collection so the next one waits for the heap to grow again
+    pub fn grow_threshold(&mut self) {
+        self.next_gc = (self.bytes_allocated * GC_HEAP_GROW_FACTOR).max(INITIAL_GC_THRESHOLD);
     }
 
     pub fn finish(self) -> RawObject {
@@ -31,14 +102,17 @@ impl Allocator {
 #[cfg(test)]
 mod test {
     use super::Allocator;
-    use crate::FunctionObject;
+    use crate::{
+        Arity, ArrayObject, ClassObject, ClosureObject, FunctionObject, MapObject, Native,
+        NativeObject, RawObject, Table, UpValueObject, Value,
+    };
 
     #[test]
     fn it_works() {
         let mut alloc = Allocator::new();
 
-        alloc.alloc(|next| FunctionObject::new(None, next));
-        alloc.alloc(|next| FunctionObject::new(None, next));
+        alloc.alloc(FunctionObject::new(None));
+        alloc.alloc(FunctionObject::new(None));
 
         let mut root = alloc.finish();
 
@@ -57,4 +131,86 @@ mod test {
 
         assert_eq!(count, 2)
     }
+
+    #[test]
+    fn it_counts_each_allocation() {
+        let mut alloc = Allocator::new();
+
+        assert_eq!(alloc.object_count(), 0);
+        assert_eq!(alloc.bytes_allocated(), 0);
+
+        for count in 1..=3 {
+            alloc.alloc(FunctionObject::new(None));
+
+            assert_eq!(alloc.object_count(), count);
+            assert_eq!(
+                alloc.bytes_allocated(),
+                count * std::mem::size_of::<FunctionObject>()
+            );
+        }
+
+        assert!(!alloc.should_collect());
+    }
+
+    #[test]
+    fn it_links_each_object_to_the_one_allocated_before() {
+        let mut alloc = Allocator::new();
+        let mut table = Table::new();
+
+        let first = alloc.alloc(FunctionObject::new(None)).raw();
+        let second = alloc.alloc_string("a", &mut table).raw();
+        let third = alloc
+            .alloc(NativeObject::new(
+                Native::Slice(|_| Value::nil()),
+                Arity::Any,
+            ))
+            .raw();
+
+        let root = alloc.finish();
+
+        assert_eq!(root, third);
+
+        unsafe {
+            assert_eq!((*third).next, second);
+            assert_eq!((*second).next, first);
+            assert!((*first).next.is_null());
+        }
+    }
+
+    #[test]
+    fn every_allocated_object_can_be_reached_from_the_root() {
+        let mut alloc = Allocator::new();
+        let mut table = Table::new();
+
+        let name = alloc.alloc_string("name", &mut table);
+        let function = alloc.alloc(FunctionObject::new(Some(name)));
+
+        let mut allocated = vec![
+            name.raw(),
+            function.raw(),
+            alloc.alloc(ClosureObject::new(function)).raw(),
+            alloc.alloc(ClassObject::new(name)).raw(),
+        ];
+
+        // Interning a string that already exists must not touch the list
+        assert_eq!(alloc.alloc_string("name", &mut table).raw(), name.raw());
+
+        allocated.push(alloc.alloc(MapObject::new()).raw());
+        allocated.push(alloc.alloc(ArrayObject::new(vec![Value::int(1)])).raw());
+        allocated.push(alloc.alloc(UpValueObject::new(Value::nil())).raw());
+
+        assert_eq!(alloc.object_count(), allocated.len());
+
+        let mut reached: Vec<RawObject> = Vec::new();
+        let mut object = alloc.finish();
+
+        while !object.is_null() {
+            reached.push(object);
+            object = unsafe { (*object).next };
+        }
+
+        reached.reverse();
+
+        assert_eq!(reached, allocated);
+    }
 }
@@ -1,9 +1,114 @@
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::io::{BufRead, Write};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-use crate::Value;
+use crate::{vm::write_value, Value, VM};
 
-pub fn clock_native(_arg_count: usize, _args: *const Value) -> Value {
+pub fn clock_native(_vm: &mut VM<'_>, _arg_count: usize, _args: *const Value) -> Value {
     let time = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
 
     Value::number(time.as_secs() as f64 + f64::from(time.subsec_nanos()) * 1e-9)
 }
+
+/// Blocks for its argument in milliseconds, which has to be a non-negative int or number
+pub fn sleep_native(vm: &mut VM<'_>, _arg_count: usize, args: *const Value) -> Value {
+    let ms = unsafe { *args };
+
+    if !ms.is_numeric() {
+        vm.raise(format!(
+            "sleep expects a number of milliseconds, found `{}`.",
+            ms.type_name()
+        ));
+        return Value::nil();
+    }
+
+    // Negative, infinite and NaN durations can't be turned into a `Duration`
+    match Duration::try_from_secs_f64(ms.as_float() / 1000.0) {
+        Ok(duration) => std::thread::sleep(duration),
+        Err(_) => vm.raise(format!(
+            "sleep expects a non-negative duration, found `{}`.",
+            ms
+        )),
+    }
+
+    Value::nil()
+}
+
+/// Prints its argument on its own line and hands it back, so it can be used inside an expression
+pub fn println_native(vm: &mut VM<'_>, arg_count: usize, args: *const Value) -> Value {
+    if arg_count == 0 {
+        let _ = write!(vm.out, "{}", vm.line_ending.as_str());
+        return Value::nil();
+    }
+
+    let value = unsafe { *args };
+
+    let _ = write_value(&mut vm.out, value, &vm.number_format);
+    let _ = write!(vm.out, "{}", vm.line_ending.as_str());
+
+    value
+}
+
+/// Converts its argument to the text `print` would show for it, a missing argument converts as `nil`
+pub fn str_native(vm: &mut VM<'_>, arg_count: usize, args: *const Value) -> Value {
+    let value = if arg_count == 0 {
+        Value::nil()
+    } else {
+        unsafe { *args }
+    };
+
+    if value.is_string() {
+        return value;
+    }
+
+    let mut chars = value.formatted(&vm.number_format).to_string();
+    chars.push('\0');
+
+    let string_object = vm.allocator.alloc_owned_string(chars, &mut vm.strings);
+
+    Value::object(string_object.into())
+}
+
+/// Reads a line from the vm's input without its line ending, or `nil` once the input runs out
+pub fn input_native(vm: &mut VM<'_>, _arg_count: usize, _args: *const Value) -> Value {
+    let mut line = String::new();
+
+    match vm.input.read_line(&mut line) {
+        Ok(0) => return Value::nil(),
+        Ok(_) => (),
+        Err(e) => {
+            vm.raise(format!("Couldn't read input: {}", e));
+            return Value::nil();
+        }
+    }
+
+    if line.ends_with('\n') {
+        line.pop();
+
+        if line.ends_with('\r') {
+            line.pop();
+        }
+    }
+
+    line.push('\0');
+
+    let string_object = vm.allocator.alloc_owned_string(line, &mut vm.strings);
+
+    Value::object(string_object.into())
+}
+
+/// Raises a runtime error when its first argument is falsey, an optional second argument is added
+/// to the message
+pub fn assert_native(vm: &mut VM<'_>, arg_count: usize, args: *const Value) -> Value {
+    let args = unsafe { std::slice::from_raw_parts(args, arg_count) };
+
+    if args.first().is_none_or(Value::is_falsey) {
+        let line = vm.frames[vm.frame_count - 1].line();
+
+        match args.get(1) {
+            Some(message) => vm.raise(format!("Assertion failed on line {}: {}", line, message)),
+            None => vm.raise(format!("Assertion failed on line {}.", line)),
+        }
+    }
+
+    Value::nil()
+}
@@ -1,11 +1,20 @@
 use std::{
-    fmt::Debug,
+    fmt::{self, Debug, Display},
     ops::{Deref, DerefMut},
 };
 
-use crate::{chunk::Chunk, Table, Value};
-
-pub type NativeFn = fn(usize, *const Value) -> Value;
+use crate::{chunk::Chunk, Table, Value, VM};
+
+/// A function implemented in Rust that scripts can call.
+///
+/// It is called with the vm, the number of arguments and a pointer to the first argument. The arguments
+/// sit next to each other on the vm's stack so `args` is valid for reading `arg_count` values, and only
+/// until the native returns. `std::slice::from_raw_parts(args, arg_count)` is the simplest way to read
+/// them. The arity isn't checked before the call so the native has to handle any count itself.
+/// The returned value is what the call evaluates to.
+pub type NativeFn = fn(&mut VM<'_>, usize, *const Value) -> Value;
+/// A native that receives its arguments as a slice, for natives that don't need the vm
+pub type SliceNativeFn = fn(&[Value]) -> Value;
 pub type RawObject = *mut Object;
 pub type ValuePtr = *const Value;
 
@@ -49,11 +58,47 @@ pub struct FunctionObject<'a> {
     pub name: Option<ObjectPtr<StringObject<'a>>>,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, Copy)]
+pub enum Native {
+    Raw(NativeFn),
+    Slice(SliceNativeFn),
+}
+
+/// How many arguments a native accepts, calls with any other count are a runtime error
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Arity {
+    Exactly(usize),
+    /// Between the two counts, inclusive
+    Range(usize, usize),
+    Any,
+}
+
+impl Arity {
+    pub fn accepts(&self, arg_count: usize) -> bool {
+        match *self {
+            Arity::Exactly(arity) => arg_count == arity,
+            Arity::Range(min, max) => (min..=max).contains(&arg_count),
+            Arity::Any => true,
+        }
+    }
+}
+
+impl Display for Arity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Arity::Exactly(arity) => write!(f, "{}", arity),
+            Arity::Range(min, max) => write!(f, "{} to {}", min, max),
+            Arity::Any => write!(f, "any number of"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 #[repr(C)]
 pub struct NativeObject {
     pub obj: Object,
-    pub function: NativeFn,
+    pub function: Native,
+    pub arity: Arity,
 }
 #[derive(Debug)]
 #[repr(C)]
@@ -64,6 +109,48 @@ pub struct ClosureObject<'a> {
     pub upvalue_count: usize,
 }
 
+/// A class and the methods declared in it, keyed by their interned name
+#[derive(Debug)]
+#[repr(C)]
+pub struct ClassObject<'a> {
+    pub obj: Object,
+    pub name: ObjectPtr<StringObject<'a>>,
+    pub methods: Table,
+}
+
+#[derive(Debug)]
+#[repr(C)]
+pub struct InstanceObject<'a> {
+    pub obj: Object,
+    pub class: ObjectPtr<ClassObject<'a>>,
+    pub fields: Table,
+}
+
+/// A method that has been read off an instance, it remembers the instance it will be called on
+#[derive(Debug)]
+#[repr(C)]
+pub struct BoundMethodObject<'a> {
+    pub obj: Object,
+    pub receiver: Value,
+    pub method: ObjectPtr<ClosureObject<'a>>,
+}
+
+/// A dictionary from strings to values, keyed by the interned string like the fields of an instance
+#[derive(Debug)]
+#[repr(C)]
+pub struct MapObject {
+    pub obj: Object,
+    pub entries: Table,
+}
+
+/// A growable list of values, indexed from zero
+#[derive(Debug)]
+#[repr(C)]
+pub struct ArrayObject {
+    pub obj: Object,
+    pub items: Vec<Value>,
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 
 pub enum ObjectType {
@@ -72,80 +159,89 @@ pub enum ObjectType {
     Native,
     Closure,
     UpValue,
+    Class,
+    Instance,
+    BoundMethod,
+    Map,
+    Array,
 }
 
-impl Object {
-    pub fn new(ty: ObjectType, next: RawObject) -> Self {
-        Object { ty, next }
-    }
+/// Links an object struct to the `ObjectType` stored in its header so a cast can check it
+pub trait ObjectTag: Debug {
+    const TYPE: ObjectType;
 }
 
-fn hash_string(string: &str) -> usize {
-    let mut hash = 2166136261usize;
-
-    for c in string.chars() {
-        hash ^= c as usize;
-        hash = hash.wrapping_mul(16777619);
-    }
-
-    hash
+impl<'a> ObjectTag for StringObject<'a> {
+    const TYPE: ObjectType = ObjectType::String;
 }
 
-impl<'a> StringObject<'a> {
-    /// Create a new string Object that dosen't take ownership of the string passed in
+impl<'a> ObjectTag for FunctionObject<'a> {
+    const TYPE: ObjectType = ObjectType::Function;
+}
 
-    pub fn new(string: &'a str, table: &mut Table, next: RawObject) -> ObjectPtr<StringObject<'a>> {
-        let mut buffer = String::with_capacity(string.len() + 1);
+impl ObjectTag for NativeObject {
+    const TYPE: ObjectType = ObjectType::Native;
+}
 
-        buffer.push_str(string);
-        buffer.push('\0');
+impl<'a> ObjectTag for ClosureObject<'a> {
+    const TYPE: ObjectType = ObjectType::Closure;
+}
 
-        let hash = hash_string(&buffer);
-        let length = buffer.len();
+impl ObjectTag for UpValueObject {
+    const TYPE: ObjectType = ObjectType::UpValue;
+}
 
-        let interned = table.find_string(&buffer, hash);
+impl<'a> ObjectTag for ClassObject<'a> {
+    const TYPE: ObjectType = ObjectType::Class;
+}
 
-        if interned.is_some() {
-            return ObjectPtr::new(interned.unwrap() as RawObject);
-        }
+impl<'a> ObjectTag for InstanceObject<'a> {
+    const TYPE: ObjectType = ObjectType::Instance;
+}
 
-        let s = StringObject {
-            _obj: Object::new(ObjectType::String, next),
-            hash,
-            chars: Box::leak(Box::new(buffer)),
-            length,
-        };
+impl<'a> ObjectTag for BoundMethodObject<'a> {
+    const TYPE: ObjectType = ObjectType::BoundMethod;
+}
 
-        let ptr = Box::into_raw(Box::new(s)) as RawObject;
+impl ObjectTag for MapObject {
+    const TYPE: ObjectType = ObjectType::Map;
+}
 
-        table.set(ptr, Value::nil());
+impl ObjectTag for ArrayObject {
+    const TYPE: ObjectType = ObjectType::Array;
+}
 
-        ObjectPtr::new(ptr)
+impl Object {
+    /// A header that isn't linked into any object list yet, `Allocator::alloc` links it
+    pub fn new(ty: ObjectType) -> Self {
+        Object {
+            ty,
+            next: std::ptr::null_mut(),
+        }
     }
+}
 
-    /// Creates a new String Object that takes ownership of the string passed in
-    pub fn from_owned(
-        chars: String,
-        table: &Table,
-        next: RawObject,
-    ) -> ObjectPtr<StringObject<'a>> {
-        let length = chars.len();
-        let hash = hash_string(&chars);
+pub(crate) fn hash_string(string: &str) -> usize {
+    let mut hash = 2166136261usize;
 
-        let interned = table.find_string(&chars, hash);
+    for c in string.chars() {
+        hash ^= c as usize;
+        hash = hash.wrapping_mul(16777619);
+    }
 
-        if interned.is_some() {
-            return ObjectPtr::new(interned.unwrap() as RawObject);
-        }
+    hash
+}
 
-        let s = StringObject {
-            _obj: Object::new(ObjectType::String, next),
-            hash,
+impl<'a> StringObject<'a> {
+    /// A string object that owns `chars`, which end in a `\0`. Use `Allocator::alloc_string` to
+    /// get an interned string
+    pub(crate) fn new(chars: String, hash: usize) -> Self {
+        StringObject {
+            _obj: Object::new(ObjectType::String),
+            length: chars.len(),
             chars: Box::leak(Box::new(chars)),
-            length,
-        };
-
-        ObjectPtr::new(Box::into_raw(Box::new(s)) as RawObject)
+            hash,
+        }
     }
 
     pub fn to_raw(&self) -> RawObject {
@@ -157,48 +253,101 @@ impl<'a> StringObject<'a> {
     pub fn value(&self) -> &str {
         self.chars
     }
+
+    /// The characters of the string without its null terminator
+    pub fn text(&self) -> &str {
+        self.chars.strip_suffix('\0').unwrap_or(self.chars)
+    }
 }
 
 impl<'a> FunctionObject<'a> {
-    pub fn new(
-        name: Option<ObjectPtr<StringObject<'a>>>,
-        next: RawObject,
-    ) -> ObjectPtr<FunctionObject<'a>> {
-        ObjectPtr::new(Box::into_raw(Box::new(Self {
-            _obj: Object::new(ObjectType::Function, next),
+    pub fn new(name: Option<ObjectPtr<StringObject<'a>>>) -> Self {
+        Self {
+            _obj: Object::new(ObjectType::Function),
             arity: 0,
             upvalue_count: 0,
             chunk: Chunk::new(),
             name,
-        })) as RawObject)
+        }
     }
 }
 
 impl NativeObject {
-    pub fn new(function: NativeFn, next: RawObject) -> ObjectPtr<NativeObject> {
-        ObjectPtr::new(Box::into_raw(Box::new(NativeObject {
-            obj: Object::new(ObjectType::Native, next),
+    pub fn new(function: Native, arity: Arity) -> Self {
+        NativeObject {
+            obj: Object::new(ObjectType::Native),
             function,
-        })) as RawObject)
+            arity,
+        }
     }
 }
 
 impl<'a> ClosureObject<'a> {
-    pub fn 
-    new(function: ObjectPtr<FunctionObject<'a>>, next: RawObject) -> ObjectPtr<Self> {
-        let mut upvalues = Vec::new();
-
-        for _ in 0..function.upvalue_count {
-            upvalues.push(None)
-        }
-        ObjectPtr::new(Box::into_raw(Box::new(ClosureObject {
-            obj: Object::new(ObjectType::Closure, next),
+    /// A closure whose upvalues are all empty, `Op::CLOSURE` fills them in once the closure has
+    /// been allocated
+    pub fn new(function: ObjectPtr<FunctionObject<'a>>) -> Self {
+        ClosureObject {
+            obj: Object::new(ObjectType::Closure),
             upvalue_count: function.upvalue_count,
-            upvalues,
+            upvalues: vec![None; function.upvalue_count],
             function,
-        })) as RawObject)
+        }
+    }
+}
+impl<'a> ClassObject<'a> {
+    pub fn new(name: ObjectPtr<StringObject<'a>>) -> Self {
+        ClassObject {
+            obj: Object::new(ObjectType::Class),
+            name,
+            methods: Table::new(),
+        }
+    }
+}
+
+impl<'a> InstanceObject<'a> {
+    pub fn new(class: ObjectPtr<ClassObject<'a>>) -> Self {
+        InstanceObject {
+            obj: Object::new(ObjectType::Instance),
+            class,
+            fields: Table::new(),
+        }
+    }
+}
+
+impl<'a> BoundMethodObject<'a> {
+    pub fn new(receiver: Value, method: ObjectPtr<ClosureObject<'a>>) -> Self {
+        BoundMethodObject {
+            obj: Object::new(ObjectType::BoundMethod),
+            receiver,
+            method,
+        }
+    }
+}
+
+impl MapObject {
+    pub fn new() -> Self {
+        MapObject {
+            obj: Object::new(ObjectType::Map),
+            entries: Table::new(),
+        }
+    }
+}
+
+impl Default for MapObject {
+    fn default() -> Self {
+        Self::new()
     }
 }
+
+impl ArrayObject {
+    pub fn new(items: Vec<Value>) -> Self {
+        ArrayObject {
+            obj: Object::new(ObjectType::Array),
+            items,
+        }
+    }
+}
+
 impl<T: ?Sized + Debug> ObjectPtr<T> {
     pub fn new(ptr: RawObject) -> ObjectPtr<T> {
         Self {
@@ -207,6 +356,7 @@ impl<T: ?Sized + Debug> ObjectPtr<T> {
         }
     }
 
+    /// The untyped pointer to the object's header, used to link it into the allocator's object list
     pub fn raw(&self) -> RawObject {
         self.ptr
     }
@@ -227,33 +377,34 @@ impl<T: ?Sized + Debug> ObjectPtr<T> {
     }
 
     pub fn as_function<'a>(&self) -> ObjectPtr<FunctionObject<'a>> {
-        #[cfg(debug_assertions)]
-        unsafe {
-            let obj = *&(*self.as_ptr());
-            assert!(obj.ty == ObjectType::Function)
-        }
-        ObjectPtr::new(self.ptr)
+        self.cast()
     }
 
     pub fn as_native(&self) -> ObjectPtr<NativeObject> {
-        #[cfg(debug_assertions)]
-        unsafe {
-            let obj = *&(*self.as_ptr());
-            assert!(obj.ty == ObjectType::Native)
-        }
-        ObjectPtr::new(self.ptr)
+        self.cast()
     }
 
     pub fn as_closure<'a>(&self) -> ObjectPtr<ClosureObject<'a>> {
+        self.cast()
+    }
+
+    /// Reinterprets the pointer as pointing to a `U`.
+    /// Every object starts with an `Object` header so in debug builds the type in the header
+    /// is checked against `U`. A null pointer is passed through unchecked.
+    pub fn cast<U: ObjectTag>(&self) -> ObjectPtr<U> {
         #[cfg(debug_assertions)]
-        unsafe {
-            let obj = *&(*self.as_ptr());
-            assert!(obj.ty == ObjectType::Closure)
+        if !self.ptr.is_null() {
+            let ty = unsafe { (*self.ptr).ty };
+
+            debug_assert_eq!(
+                U::TYPE,
+                ty,
+                "Object is type `{:?}` instead of {:?}",
+                ty,
+                U::TYPE,
+            );
         }
-        ObjectPtr::new(self.ptr)
-    }
 
-    pub fn cast<C: ?Sized + Debug>(&self) -> ObjectPtr<C> {
         ObjectPtr {
             ptr: self.ptr,
             tag: std::marker::PhantomData,
@@ -270,16 +421,13 @@ impl<T: ?Sized + Debug> ObjectPtr<T> {
 }
 
 impl UpValueObject {
-    pub fn new(location: Value) -> ObjectPtr<UpValueObject> {
-        ObjectPtr::new(Box::into_raw(Box::new(Self {
-            _obj: Object::new(
-                ObjectType::UpValue,
-                std::ptr::null::<RawObject>() as RawObject,
-            ),
+    pub fn new(location: Value) -> Self {
+        Self {
+            _obj: Object::new(ObjectType::UpValue),
             location,
             next: ObjectPtr::null(),
             closed: Value::nil(),
-        })) as RawObject)
+        }
     }
 }
 macro_rules! impl_object_traits {
@@ -372,9 +520,62 @@ impl<'a> Deref for ObjectPtr<NativeObject> {
     }
 }
 
+impl Deref for ObjectPtr<MapObject> {
+    type Target = MapObject;
+
+    fn deref(&self) -> &Self::Target {
+        unsafe { &*(self.ptr as *const MapObject) }
+    }
+}
+
+impl DerefMut for ObjectPtr<MapObject> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        unsafe { &mut *(self.ptr as *mut MapObject) }
+    }
+}
+
+impl Deref for ObjectPtr<ArrayObject> {
+    type Target = ArrayObject;
+
+    fn deref(&self) -> &Self::Target {
+        unsafe { &*(self.ptr as *const ArrayObject) }
+    }
+}
+
+impl DerefMut for ObjectPtr<ArrayObject> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        unsafe { &mut *(self.ptr as *mut ArrayObject) }
+    }
+}
+
+macro_rules! impl_object_deref {
+    ($object:ident) => {
+        impl<'a> Deref for ObjectPtr<$object<'a>> {
+            type Target = $object<'a>;
+
+            fn deref(&self) -> &Self::Target {
+                unsafe { &*(self.ptr as *const $object<'a>) }
+            }
+        }
+
+        impl<'a> DerefMut for ObjectPtr<$object<'a>> {
+            fn deref_mut(&mut self) -> &mut Self::Target {
+                unsafe { &mut *(self.ptr as *mut $object<'a>) }
+            }
+        }
+    };
+}
+
+impl_object_deref!(ClassObject);
+impl_object_deref!(InstanceObject);
+impl_object_deref!(BoundMethodObject);
+
 impl_object_traits!(StringObject);
 impl_object_traits!(FunctionObject);
 impl_object_traits!(ClosureObject);
+impl_object_traits!(ClassObject);
+impl_object_traits!(InstanceObject);
+impl_object_traits!(BoundMethodObject);
 
 impl<'a> Into<ObjectPtr<RawObject>> for ObjectPtr<NativeObject> {
     fn into(self) -> ObjectPtr<RawObject> {
@@ -415,3 +616,55 @@ impl<'a> Debug for FunctionObject<'a> {
             .finish()
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::{FunctionObject, ObjectPtr, RawObject, StringObject};
+    use crate::{Allocator, Table};
+
+    #[test]
+    fn it_casts_and_reads_back_an_object() {
+        let mut allocator = Allocator::new();
+        let mut table = Table::new();
+
+        let string = allocator.alloc_string("hello", &mut table);
+        let raw: ObjectPtr<RawObject> = string.into();
+
+        let string = raw.cast::<StringObject>();
+
+        assert_eq!(string.value(), "hello\0");
+        assert_eq!(string.length, 6);
+
+        let function: ObjectPtr<RawObject> = allocator.alloc(FunctionObject::new(None)).into();
+        let function = function.as_function();
+
+        assert_eq!(function.arity, 0);
+        assert_eq!(function.chunk.code.len(), 0);
+    }
+
+    #[test]
+    fn it_interns_owned_strings() {
+        let mut allocator = Allocator::new();
+        let mut table = Table::new();
+
+        let first = allocator.alloc_owned_string("ab\0".to_string(), &mut table);
+        let second = allocator.alloc_owned_string("ab\0".to_string(), &mut table);
+        let borrowed = allocator.alloc_string("ab", &mut table);
+
+        assert_eq!(first.raw(), second.raw());
+        assert_eq!(first.raw(), borrowed.raw());
+        assert_eq!(allocator.object_count(), 1);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "Object is type `String` instead of Function")]
+    fn it_panics_when_casting_to_the_wrong_object() {
+        let mut table = Table::new();
+
+        let string: ObjectPtr<RawObject> =
+            Allocator::new().alloc_string("hello", &mut table).into();
+
+        string.cast::<FunctionObject>();
+    }
+}
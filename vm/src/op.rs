@@ -31,4 +31,23 @@ pub enum Op {
     GET_UPVALUE = 26,
     SET_UPVALUE = 27,
     CLOSE_UPVALUE = 28,
+    CLASS = 29,
+    GET_PROPERTY = 30,
+    SET_PROPERTY = 31,
+    METHOD = 32,
+    MAP_NEW = 33,
+    /// Reads an element of an array or an entry of a map: `[receiver, index] -> [value]`
+    INDEX_GET = 34,
+    /// Writes an element of an array or an entry of a map: `[receiver, index, value] -> [value]`
+    INDEX_SET = 35,
+    /// Pushes a constant from the program-wide pool, the operand is a two byte index
+    SHARED_CONSTANT = 36,
+    /// Collects the values on top of the stack into an array, the operand is how many there are
+    ARRAY_NEW = 37,
+    /// Stands in for code that failed to compile, reaching it is a runtime error
+    TRAP = 38,
+    /// Pushes a copy of the value on top of the stack: `[a] -> [a, a]`
+    DUP = 39,
+    /// Swaps the two values on top of the stack: `[a, b] -> [b, a]`
+    SWAP = 40,
 }
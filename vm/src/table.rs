@@ -1,12 +1,14 @@
 use crate::{RawObject, StringObject, Value};
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Table {
     pub entries: Vec<Entry>,
     pub count: usize,
+    /// Deleted entries still take up a slot until the table is rebuilt
+    pub tombstones: usize,
     pub capacity: usize,
 }
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Entry {
     pub key: Option<RawObject>,
     pub value: Value,
@@ -57,16 +59,20 @@ impl Table {
         Self {
             entries: vec![],
             count: 0,
+            tombstones: 0,
             capacity: 0,
         }
     }
 
     pub fn set(&mut self, key: RawObject, value: Value) -> bool {
-        if (self.count + 1) as f64 > self.capacity as f64 * MAX_LOAD {
+        if (self.count + self.tombstones + 1) as f64 > self.capacity as f64 * MAX_LOAD {
+            // When it's mostly tombstones that fill the table rebuilding it at the same size clears them out
             self.adjust_capacity(if self.capacity < 8 {
                 8
-            } else {
+            } else if (self.count + 1) as f64 > self.capacity as f64 * MAX_LOAD / 2.0 {
                 self.capacity * 2
+            } else {
+                self.capacity
             });
         }
 
@@ -76,7 +82,11 @@ impl Table {
 
         let is_new_key = entry.key.is_none();
 
-        if is_new_key && entry.value.is_nil() {
+        if is_new_key {
+            if !entry.value.is_nil() {
+                self.tombstones -= 1;
+            }
+
             self.count += 1;
         }
 
@@ -120,10 +130,18 @@ impl Table {
         entry.value = Value::bool(false);
 
         self.count -= 1;
+        self.tombstones += 1;
 
         true
     }
 
+    /// The keys and values of the live entries, skipping empty slots and tombstones
+    pub fn iter(&self) -> impl Iterator<Item = (RawObject, Value)> + '_ {
+        self.entries
+            .iter()
+            .filter_map(|entry| entry.key.map(|key| (key, entry.value)))
+    }
+
     pub fn add_all(&mut self, other: &mut Table) {
         let other_entries = std::mem::replace(&mut other.entries, vec![]);
         for entry in other_entries {
@@ -145,6 +163,7 @@ impl Table {
         }
 
         self.count = 0;
+        self.tombstones = 0;
 
         for _ in 0..self.capacity {
             old_entries.drain(..).for_each(|entry| {
@@ -191,3 +210,105 @@ impl Table {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::Table;
+    use crate::{Allocator, RawObject, Value, VM};
+
+    /// The longest run of slots a lookup might have to walk past before reaching an empty one
+    fn longest_probe(table: &Table) -> usize {
+        let mut longest = 0;
+        let mut run = 0;
+
+        // Go round twice so a run that wraps around the end is counted in full
+        for entry in table.entries.iter().chain(table.entries.iter()) {
+            if entry.key.is_none() && entry.value.is_nil() {
+                run = 0;
+            } else {
+                run += 1;
+                longest = longest.max(run);
+            }
+        }
+
+        longest
+    }
+
+    #[test]
+    fn it_clears_out_tombstones_when_keys_are_deleted_repeatedly() {
+        let mut allocator = Allocator::new();
+        let mut strings = Table::new();
+        let mut table = Table::new();
+
+        let keys: Vec<RawObject> = (0..10_000)
+            .map(|i| {
+                allocator
+                    .alloc_string(&format!("key{}", i), &mut strings)
+                    .raw()
+            })
+            .collect();
+
+        for (i, key) in keys.iter().enumerate() {
+            table.set(*key, Value::nil());
+
+            if i >= 4 {
+                assert!(table.delete(keys[i - 4]));
+            }
+
+            assert!(longest_probe(&table) < table.capacity);
+        }
+
+        assert_eq!(table.count, 4);
+        assert!(table.capacity <= 16);
+        assert_eq!(table.get(keys[0]), None);
+        assert!(table.get(keys[9_999]).is_some());
+
+        // Objects are freed by the vm that owns them
+        drop(VM::new(strings, allocator));
+    }
+
+    #[test]
+    fn it_counts_a_key_set_again_after_being_deleted() {
+        let mut allocator = Allocator::new();
+        let mut strings = Table::new();
+        let mut table = Table::new();
+
+        let key = allocator.alloc_string("key", &mut strings).raw();
+
+        table.set(key, Value::nil());
+        table.delete(key);
+        table.set(key, Value::bool(true));
+
+        assert_eq!(table.count, 1);
+        assert_eq!(table.tombstones, 0);
+        assert!(table.get(key).is_some());
+
+        // Objects are freed by the vm that owns them
+        drop(VM::new(strings, allocator));
+    }
+
+    #[test]
+    fn it_iterates_over_the_keys_that_are_still_set() {
+        let mut strings = Table::new();
+        let mut table = Table::new();
+        let mut allocator = Allocator::new();
+
+        let a = allocator.alloc_string("a", &mut strings).raw();
+        let b = allocator.alloc_string("b", &mut strings).raw();
+        let c = allocator.alloc_string("c", &mut strings).raw();
+
+        table.set(a, Value::int(1));
+        table.set(b, Value::int(2));
+        table.set(c, Value::int(3));
+        table.delete(b);
+
+        let keys: Vec<RawObject> = table.iter().map(|(key, _)| key).collect();
+
+        assert_eq!(keys.len(), 2);
+        assert!(keys.contains(&a));
+        assert!(keys.contains(&c));
+
+        // Objects are freed by the vm that owns them
+        drop(VM::new(strings, allocator));
+    }
+}
@@ -1,8 +1,11 @@
-use std::fmt::Debug;
+use std::cmp::Ordering;
+use std::fmt::{self, Debug, Display};
+use std::hash::{Hash, Hasher};
 
 use crate::{
     object::{ObjectType, StringObject},
-    ClosureObject, FunctionObject, NativeObject, ObjectPtr, RawObject, ValuePtr,
+    ArrayObject, BoundMethodObject, ClassObject, ClosureObject, FunctionObject, InstanceObject,
+    MapObject, NativeObject, ObjectPtr, RawObject, ValuePtr,
 };
 
 #[derive(Clone, Copy)]
@@ -20,7 +23,9 @@ impl Debug for Value {
                 &match self.ty {
                     ValueType::Bool => self.as_bool_ref().to_string(),
                     ValueType::Nil => "nil".to_string(),
+                    ValueType::Int => self.as_int().to_string(),
                     ValueType::Number => self.as_number_ref().to_string(),
+                    ValueType::Object if self.is_null_object() => "<null object>".to_string(),
                     ValueType::Object => match self.obj_type() {
                         ObjectType::String => {
                             format!("{:?}", self.as_string().chars)
@@ -29,6 +34,11 @@ impl Debug for Value {
                         ObjectType::Native => "<native fn>".to_string(),
                         ObjectType::Closure => "<closure fn>".to_string(),
                         ObjectType::UpValue => "upvalue".to_string(),
+                        ObjectType::Class => "<class>".to_string(),
+                        ObjectType::Instance => "<instance>".to_string(),
+                        ObjectType::BoundMethod => "<bound method>".to_string(),
+                        ObjectType::Map => "<map>".to_string(),
+                        ObjectType::Array => "<array>".to_string(),
                     },
                 }
             })
@@ -36,10 +46,159 @@ impl Debug for Value {
     }
 }
 
+impl Display for Value {
+    /// The textual form of a value, as shown by `print`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Display::fmt(&self.formatted(&NumberFormat::default()), f)
+    }
+}
+
+/// How many digits of a number `print` shows, the default writes the shortest text that reads
+/// back as the same number
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Precision {
+    #[default]
+    Shortest,
+    /// Exactly this many digits after the point
+    Decimals(usize),
+    /// At most this many significant digits
+    Significant(usize),
+}
+
+/// How numbers are written when a value is shown, ints are always written in full
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct NumberFormat {
+    pub precision: Precision,
+    /// Numbers whose magnitude is at least this are written as `1.5e6`
+    pub scientific_threshold: Option<f64>,
+}
+
+impl NumberFormat {
+    fn write(&self, f: &mut fmt::Formatter<'_>, number: f64) -> fmt::Result {
+        let scientific = self
+            .scientific_threshold
+            .is_some_and(|threshold| number.is_finite() && number.abs() >= threshold);
+
+        match (self.precision, scientific) {
+            (Precision::Shortest, false) => write!(f, "{}", number),
+            (Precision::Shortest, true) => write!(f, "{:e}", number),
+            (Precision::Decimals(decimals), false) => write!(f, "{:.*}", decimals, number),
+            (Precision::Decimals(decimals), true) => write!(f, "{:.*e}", decimals, number),
+            (Precision::Significant(digits), false) => {
+                write!(f, "{}", round_to_significant(number, digits))
+            }
+            (Precision::Significant(digits), true) => {
+                write!(f, "{:.*e}", digits.saturating_sub(1), number)
+            }
+        }
+    }
+}
+
+fn round_to_significant(number: f64, digits: usize) -> f64 {
+    if number == 0.0 || !number.is_finite() {
+        return number;
+    }
+
+    let magnitude = number.abs().log10().floor() as i32;
+    let scale = 10f64.powi(digits.max(1) as i32 - 1 - magnitude);
+
+    (number * scale).round() / scale
+}
+
+/// A value shown with a particular `NumberFormat`, see `Value::formatted`
+pub struct Formatted<'f> {
+    value: Value,
+    format: &'f NumberFormat,
+}
+
+impl Display for Formatted<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let value = self.value;
+
+        match value.ty {
+            ValueType::Bool => write!(f, "{}", value.as_bool()),
+            ValueType::Nil => write!(f, "nil"),
+            ValueType::Int => write!(f, "{}", value.as_int()),
+            ValueType::Number => self.format.write(f, value.as_number()),
+            ValueType::Object if value.is_null_object() => write!(f, "<null object>"),
+            ValueType::Object => match value.obj_type() {
+                ObjectType::String => write!(f, "{}", trim_nul(value.as_raw_string())),
+                ObjectType::Function => fmt_function(f, &value.as_function()),
+                ObjectType::Native => write!(f, "<native fn>"),
+                ObjectType::Closure => fmt_function(f, &value.as_closure().function),
+                ObjectType::UpValue => write!(f, "upvalue"),
+                ObjectType::Class => write!(f, "{}", trim_nul(value.as_class().name.value())),
+                ObjectType::Instance => write!(
+                    f,
+                    "{} instance",
+                    trim_nul(value.as_instance().class.name.value())
+                ),
+                ObjectType::BoundMethod => {
+                    fmt_function(f, &value.as_bound_method().method.function)
+                }
+                ObjectType::Map => fmt_map(f, &value.as_map(), self.format),
+                ObjectType::Array => fmt_array(f, &value.as_array(), self.format),
+            },
+        }
+    }
+}
+
+/// Writes the entries as `{"a": 1, "b": 2}`, in the order they sit in the table
+fn fmt_map(f: &mut fmt::Formatter<'_>, map: &MapObject, format: &NumberFormat) -> fmt::Result {
+    write!(f, "{{")?;
+
+    for (i, (key, value)) in map.entries.iter().enumerate() {
+        if i > 0 {
+            write!(f, ", ")?;
+        }
+
+        write!(
+            f,
+            "\"{}\": {}",
+            Value::object(ObjectPtr::new(key)),
+            value.formatted(format)
+        )?;
+    }
+
+    write!(f, "}}")
+}
+
+/// Writes the items as `[1, 2, 3]`
+fn fmt_array(
+    f: &mut fmt::Formatter<'_>,
+    array: &ArrayObject,
+    format: &NumberFormat,
+) -> fmt::Result {
+    write!(f, "[")?;
+
+    for (i, item) in array.items.iter().enumerate() {
+        if i > 0 {
+            write!(f, ", ")?;
+        }
+
+        write!(f, "{}", item.formatted(format))?;
+    }
+
+    write!(f, "]")
+}
+
+fn fmt_function(f: &mut fmt::Formatter<'_>, function: &FunctionObject) -> fmt::Result {
+    match &function.name {
+        Some(name) => write!(f, "<fn {}>", trim_nul(name.value())),
+        None => write!(f, "<script>"),
+    }
+}
+
+/// Strings are stored with a null terminator that isn't part of their text
+fn trim_nul(string: &str) -> &str {
+    string.trim_end_matches('\0')
+}
+
 #[derive(Clone, Copy)]
 #[repr(C)]
 pub union As {
     boolean: bool,
+    integer: i64,
     number: f64,
     object: ObjectPtr<RawObject>,
 }
@@ -47,6 +206,7 @@ pub union As {
 pub enum ValueType {
     Bool,
     Nil,
+    Int,
     Number,
     Object,
 }
@@ -67,6 +227,13 @@ impl Value {
         }
     }
     #[inline]
+    pub fn int(value: i64) -> Value {
+        Value {
+            repr: As { integer: value },
+            ty: ValueType::Int,
+        }
+    }
+    #[inline]
     pub fn number(value: f64) -> Value {
         Value {
             repr: As { number: value },
@@ -106,6 +273,18 @@ impl Value {
         unsafe { &self.repr.boolean }
     }
 
+    #[inline]
+    pub fn as_int(&self) -> i64 {
+        debug_assert_eq!(
+            self.ty,
+            ValueType::Int,
+            "Value is type `{:?}` instead of {:?}",
+            self.ty,
+            ValueType::Int
+        );
+        unsafe { self.repr.integer }
+    }
+
     #[inline]
     pub fn as_number(&self) -> f64 {
         debug_assert_eq!(
@@ -129,6 +308,16 @@ impl Value {
         unsafe { &self.repr.number }
     }
 
+    /// Reads an int or a number as a float, used when an arithmetic op promotes an int
+    #[inline]
+    pub fn as_float(&self) -> f64 {
+        if self.is_int() {
+            self.as_int() as f64
+        } else {
+            self.as_number()
+        }
+    }
+
     #[inline]
     pub fn as_obj(&self) -> RawObject {
         unsafe { self.repr.object.as_ptr() }
@@ -136,77 +325,46 @@ impl Value {
 
     #[inline]
     pub fn as_string<'a>(&self) -> ObjectPtr<StringObject<'a>> {
-        #[cfg(debug_assertions)]
-        {
-            let repr = self.as_obj();
-
-            let ty = unsafe { (*repr).ty };
-
-            debug_assert_eq!(
-                ObjectType::String,
-                ty,
-                "Value is type `{:?}` instead of {:?}",
-                ty,
-                ObjectType::String,
-            );
-        }
         unsafe { self.repr.object.cast() }
     }
 
     #[inline]
     pub fn as_function<'a>(&self) -> ObjectPtr<FunctionObject<'a>> {
-        #[cfg(debug_assertions)]
-        {
-            let repr = self.as_obj();
-
-            let ty = unsafe { (*repr).ty };
-
-            debug_assert_eq!(
-                ObjectType::Function,
-                ty,
-                "Value is type `{:?}` instead of {:?}",
-                ty,
-                ObjectType::Function,
-            );
-        }
         unsafe { self.repr.object.cast() }
     }
 
     #[inline]
     pub fn as_native(&self) -> ObjectPtr<NativeObject> {
-        #[cfg(debug_assertions)]
-        {
-            let repr = self.as_obj();
-
-            let ty = unsafe { (*repr).ty };
-
-            debug_assert_eq!(
-                ObjectType::Native,
-                ty,
-                "Value is type `{:?}` instead of {:?}",
-                ty,
-                ObjectType::Native
-            );
-        }
         unsafe { self.repr.object.cast() }
     }
 
     #[inline]
     pub fn as_closure<'a>(&self) -> ObjectPtr<ClosureObject<'a>> {
-        #[cfg(debug_assertions)]
-        {
-            let repr = self.as_obj();
-
-            let ty = unsafe { (*repr).ty };
-
-            debug_assert_eq!(
-                ObjectType::Closure,
-                ty,
-                "Value is type `{:?}` instead of {:?}",
-                ty,
-                ObjectType::Closure,
-            );
-        }
+        unsafe { self.repr.object.cast() }
+    }
+
+    #[inline]
+    pub fn as_class<'a>(&self) -> ObjectPtr<ClassObject<'a>> {
+        unsafe { self.repr.object.cast() }
+    }
+
+    #[inline]
+    pub fn as_instance<'a>(&self) -> ObjectPtr<InstanceObject<'a>> {
+        unsafe { self.repr.object.cast() }
+    }
+
+    #[inline]
+    pub fn as_bound_method<'a>(&self) -> ObjectPtr<BoundMethodObject<'a>> {
+        unsafe { self.repr.object.cast() }
+    }
+
+    #[inline]
+    pub fn as_map(&self) -> ObjectPtr<MapObject> {
+        unsafe { self.repr.object.cast() }
+    }
+
+    #[inline]
+    pub fn as_array(&self) -> ObjectPtr<ArrayObject> {
         unsafe { self.repr.object.cast() }
     }
 
@@ -229,10 +387,31 @@ impl Value {
         self.ty == ValueType::Nil
     }
     #[inline]
+    pub fn is_int(&self) -> bool {
+        self.ty == ValueType::Int
+    }
+    #[inline]
     pub fn is_number(&self) -> bool {
         self.ty == ValueType::Number
     }
 
+    /// Is the value either an int or a number
+    #[inline]
+    pub fn is_numeric(&self) -> bool {
+        self.is_int() || self.is_number()
+    }
+
+    /// Orders two numeric values exactly, an int isn't rounded to a float before it's compared with
+    /// one. `None` when either is `NaN`
+    pub fn numeric_cmp(&self, other: &Value) -> Option<Ordering> {
+        match (self.is_int(), other.is_int()) {
+            (true, true) => Some(self.as_int().cmp(&other.as_int())),
+            (true, false) => cmp_int_float(self.as_int(), other.as_number()),
+            (false, true) => cmp_int_float(other.as_int(), self.as_number()).map(Ordering::reverse),
+            (false, false) => self.as_number().partial_cmp(&other.as_number()),
+        }
+    }
+
     #[inline]
     pub fn is_obj(&self) -> bool {
         self.ty == ValueType::Object
@@ -248,6 +427,11 @@ impl Value {
         self.is_obj_type(ObjectType::Native)
     }
 
+    #[inline]
+    pub fn is_instance(&self) -> bool {
+        self.is_obj_type(ObjectType::Instance)
+    }
+
     #[inline]
     pub fn is_falsey(&self) -> bool {
         self.is_nil() || (self.is_bool() && !self.as_bool())
@@ -255,17 +439,68 @@ impl Value {
 
     #[inline]
     pub fn obj_type(&self) -> ObjectType {
+        debug_assert!(
+            !self.is_null_object(),
+            "Read the type of a null object pointer"
+        );
+
         unsafe { (*self.as_obj()).ty }
     }
 
+    /// Whether this is an object value whose pointer is null, which has no type to read
+    #[inline]
+    pub fn is_null_object(&self) -> bool {
+        self.is_obj() && self.as_obj().is_null()
+    }
+
+    /// Shows the value like `Display` does but with numbers written in `format`
+    pub fn formatted<'f>(&self, format: &'f NumberFormat) -> Formatted<'f> {
+        Formatted {
+            value: *self,
+            format,
+        }
+    }
+
+    /// The name of the value's type as a script would think of it, used in runtime error messages
+    pub fn type_name(&self) -> &'static str {
+        match self.ty {
+            ValueType::Bool => "bool",
+            ValueType::Nil => "nil",
+            ValueType::Int => "int",
+            ValueType::Number => "number",
+            ValueType::Object if self.is_null_object() => "null object",
+            ValueType::Object => match self.obj_type() {
+                ObjectType::String => "string",
+                ObjectType::Function | ObjectType::Closure => "function",
+                ObjectType::Native => "native function",
+                ObjectType::UpValue => "upvalue",
+                ObjectType::Class => "class",
+                ObjectType::Instance => "instance",
+                ObjectType::BoundMethod => "function",
+                ObjectType::Map => "map",
+                ObjectType::Array => "array",
+            },
+        }
+    }
+
     #[inline]
     pub fn is_string(&self) -> bool {
         self.is_obj_type(ObjectType::String)
     }
+
+    #[inline]
+    pub fn is_map(&self) -> bool {
+        self.is_obj_type(ObjectType::Map)
+    }
+
+    #[inline]
+    pub fn is_array(&self) -> bool {
+        self.is_obj_type(ObjectType::Array)
+    }
     #[inline]
 
     pub fn is_obj_type(&self, ty: ObjectType) -> bool {
-        self.is_obj() && self.obj_type() == ty
+        self.is_obj() && !self.is_null_object() && self.obj_type() == ty
     }
 
     #[inline]
@@ -276,14 +511,212 @@ impl Value {
 
 impl PartialEq for Value {
     fn eq(&self, other: &Self) -> bool {
+        match (self.ty, other.ty) {
+            (ValueType::Int, ValueType::Number) => {
+                return float_as_int(other.as_number()) == Some(self.as_int())
+            }
+            (ValueType::Number, ValueType::Int) => {
+                return float_as_int(self.as_number()) == Some(other.as_int())
+            }
+            _ => {}
+        }
+
         if self.ty != other.ty {
             return false;
         }
         match self.ty {
             ValueType::Bool => self.as_bool() == other.as_bool(),
             ValueType::Nil => true,
+            ValueType::Int => self.as_int() == other.as_int(),
             ValueType::Number => self.as_number() == other.as_number(),
             ValueType::Object => self.as_obj() == other.as_obj(),
         }
     }
 }
+
+/// A float that holds a whole number as the int with exactly the same value, floats with a fraction,
+/// out of range or not finite have none. An int and a number are only equal when this matches.
+fn float_as_int(float: f64) -> Option<i64> {
+    if float.fract() == 0.0 && (-I64_LIMIT..I64_LIMIT).contains(&float) {
+        Some(float as i64)
+    } else {
+        None
+    }
+}
+
+/// 2^63, which is exact as a float while `i64::MAX` isn't. Every int is below it and at or above its
+/// negation
+const I64_LIMIT: f64 = 9_223_372_036_854_775_808.0;
+
+/// Compares the whole part of `float` as an int, then its fraction, so no precision is lost
+fn cmp_int_float(int: i64, float: f64) -> Option<Ordering> {
+    if float.is_nan() {
+        None
+    } else if float >= I64_LIMIT {
+        Some(Ordering::Less)
+    } else if float < -I64_LIMIT {
+        Some(Ordering::Greater)
+    } else {
+        let whole = int.cmp(&(float.trunc() as i64));
+
+        // A fraction above zero puts the float above the int, `-0.0` is the same as no fraction
+        0.0_f64
+            .partial_cmp(&float.fract())
+            .map(|fraction| whole.then(fraction))
+    }
+}
+
+/// Values that are equal hash the same. A number holding a whole number hashes as the int it equals,
+/// and strings are interned so equal strings are the same object and hash by their stored hash.
+/// `Eq` is claimed even though `NaN` isn't equal to itself, as it is for the vm's `==`
+impl Eq for Value {}
+
+impl Hash for Value {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        match self.ty {
+            ValueType::Bool => {
+                0u8.hash(state);
+                self.as_bool().hash(state);
+            }
+            ValueType::Nil => 1u8.hash(state),
+            ValueType::Int => {
+                2u8.hash(state);
+                self.as_int().hash(state);
+            }
+            // `0.0 == -0.0` and both are the int 0
+            ValueType::Number => match float_as_int(self.as_number()) {
+                Some(int) => {
+                    2u8.hash(state);
+                    int.hash(state);
+                }
+                None => {
+                    4u8.hash(state);
+                    self.as_number().to_bits().hash(state);
+                }
+            },
+            ValueType::Object => {
+                3u8.hash(state);
+
+                if self.is_string() {
+                    self.as_string().hash.hash(state);
+                } else {
+                    self.as_obj().hash(state);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{NumberFormat, Precision, Value};
+    use crate::{Allocator, Table, VM};
+    use std::collections::HashSet;
+
+    #[test]
+    fn it_displays_a_whole_number_without_a_fraction() {
+        assert_eq!(format!("{}", Value::number(3.0)), "3");
+        assert_eq!(format!("{}", Value::number(1.5)), "1.5");
+        assert_eq!(format!("{}", Value::int(-4)), "-4");
+        assert_eq!(format!("{}", Value::nil()), "nil");
+        assert_eq!(format!("{}", Value::bool(true)), "true");
+    }
+
+    #[test]
+    fn it_displays_a_number_in_the_given_format() {
+        let value = Value::number(1234.5678);
+
+        let fixed = NumberFormat {
+            precision: Precision::Decimals(2),
+            ..NumberFormat::default()
+        };
+        let scientific = NumberFormat {
+            precision: Precision::Significant(3),
+            scientific_threshold: Some(1000.0),
+        };
+
+        assert_eq!(
+            value.formatted(&NumberFormat::default()).to_string(),
+            "1234.5678"
+        );
+        assert_eq!(value.formatted(&fixed).to_string(), "1234.57");
+        assert_eq!(value.formatted(&scientific).to_string(), "1.23e3");
+        assert_eq!(
+            Value::number(0.012345).formatted(&scientific).to_string(),
+            "0.0123"
+        );
+        assert_eq!(
+            Value::int(12345).formatted(&scientific).to_string(),
+            "12345"
+        );
+    }
+
+    #[test]
+    fn it_displays_a_string_without_its_null_terminator() {
+        let mut table = Table::new();
+
+        let mut allocator = Allocator::new();
+
+        let string = allocator.alloc_string("hello", &mut table);
+
+        assert_eq!(format!("{}", Value::object(string.into())), "hello");
+
+        // Objects are freed by the vm that owns them
+        drop(VM::new(table, allocator));
+    }
+
+    #[test]
+    fn equal_numbers_are_one_entry_in_a_set() {
+        let mut set = HashSet::new();
+
+        set.insert(Value::number(2.0));
+        set.insert(Value::number(2.0));
+        set.insert(Value::int(2));
+        set.insert(Value::number(-0.0));
+        set.insert(Value::int(0));
+
+        assert_eq!(set.len(), 2);
+    }
+
+    #[test]
+    fn ints_and_numbers_are_only_equal_when_the_number_is_exactly_the_int() {
+        let two_53 = 1i64 << 53;
+
+        // 2^53 + 1 has no float of its own, it rounds to 2^53
+        assert_eq!(Value::int(two_53), Value::number(two_53 as f64));
+        assert_ne!(Value::int(two_53 + 1), Value::number(two_53 as f64));
+        assert_ne!(Value::number((two_53 + 1) as f64), Value::int(two_53 + 1));
+        assert_ne!(Value::int(1), Value::number(1.5));
+        assert_ne!(Value::int(i64::MAX), Value::number(i64::MAX as f64));
+
+        let mut set = HashSet::new();
+
+        set.insert(Value::int(two_53));
+        set.insert(Value::int(two_53 + 1));
+        set.insert(Value::number(two_53 as f64));
+
+        assert_eq!(set.len(), 2);
+    }
+
+    #[test]
+    fn equal_interned_strings_are_one_entry_in_a_set() {
+        let mut allocator = Allocator::new();
+        let mut table = Table::new();
+
+        let a = allocator.alloc_string("key", &mut table);
+        let b = allocator.alloc_string("key", &mut table);
+        let other = allocator.alloc_string("other", &mut table);
+
+        let mut set = HashSet::new();
+
+        set.insert(Value::object(a.into()));
+        set.insert(Value::object(b.into()));
+        set.insert(Value::object(other.into()));
+
+        assert_eq!(set.len(), 2);
+        assert!(set.contains(&Value::object(a.into())));
+
+        // Objects are freed by the vm that owns them
+        drop(VM::new(table, allocator));
+    }
+}
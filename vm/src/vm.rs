@@ -1,14 +1,47 @@
 use crate::{
+    chunk::Chunk,
     frame::CallFrame,
-    native::clock_native,
+    native::{assert_native, clock_native, input_native, println_native, sleep_native, str_native},
     op::Op,
-    value::{Value, ValueType},
-    Allocator, ClosureObject, FunctionObject, NativeFn, NativeObject, ObjectPtr, ObjectType,
-    RawObject, StringObject, Table, UpValueObject, ValuePtr,
+    value::Value,
+    Allocator, Arity, ArrayObject, BoundMethodObject, ClassObject, ClosureObject, FunctionObject,
+    InstanceObject, MapObject, Native, NativeFn, NativeObject, NumberFormat, ObjectPtr, ObjectType,
+    RawObject, SliceNativeFn, StringObject, Table, UpValueObject, ValuePtr,
 };
+use std::cell::RefCell;
+use std::collections::BTreeSet;
 use std::fmt::Display;
+use std::io::{BufRead, BufReader, Write};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 pub const STACK_MAX: usize = FRAMES_MAX * (u8::BITS as usize);
 pub const FRAMES_MAX: usize = 64;
+/// One past the highest opcode
+#[cfg(feature = "profile")]
+const OP_COUNT: usize = Op::SWAP as usize + 1;
+
+/// A writer that keeps everything written to it, shared between its clones. Hand one to
+/// `VM::set_output` and read what the vm printed from another
+#[derive(Clone, Default)]
+pub struct CapturedOutput(Rc<RefCell<Vec<u8>>>);
+
+impl CapturedOutput {
+    /// Everything written so far
+    pub fn contents(&self) -> String {
+        String::from_utf8_lossy(&self.0.borrow()).into_owned()
+    }
+}
+
+impl Write for CapturedOutput {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
 
 pub struct VM<'a> {
     stack: [Value; STACK_MAX],
@@ -16,23 +49,86 @@ pub struct VM<'a> {
     pub stack_top: usize,
     pub frame_count: usize,
 
-    strings: Table,
+    pub(crate) strings: Table,
     globals: Table,
     pub open_upvalues: ObjectPtr<UpValueObject>,
     pub allocator: Allocator,
+    pub(crate) out: Box<dyn Write>,
+    /// Where `input` reads its lines from
+    pub(crate) input: Box<dyn BufRead>,
+    /// How `print` writes numbers
+    pub(crate) number_format: NumberFormat,
+    /// What `print` ends a line with
+    pub(crate) line_ending: LineEnding,
+    /// The interned name of class initializers
+    init_string: ObjectPtr<StringObject<'a>>,
+    /// How many times each opcode has been executed
+    #[cfg(feature = "profile")]
+    op_counts: [usize; OP_COUNT],
+    /// The lines that ran, when coverage is being recorded
+    coverage: Option<Coverage>,
+    /// Set from outside the vm, e.g. by a signal handler, to stop the running script
+    interrupt: Arc<AtomicBool>,
+    /// A failure raised by a native with `raise`, reported as a runtime error once the native returns
+    native_error: Option<String>,
+}
+
+#[derive(Debug, Default)]
+struct Coverage {
+    /// Every line the interpreted script has code on
+    lines: BTreeSet<usize>,
+    executed: BTreeSet<usize>,
+}
+
+/// Which of the lines with code on them ran, see `VM::record_coverage`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CoverageReport {
+    pub executed: Vec<usize>,
+    pub unexecuted: Vec<usize>,
+}
+
+/// What `print` and `println` end a line with, see `VM::set_line_ending`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LineEnding {
+    /// `\n`
+    #[default]
+    Lf,
+    /// `\r\n`
+    CrLf,
+}
+
+impl LineEnding {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::CrLf => "\r\n",
+        }
+    }
+}
+
+/// The globals and stack of a vm at some point, see `VM::checkpoint`
+#[derive(Debug, Clone)]
+pub struct Checkpoint {
+    globals: Table,
+    stack_top: usize,
+    frame_count: usize,
 }
 
 #[derive(Debug)]
 pub enum Error {
     CompileError(String),
-    RuntimeError,
+    /// The message and the source line of the instruction that failed
+    RuntimeError {
+        message: String,
+        line: usize,
+    },
 }
 
 impl Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Error::CompileError(e) => write!(f, "Compile error: {}", e),
-            Error::RuntimeError => write!(f, "Runtime error"),
+            Error::RuntimeError { message, .. } => write!(f, "Runtime error: {}", message),
         }
     }
 }
@@ -97,16 +193,57 @@ macro_rules! read_constant {
 macro_rules! binary_op {
     ($val_ty:ident,$op:tt,$self:ident) => {{
 
-        if !$self.peek(0).is_number() || !$self.peek(1).is_number() {
-            runtime_error!($self, "{} operands must be numbers",stringify!($op));
-            return Err(Box::new(Error::RuntimeError));
+        if !$self.peek(0).is_numeric() || !$self.peek(1).is_numeric() {
+            return Err(Box::new(runtime_error!(
+                $self,
+                "{} operands must be numbers, found `{}` and `{}`",
+                stringify!($op),
+                $self.peek(1).type_name(),
+                $self.peek(0).type_name()
+            )));
+        }
+
+        let b = $self.pop();
+
+        let a = $self.pop();
+
+        // Mixed ints and floats are compared exactly, casting the int could round it
+        let ordering = a.numeric_cmp(&b);
+
+        $self.push(Value::$val_ty(
+            ordering.is_some_and(|ordering| ordering $op std::cmp::Ordering::Equal),
+        ))?;
+    }};
+}
+
+/// Arithmetic on two ints stays an int and reports overflow, anything else is promoted to a float
+macro_rules! arithmetic_op {
+    ($checked:ident,$op:tt,$self:ident) => {{
+
+        if !$self.peek(0).is_numeric() || !$self.peek(1).is_numeric() {
+            return Err(Box::new(runtime_error!(
+                $self,
+                "{} operands must be numbers, found `{}` and `{}`",
+                stringify!($op),
+                $self.peek(1).type_name(),
+                $self.peek(0).type_name()
+            )));
         }
 
-        let b = $self.pop().as_number();
+        let b = $self.pop();
 
-        let a = $self.pop().as_number();
+        let a = $self.pop();
 
-        $self.push(Value::$val_ty(a $op b));
+        if a.is_int() && b.is_int() {
+            match a.as_int().$checked(b.as_int()) {
+                Some(result) => $self.push(Value::int(result))?,
+                None => {
+                    return Err(Box::new(runtime_error!($self, "Integer overflow.")));
+                }
+            }
+        } else {
+            $self.push(Value::number(a.as_float() $op b.as_float()))?;
+        }
     }};
 }
 
@@ -115,14 +252,22 @@ macro_rules! runtime_error {
         $crate::eprint!("\n")
     };
     ($self:ident,$($arg:tt)*) => {{
+        let message = format!($($arg)*);
+
         eprintln!("");
-        eprintln!($($arg)*);
+        eprintln!("{}", message);
+
 
+        let mut error_line = 0;
 
         for i in (0..$self.frame_count).rev() {
             let frame = frame!($self,i);
-            let instruction = frame.ip;
-            let line = frame.closure.function.chunk.lines[instruction];
+            let line = frame.line();
+
+            if i == $self.frame_count - 1 {
+                error_line = line;
+            }
+
             eprint!(" [line {}] in ", line);
             if frame.closure.function.name.is_none() {
                 eprintln!("script");
@@ -134,18 +279,20 @@ macro_rules! runtime_error {
 
         $self.reset_stack();
 
-
+        Error::RuntimeError { message, line: error_line }
     }};
 }
 
 impl<'a> VM<'a> {
-    pub fn new(strings: Table, mut allocator: Allocator) -> Self {
+    pub fn new(mut strings: Table, mut allocator: Allocator) -> Self {
         let mut frames = Vec::new();
 
         for _ in 0..FRAMES_MAX {
             frames.push(CallFrame::new(&mut allocator))
         }
 
+        let init_string = allocator.alloc_string("init", &mut strings);
+
         let mut vm = Self {
             stack: [Value::nil(); STACK_MAX],
             frames,
@@ -155,17 +302,98 @@ impl<'a> VM<'a> {
             strings,
             globals: Table::new(),
             open_upvalues: ObjectPtr::null(),
+            out: Box::new(std::io::stdout()),
+            input: Box::new(BufReader::new(std::io::stdin())),
+            number_format: NumberFormat::default(),
+            line_ending: LineEnding::default(),
+            init_string,
+            #[cfg(feature = "profile")]
+            op_counts: [0; OP_COUNT],
+            coverage: None,
+            interrupt: Arc::new(AtomicBool::new(false)),
+            native_error: None,
         };
 
-        vm.define_native("clock", clock_native);
+        vm.register_native("clock", Arity::Exactly(0), clock_native);
+        vm.register_native("sleep", Arity::Exactly(1), sleep_native);
+        vm.register_native("println", Arity::Range(0, 1), println_native);
+        vm.register_native("str", Arity::Range(0, 1), str_native);
+        vm.register_native("input", Arity::Exactly(0), input_native);
+        vm.register_native("assert", Arity::Range(1, 2), assert_native);
 
         vm
     }
 
+    /// Redirects the output of `print` to `out`, which defaults to stdout
+    pub fn set_output(&mut self, out: Box<dyn Write>) {
+        self.out = out;
+    }
+
+    /// Changes how numbers are written by `print`, `println` and `str`
+    pub fn set_number_format(&mut self, format: NumberFormat) {
+        self.number_format = format;
+    }
+
+    /// Changes what `print` and `println` end a line with, which defaults to `\n`
+    pub fn set_line_ending(&mut self, line_ending: LineEnding) {
+        self.line_ending = line_ending;
+    }
+
+    /// Changes where `input` reads its lines from, which defaults to stdin
+    pub fn set_input(&mut self, input: Box<dyn BufRead>) {
+        self.input = input;
+    }
+
+    /// Wraps the top level script function in a closure and runs it
+    pub fn interpret(
+        &mut self,
+        function: ObjectPtr<FunctionObject<'a>>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let function_ptr = function.as_function();
+
+        if let Some(coverage) = &mut self.coverage {
+            collect_lines(&function.chunk, &mut coverage.lines);
+        }
+
+        self.push(Value::object(function.as_ptr_obj()))?;
+
+        let closure = self.allocator.alloc(ClosureObject::new(function_ptr));
+
+        self.pop();
+
+        self.push(Value::object(closure.clone().into()))?;
+
+        self.call(closure, 0)?;
+
+        self.run()
+    }
+
     pub fn run(&mut self) -> Result<(), Box<dyn std::error::Error>> {
         loop {
+            // A plain load keeps the common case free of a read-modify-write on every instruction
+            if self.interrupt.load(Ordering::Relaxed) {
+                self.interrupt.store(false, Ordering::Relaxed);
+                return Err(Box::new(runtime_error!(self, "Interrupted.")));
+            }
+
+            let frame = frame_mut!(self);
+            frame.instruction = frame.ip;
+
             let instruction = read_byte!(self);
 
+            if let Some(coverage) = &mut self.coverage {
+                let frame = frame!(self);
+
+                coverage
+                    .executed
+                    .insert(frame.closure.function.chunk.line_at(frame.instruction));
+            }
+
+            #[cfg(feature = "profile")]
+            {
+                self.op_counts[instruction as usize] += 1;
+            }
+
             {
                 #[cfg(feature = "trace")]
                 {
@@ -210,16 +438,37 @@ impl<'a> VM<'a> {
 
                         self.stack_top = slot;
 
-                        self.push(result);
+                        self.push(result)?;
                     }
                     Op::NEGATE => {
-                        if !self.peek(0).is_number() {
-                            runtime_error!(self, "Operand must be a number.");
-
-                            return Err(Box::new(Error::RuntimeError));
+                        if !self.peek(0).is_numeric() {
+                            return Err(Box::new(runtime_error!(
+                                self,
+                                "Operand must be a number, found `{}`.",
+                                self.peek(0).type_name()
+                            )));
                         }
                         let value = self.pop();
-                        self.push(Value::number(-value.as_number()));
+
+                        if value.is_int() {
+                            match value.as_int().checked_neg() {
+                                Some(result) => self.push(Value::int(result))?,
+                                None => {
+                                    return Err(Box::new(runtime_error!(
+                                        self,
+                                        "Integer overflow."
+                                    )));
+                                }
+                            }
+                        } else {
+                            self.push(Value::number(-value.as_number()))?;
+                        }
+                    }
+                    Op::SHARED_CONSTANT => {
+                        let index = read_short!(self) as usize;
+                        let constant = frame!(self).closure.function.chunk.shared_constant(index);
+
+                        self.push(constant)?;
                     }
                     Op::CONSTANT => {
                         let constant = read_constant!(self);
@@ -228,46 +477,84 @@ impl<'a> VM<'a> {
                             print_value(constant);
                             print!("\n");
                         }
-                        self.push(constant);
+                        self.push(constant)?;
                     }
                     Op::GREATER => binary_op!(bool,>, self),
                     Op::LESS => binary_op!(bool,< , self),
                     Op::ADD => {
                         if self.peek(0).is_string() && self.peek(1).is_string() {
-                            self.concatenate();
-                        } else if self.peek(0).is_number() && self.peek(1).is_number() {
-                            let b = self.pop();
-                            let a = self.pop();
-
-                            self.push(Value::number(a.as_number() + b.as_number()));
+                            self.concatenate()?;
+                        } else if self.peek(0).is_numeric() && self.peek(1).is_numeric() {
+                            arithmetic_op!(checked_add,+, self)
+                        } else if self.peek(0).is_string() || self.peek(1).is_string() {
+                            // Only strings are joined, anything else has to be converted first
+                            return Err(Box::new(runtime_error!(
+                                self,
+                                "Can't add `{}` and `{}`, convert the other operand with str() to join them.",
+                                self.peek(1).type_name(),
+                                self.peek(0).type_name()
+                            )));
                         } else {
-                            runtime_error!(self, "Operands must be two numbers or two strings.");
-                            return Err(Box::new(Error::RuntimeError));
+                            return Err(Box::new(runtime_error!(
+                                self,
+                                "Operands must be two numbers or two strings, found `{}` and `{}`.",
+                                self.peek(1).type_name(),
+                                self.peek(0).type_name()
+                            )));
+                        }
+                    }
+                    Op::SUBTRACT => arithmetic_op!(checked_sub,- , self),
+                    Op::MULTIPLY => arithmetic_op!(checked_mul,* , self),
+                    // Division always produces a float so `7 / 2` is `3.5`; a whole result such as `6 / 3` still prints as `2`
+                    Op::DIVIDE => {
+                        if !self.peek(0).is_numeric() || !self.peek(1).is_numeric() {
+                            return Err(Box::new(runtime_error!(
+                                self,
+                                "/ operands must be numbers, found `{}` and `{}`",
+                                self.peek(1).type_name(),
+                                self.peek(0).type_name()
+                            )));
                         }
+
+                        let b = self.pop();
+                        let a = self.pop();
+
+                        self.push(Value::number(a.as_float() / b.as_float()))?;
                     }
-                    Op::SUBTRACT => binary_op!(number,- , self),
-                    Op::MULTIPLY => binary_op!(number,* , self),
-                    Op::DIVIDE => binary_op!(number,/ , self),
-                    Op::NIL => self.push(Value::nil()),
-                    Op::TRUE => self.push(Value::bool(true)),
-                    Op::FALSE => self.push(Value::bool(false)),
+                    Op::NIL => self.push(Value::nil())?,
+                    Op::TRUE => self.push(Value::bool(true))?,
+                    Op::FALSE => self.push(Value::bool(false))?,
                     Op::NOT => {
                         let val = Value::bool(self.pop().is_falsey());
-                        self.push(val)
+                        self.push(val)?
                     }
                     Op::EQUAL => {
                         let b = self.pop();
                         let a = self.pop();
-                        self.push(Value::bool(a == b));
+                        self.push(Value::bool(a == b))?;
                     }
                     Op::PRINT => {
+                        if self.peek(0).is_null_object() {
+                            return Err(Box::new(runtime_error!(
+                                self,
+                                "Can't print a null object."
+                            )));
+                        }
+
                         let val = self.pop();
-                        print_value(val);
-                        print!("\n");
+                        write_value(&mut self.out, val, &self.number_format)?;
+                        write!(self.out, "{}", self.line_ending.as_str())?;
                     }
                     Op::POP => {
                         self.pop();
                     }
+                    Op::DUP => {
+                        let value = self.peek(0);
+                        self.push(value)?;
+                    }
+                    Op::SWAP => {
+                        self.stack.swap(self.stack_top - 1, self.stack_top - 2);
+                    }
 
                     Op::DEFINE_GLOBAL => {
                         let name = read_constant!(self).as_obj();
@@ -286,11 +573,14 @@ impl<'a> VM<'a> {
                         let val = self.globals.get(obj_ptr);
 
                         if val.is_none() {
-                            runtime_error!(self, "Undefined variable '{}'", as_str.chars);
-                            return Err(Box::new(Error::RuntimeError));
+                            return Err(Box::new(runtime_error!(
+                                self,
+                                "Undefined variable '{}'",
+                                as_str.chars
+                            )));
                         }
 
-                        self.push(val.unwrap());
+                        self.push(val.unwrap())?;
                     }
 
                     Op::SET_GLOBAL => {
@@ -304,8 +594,11 @@ impl<'a> VM<'a> {
 
                         if self.globals.set(obj_ptr, value) {
                             self.globals.delete(obj_ptr);
-                            runtime_error!(self, "Undefined variable '{}'", as_str.chars);
-                            return Err(Box::new(Error::RuntimeError));
+                            return Err(Box::new(runtime_error!(
+                                self,
+                                "Undefined variable '{}'",
+                                as_str.chars
+                            )));
                         }
 
                         // self.push(val.unwrap());
@@ -314,7 +607,7 @@ impl<'a> VM<'a> {
                     Op::GET_LOCAL => {
                         let slot = read_byte!(self);
                         let index = frame!(self).slots + slot as usize;
-                        self.push(self.stack[index])
+                        self.push(self.stack[index])?
                     }
 
                     Op::SET_LOCAL => {
@@ -352,16 +645,12 @@ impl<'a> VM<'a> {
 
                         let callee = self.peek(arg_count as usize);
 
-                        if !self.call_value(callee, arg_count as usize) {
-                            return Err(Box::new(Error::RuntimeError));
-                        }
+                        self.call_value(callee, arg_count as usize)?;
                     }
 
                     Op::CLOSURE => {
                         let function = read_constant!(self).as_function();
-                        let mut closure = self
-                            .allocator
-                            .alloc(move |next| ClosureObject::new(function, next));
+                        let mut closure = self.allocator.alloc(ClosureObject::new(function));
 
                         for i in 0..closure.upvalue_count {
                             let is_local = read_byte!(self);
@@ -378,7 +667,7 @@ impl<'a> VM<'a> {
                             }
                         }
 
-                        self.push(Value::object(closure.into()));
+                        self.push(Value::object(closure.into()))?;
                     }
 
                     Op::GET_UPVALUE => {
@@ -388,7 +677,7 @@ impl<'a> VM<'a> {
                             .unwrap()
                             .location;
 
-                        self.push(value);
+                        self.push(value)?;
                     }
 
                     Op::SET_UPVALUE => {
@@ -405,14 +694,215 @@ impl<'a> VM<'a> {
                         self.close_upvalue(self.stack[self.stack_top - 1].as_ptr());
                         self.pop();
                     }
+
+                    Op::CLASS => {
+                        let name = read_constant!(self).as_string();
+
+                        let class = self.allocator.alloc(ClassObject::new(name));
+
+                        self.push(Value::object(class.into()))?;
+                    }
+
+                    Op::GET_PROPERTY => {
+                        let name = read_constant!(self);
+
+                        if !self.peek(0).is_instance() {
+                            return Err(Box::new(runtime_error!(
+                                self,
+                                "Only instances have properties, found `{}`.",
+                                self.peek(0).type_name()
+                            )));
+                        }
+
+                        let instance = self.peek(0).as_instance();
+
+                        match instance.fields.get(name.as_obj()) {
+                            Some(value) => {
+                                self.pop();
+                                self.push(value)?;
+                            }
+                            None => self.bind_method(instance.class.cast(), name)?,
+                        }
+                    }
+
+                    Op::SET_PROPERTY => {
+                        let name = read_constant!(self);
+
+                        if !self.peek(1).is_instance() {
+                            return Err(Box::new(runtime_error!(
+                                self,
+                                "Only instances have fields, found `{}`.",
+                                self.peek(1).type_name()
+                            )));
+                        }
+
+                        let mut instance = self.peek(1).as_instance();
+
+                        instance.fields.set(name.as_obj(), self.peek(0));
+
+                        let value = self.pop();
+                        self.pop();
+                        self.push(value)?;
+                    }
+
+                    Op::METHOD => {
+                        let name = read_constant!(self);
+
+                        let method = self.peek(0);
+                        let mut class = self.peek(1).as_class();
+
+                        class.methods.set(name.as_obj(), method);
+
+                        self.pop();
+                    }
+
+                    Op::MAP_NEW => {
+                        let count = read_byte!(self) as usize;
+                        let first = self.stack_top - count * 2;
+
+                        let mut map = self.allocator.alloc(MapObject::new());
+
+                        for slot in (first..self.stack_top).step_by(2) {
+                            let key = self.stack[slot];
+
+                            if !key.is_string() {
+                                return Err(Box::new(runtime_error!(
+                                    self,
+                                    "Map keys must be strings, found `{}`.",
+                                    key.type_name()
+                                )));
+                            }
+
+                            map.entries.set(key.as_obj(), self.stack[slot + 1]);
+                        }
+
+                        self.stack_top = first;
+                        self.push(Value::object(map.as_ptr_obj()))?;
+                    }
+
+                    Op::ARRAY_NEW => {
+                        let count = read_byte!(self) as usize;
+                        let first = self.stack_top - count;
+
+                        let items = self.stack[first..self.stack_top].to_vec();
+                        let array = self.allocator.alloc(ArrayObject::new(items));
+
+                        self.stack_top = first;
+                        self.push(Value::object(array.as_ptr_obj()))?;
+                    }
+
+                    Op::INDEX_GET => {
+                        let receiver = self.peek(1);
+                        let index = self.peek(0);
+
+                        let value = if receiver.is_array() {
+                            let array = receiver.as_array();
+
+                            array.items[self.array_index(array.items.len(), index)?]
+                        } else {
+                            self.check_map_access(1)?;
+
+                            let map = receiver.as_map();
+
+                            map.entries.get(index.as_obj()).unwrap_or(Value::nil())
+                        };
+
+                        self.stack_top -= 2;
+                        self.push(value)?;
+                    }
+
+                    Op::INDEX_SET => {
+                        let receiver = self.peek(2);
+                        let index = self.peek(1);
+                        let value = self.peek(0);
+
+                        if receiver.is_array() {
+                            let mut array = receiver.as_array();
+                            let slot = self.array_index(array.items.len(), index)?;
+
+                            array.items[slot] = value;
+                        } else {
+                            self.check_map_access(2)?;
+
+                            let mut map = receiver.as_map();
+
+                            map.entries.set(index.as_obj(), value);
+                        }
+
+                        self.stack_top -= 3;
+                        self.push(value)?;
+                    }
+                    Op::TRAP => {
+                        return Err(Box::new(runtime_error!(self, "Reached erroneous code.")));
+                    }
                 }
             }
         }
     }
 
-    pub fn push(&mut self, val: Value) {
+    /// Checks that the map being indexed, `distance` slots down the stack, and the key above it have
+    /// the right types
+    fn check_map_access(&mut self, distance: usize) -> Result<(), Error> {
+        let map = self.peek(distance);
+        let key = self.peek(distance - 1);
+
+        if !map.is_map() {
+            return Err(runtime_error!(
+                self,
+                "Only arrays and maps can be indexed, found `{}`.",
+                map.type_name()
+            ));
+        }
+
+        if !key.is_string() {
+            return Err(runtime_error!(
+                self,
+                "Map keys must be strings, found `{}`.",
+                key.type_name()
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// The slot `index` refers to in an array holding `len` items, a negative index counts back from
+    /// the end so `-1` is the last item
+    fn array_index(&mut self, len: usize, index: Value) -> Result<usize, Error> {
+        if !index.is_int() {
+            return Err(runtime_error!(
+                self,
+                "Array indices must be integers, found `{}`.",
+                index.type_name()
+            ));
+        }
+
+        let index = index.as_int();
+        let slot = if index < 0 {
+            index.checked_add(len as i64)
+        } else {
+            Some(index)
+        };
+
+        match slot {
+            Some(slot) if slot >= 0 && (slot as usize) < len => Ok(slot as usize),
+            _ => Err(runtime_error!(
+                self,
+                "Index {} is out of bounds for an array of length {}.",
+                index,
+                len
+            )),
+        }
+    }
+
+    pub fn push(&mut self, val: Value) -> Result<(), Error> {
+        if self.stack_top == STACK_MAX {
+            return Err(runtime_error!(self, "Stack overflow."));
+        }
+
         self.stack[self.stack_top] = val;
         self.stack_top += 1;
+
+        Ok(())
     }
 
     pub fn pop(&mut self) -> Value {
@@ -420,110 +910,275 @@ impl<'a> VM<'a> {
         self.stack[self.stack_top]
     }
 
+    /// How many times each opcode that ran was executed, the most frequent first
+    #[cfg(feature = "profile")]
+    pub fn op_counts(&self) -> Vec<(Op, usize)> {
+        let mut counts = self
+            .op_counts
+            .iter()
+            .enumerate()
+            .filter(|(_, count)| **count > 0)
+            .map(|(op, count)| (unsafe { std::mem::transmute::<u8, Op>(op as u8) }, *count))
+            .collect::<Vec<_>>();
+
+        counts.sort_by(|(_, lhs), (_, rhs)| rhs.cmp(lhs));
+
+        counts
+    }
+
+    /// Writes the opcode counts as `GET_LOCAL: 98201, ADD: 10523, ...`
+    #[cfg(feature = "profile")]
+    pub fn dump_op_counts(&self, out: &mut dyn Write) -> std::io::Result<()> {
+        let counts = self
+            .op_counts()
+            .iter()
+            .map(|(op, count)| format!("{:?}: {}", op, count))
+            .collect::<Vec<_>>();
+
+        writeln!(out, "{}", counts.join(", "))
+    }
+
+    /// The flag that stops the running script with an `Interrupted.` error when it is set
+    pub fn interrupt_handle(&self) -> Arc<AtomicBool> {
+        self.interrupt.clone()
+    }
+
+    /// Shares `interrupt` with this vm, so one handler can stop whichever vm is running
+    pub fn set_interrupt(&mut self, interrupt: Arc<AtomicBool>) {
+        self.interrupt = interrupt;
+    }
+
+    /// Records which source lines run from the next call to `interpret` on
+    pub fn record_coverage(&mut self, enabled: bool) {
+        self.coverage = if enabled {
+            Some(Coverage::default())
+        } else {
+            None
+        };
+    }
+
+    /// The lines that did and didn't run, if coverage is being recorded
+    pub fn coverage(&self) -> Option<CoverageReport> {
+        self.coverage.as_ref().map(|coverage| CoverageReport {
+            executed: coverage.executed.iter().copied().collect(),
+            unexecuted: coverage
+                .lines
+                .difference(&coverage.executed)
+                .copied()
+                .collect(),
+        })
+    }
+
+    /// Captures the globals and the stack so the effects of running more code can be undone with `restore`
+    pub fn checkpoint(&self) -> Checkpoint {
+        Checkpoint {
+            globals: self.globals.clone(),
+            stack_top: self.stack_top,
+            frame_count: self.frame_count,
+        }
+    }
+
+    /// Puts the globals and the stack back to how they were when the checkpoint was taken
+    pub fn restore(&mut self, checkpoint: Checkpoint) {
+        self.globals = checkpoint.globals;
+        self.stack_top = checkpoint.stack_top;
+        self.frame_count = checkpoint.frame_count;
+    }
+
     fn reset_stack(&mut self) {
         self.stack_top = 0;
+        self.frame_count = 0;
     }
 
     const fn peek(&self, distance: usize) -> Value {
         self.stack[self.stack_top - 1 - distance as usize]
     }
 
-    fn define_native(&mut self, name: &str, fn_ptr: NativeFn) {
-        let string_object = self
-            .allocator
-            .alloc(|next| StringObject::new(name, &mut self.strings, next));
+    /// Defines a global called `name` that calls `fn_ptr`, replacing any global with the same name.
+    /// See `NativeFn` for how the arguments are passed, calls that don't match `arity` are a runtime
+    /// error and never reach `fn_ptr`.
+    pub fn register_native(&mut self, name: &str, arity: Arity, fn_ptr: NativeFn) {
+        self.define_native(name, arity, Native::Raw(fn_ptr))
+    }
+
+    /// Like `register_native` but the native is handed its arguments as a slice
+    pub fn register_slice_native(&mut self, name: &str, arity: Arity, fn_ptr: SliceNativeFn) {
+        self.define_native(name, arity, Native::Slice(fn_ptr))
+    }
+
+    /// Makes the native being called fail with a runtime error, the value it returns is discarded
+    pub fn raise(&mut self, message: impl Into<String>) {
+        self.native_error = Some(message.into());
+    }
+
+    fn define_native(&mut self, name: &str, arity: Arity, native: Native) {
+        let string_object = self.allocator.alloc_string(name, &mut self.strings);
         let name = Value::object(string_object.into());
-        self.push(name);
 
+        // Nothing is collected between the two allocations so the name doesn't need to be kept on the stack
         let native_object = Value::object(
             self.allocator
-                .alloc(|next| NativeObject::new(fn_ptr, next))
+                .alloc(NativeObject::new(native, arity))
                 .into(),
         );
 
-        self.push(native_object);
-
-        self.globals.set(name.as_obj(), self.stack[1]);
-
-        self.pop();
-        self.pop();
+        self.globals.set(name.as_obj(), native_object);
     }
 
-    fn concatenate(&mut self) {
-        let b = self.pop();
-        let a = self.pop();
+    fn concatenate(&mut self) -> Result<(), Error> {
+        let b = self.pop().as_string();
+        let a = self.pop().as_string();
 
-        let mut new_string = String::with_capacity(
-            b.as_string().chars.len() - 1 + a.as_string().chars.len() - 1 + 1,
-        );
-        // We don't include the null terminator in the length of the string.
-        new_string.push_str(&a.as_string().chars[0..a.as_string().chars.len() - 1]);
+        let (a, b) = (a.text(), b.text());
 
-        new_string.push_str(&b.as_string().chars[0..b.as_string().chars.len() - 1]);
+        // Room for both strings and the one null terminator the result ends in
+        let mut new_string = String::with_capacity(a.len() + b.len() + 1);
+
+        new_string.push_str(a);
+        new_string.push_str(b);
         new_string.push('\0');
 
         let string_object = self
             .allocator
-            .alloc(|next| StringObject::from_owned(new_string, &mut self.strings, next));
+            .alloc_owned_string(new_string, &mut self.strings);
 
         let result = Value::object(string_object.into());
-        self.push(result);
+        self.push(result)
     }
 
-    fn call_value(&mut self, callee: Value, arg_count: usize) -> bool {
+    fn call_value(&mut self, callee: Value, arg_count: usize) -> Result<(), Error> {
+        if callee.is_null_object() {
+            return Err(runtime_error!(self, "Can't call a null object."));
+        }
+
         if callee.is_obj() {
             match callee.obj_type() {
                 //we wrap all functions in ClosureObjects so the runtime will never try to invoke a bare FunctionObject anymore
-                ObjectType::String | ObjectType::UpValue | ObjectType::Function => {}
+                ObjectType::String
+                | ObjectType::UpValue
+                | ObjectType::Function
+                | ObjectType::Instance
+                | ObjectType::Map
+                | ObjectType::Array => {}
 
                 ObjectType::Closure => return self.call(callee.as_closure(), arg_count),
+                ObjectType::Class => {
+                    let class = callee.as_class();
+
+                    let instance = self.allocator.alloc(InstanceObject::new(class.cast()));
+
+                    // The instance takes the place of the class so it is `this` inside the initializer
+                    self.stack[self.stack_top - arg_count - 1] = Value::object(instance.into());
+
+                    return match class.methods.get(self.init_string.raw()) {
+                        Some(initializer) => self.call(initializer.as_closure(), arg_count),
+                        None if arg_count != 0 => Err(runtime_error!(
+                            self,
+                            "Expected 0 arguments but got {}",
+                            arg_count
+                        )),
+                        None => Ok(()),
+                    };
+                }
+                ObjectType::BoundMethod => {
+                    let bound = callee.as_bound_method();
+
+                    self.stack[self.stack_top - arg_count - 1] = bound.receiver;
+
+                    return self.call(bound.method.cast(), arg_count);
+                }
                 ObjectType::Native => {
                     let native = callee.as_native();
 
-                    let result = (native.function)(
-                        arg_count as usize,
-                        self.stack[self.stack_top - arg_count..self.stack_top].as_ptr(),
-                    );
+                    if !native.arity.accepts(arg_count) {
+                        return Err(runtime_error!(
+                            self,
+                            "Expected {} arguments but got {}",
+                            native.arity,
+                            arg_count
+                        ));
+                    }
+
+                    let args = self.stack_top - arg_count..self.stack_top;
+
+                    let result = match native.function {
+                        Native::Raw(function) => {
+                            function(self, arg_count, self.stack[args].as_ptr())
+                        }
+                        Native::Slice(function) => function(&self.stack[args]),
+                    };
+
+                    if let Some(message) = self.native_error.take() {
+                        return Err(runtime_error!(self, "{}", message));
+                    }
 
                     self.stack_top -= arg_count + 1;
 
-                    self.push(result);
+                    self.push(result)?;
 
-                    return true;
+                    return Ok(());
                 }
             }
         }
 
-        runtime_error!(self, "Can only call functions and classes.");
-        false
+        Err(runtime_error!(
+            self,
+            "Can only call functions and classes, found `{}`.",
+            callee.type_name()
+        ))
     }
 
-    pub fn call(&mut self, callee: ObjectPtr<ClosureObject<'a>>, arg_count: usize) -> bool {
+    pub fn call(
+        &mut self,
+        callee: ObjectPtr<ClosureObject<'a>>,
+        arg_count: usize,
+    ) -> Result<(), Error> {
         if self.frame_count == FRAMES_MAX {
-            runtime_error!(self, "Stack overflow.");
-            return false;
+            return Err(runtime_error!(self, "Stack overflow."));
         }
 
-        self.frame_count += 1;
-
-        let frame = frame_mut!(self);
-
         if arg_count != callee.function.arity {
-            runtime_error!(
+            return Err(runtime_error!(
                 self,
                 "Expected {} arguments but got {}",
                 callee.function.arity,
                 arg_count
-            );
-
-            return false;
+            ));
         }
 
+        self.frame_count += 1;
+
+        let frame = frame_mut!(self);
+
         frame.ip = 0;
+        frame.instruction = 0;
         frame.closure = callee;
         frame.slots = self.stack_top - arg_count - 1 as usize;
 
-        true
+        Ok(())
+    }
+
+    /// Replaces the instance on top of the stack with its method called `name`, bound to the instance
+    fn bind_method(&mut self, class: ObjectPtr<ClassObject<'a>>, name: Value) -> Result<(), Error> {
+        let method = match class.methods.get(name.as_obj()) {
+            Some(method) => method.as_closure(),
+            None => {
+                return Err(runtime_error!(
+                    self,
+                    "Undefined property '{}'.",
+                    name.as_string().value().trim_end_matches('\0')
+                ))
+            }
+        };
+
+        let receiver = self.peek(0);
+
+        let bound = self
+            .allocator
+            .alloc(BoundMethodObject::new(receiver, method));
+
+        self.pop();
+        self.push(Value::object(bound.into()))
     }
 
     fn capture_value(&mut self, local: Value) -> ObjectPtr<UpValueObject> {
@@ -539,7 +1194,7 @@ impl<'a> VM<'a> {
             return upvalue;
         }
 
-        let mut created_up_value = UpValueObject::new(local);
+        let mut created_up_value = self.allocator.alloc(UpValueObject::new(local));
 
         created_up_value.next = upvalue;
 
@@ -564,35 +1219,34 @@ impl<'a> VM<'a> {
     }
 }
 
-pub fn print_value(value: Value) {
-    match value.ty {
-        ValueType::Bool => print!("{}", value.as_bool()),
-        ValueType::Nil => print!("nil"),
-        ValueType::Number => print!("{}", value.as_number()),
-        ValueType::Object => print_object(value),
+/// Gathers the lines of a chunk and of every function in its constants
+fn collect_lines(chunk: &Chunk, lines: &mut BTreeSet<usize>) {
+    lines.extend(chunk.line_runs().iter().map(|(line, _)| *line));
+
+    for constant in &chunk.constants {
+        if constant.is_obj() && constant.obj_type() == ObjectType::Function {
+            collect_lines(&constant.as_function().chunk, lines);
+        }
     }
 }
 
+pub fn print_value(value: Value) {
+    print!("{}", value);
+}
+
+#[cfg(feature = "debug")]
 #[inline]
 pub fn print_object(value: Value) {
-    match value.obj_type() {
-        ObjectType::String => print!("{}", value.as_raw_string()),
-        ObjectType::Function => print_function(&value.as_function()),
-        ObjectType::Native => print!("<native fn>"),
-        ObjectType::Closure => print_function(&value.as_closure().function),
-        ObjectType::UpValue => print!("upvalue"),
-    }
+    print!("{}", value);
 }
 
-fn print_function(function: &FunctionObject) {
-    match &function.name {
-        Some(name) => {
-            print!("<fn {}>", name.chars)
-        }
-        None => {
-            print!("<script>")
-        }
-    }
+/// Writes the textual form of a value, as shown by `print`, to `out`
+pub fn write_value(
+    out: &mut dyn Write,
+    value: Value,
+    format: &NumberFormat,
+) -> std::io::Result<()> {
+    write!(out, "{}", value.formatted(format))
 }
 
 unsafe fn free_object(obj: RawObject) {
@@ -614,6 +1268,21 @@ unsafe fn free_object(obj: RawObject) {
         ObjectType::UpValue => {
             let _ = Box::from_raw(obj as *mut UpValueObject);
         }
+        ObjectType::Class => {
+            let _ = Box::from_raw(obj as *mut ClassObject);
+        }
+        ObjectType::Instance => {
+            let _ = Box::from_raw(obj as *mut InstanceObject);
+        }
+        ObjectType::BoundMethod => {
+            let _ = Box::from_raw(obj as *mut BoundMethodObject);
+        }
+        ObjectType::Map => {
+            let _ = Box::from_raw(obj as *mut MapObject);
+        }
+        ObjectType::Array => {
+            let _ = Box::from_raw(obj as *mut ArrayObject);
+        }
     }
 }
 
@@ -639,3 +1308,128 @@ impl<'a> Drop for VM<'a> {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::{CapturedOutput, Error, STACK_MAX, VM};
+    use crate::{chunk::Chunk, op::Op, Allocator, FunctionObject, ObjectPtr, Table, Value};
+
+    #[test]
+    fn it_errors_instead_of_calling_a_null_object() {
+        let mut vm = VM::new(Table::new(), Allocator::new());
+
+        let callee = Value::object(ObjectPtr::null());
+
+        vm.push(callee).unwrap();
+
+        match vm.call_value(callee, 0) {
+            Err(Error::RuntimeError { message, .. }) => {
+                assert_eq!(message, "Can't call a null object.")
+            }
+            result => panic!("expected a runtime error, found {:?}", result),
+        }
+
+        assert_eq!(callee.type_name(), "null object");
+        assert_eq!(callee.to_string(), "<null object>");
+    }
+
+    #[test]
+    fn it_errors_instead_of_pushing_past_the_end_of_the_stack() {
+        let mut vm = VM::new(Table::new(), Allocator::new());
+
+        for _ in 0..STACK_MAX {
+            vm.push(Value::nil()).unwrap();
+        }
+
+        match vm.push(Value::nil()) {
+            Err(Error::RuntimeError { message, .. }) => assert_eq!(message, "Stack overflow."),
+            result => panic!("expected a stack overflow, found {:?}", result),
+        }
+
+        assert_eq!(vm.stack_top, 0);
+    }
+
+    /// Runs a script whose code is written by `write`, followed by a return, and returns what it printed
+    fn run_chunk(write: impl FnOnce(&mut Chunk)) -> String {
+        let mut vm = VM::new(Table::new(), Allocator::new());
+
+        let output = CapturedOutput::default();
+        vm.set_output(Box::new(output.clone()));
+
+        let mut function = vm.allocator.alloc(FunctionObject::new(None));
+        write(&mut function.chunk);
+        function.chunk.write(Op::NIL as u8, 1);
+        function.chunk.write(Op::RETURN as u8, 1);
+
+        vm.interpret(function).unwrap();
+
+        output.contents()
+    }
+
+    #[test]
+    fn dup_pushes_a_copy_of_the_top_value() {
+        let printed = run_chunk(|chunk| {
+            let seven = chunk.add_constant(Value::int(7));
+
+            chunk.write(Op::CONSTANT as u8, 1);
+            chunk.write(seven as u8, 1);
+            chunk.write(Op::DUP as u8, 1);
+            chunk.write(Op::ADD as u8, 1);
+            chunk.write(Op::PRINT as u8, 1);
+        });
+
+        assert_eq!(printed, "14\n");
+    }
+
+    #[test]
+    fn swap_exchanges_the_top_two_values() {
+        let printed = run_chunk(|chunk| {
+            let one = chunk.add_constant(Value::int(1));
+            let two = chunk.add_constant(Value::int(2));
+
+            chunk.write(Op::CONSTANT as u8, 1);
+            chunk.write(one as u8, 1);
+            chunk.write(Op::CONSTANT as u8, 1);
+            chunk.write(two as u8, 1);
+            chunk.write(Op::SWAP as u8, 1);
+            chunk.write(Op::PRINT as u8, 1);
+            chunk.write(Op::PRINT as u8, 1);
+        });
+
+        assert_eq!(printed, "1\n2\n");
+    }
+
+    #[test]
+    fn it_errors_when_it_reaches_a_trap() {
+        let mut vm = VM::new(Table::new(), Allocator::new());
+
+        let mut function = vm.allocator.alloc(FunctionObject::new(None));
+        function.chunk.write(Op::NIL as u8, 1);
+        function.chunk.write(Op::TRAP as u8, 2);
+
+        assert_eq!(
+            vm.interpret(function).unwrap_err().to_string(),
+            "Runtime error: Reached erroneous code."
+        );
+    }
+
+    #[test]
+    fn it_restores_the_globals_from_a_checkpoint() {
+        let mut vm = VM::new(Table::new(), Allocator::new());
+
+        let name = vm.allocator.alloc_string("a", &mut vm.strings).raw();
+
+        vm.globals.set(name, Value::int(1));
+        vm.push(Value::int(10)).unwrap();
+
+        let checkpoint = vm.checkpoint();
+
+        vm.globals.set(name, Value::int(2));
+        vm.push(Value::int(20)).unwrap();
+
+        vm.restore(checkpoint);
+
+        assert_eq!(vm.globals.get(name).unwrap().as_int(), 1);
+        assert_eq!(vm.stack_top, 1);
+    }
+}